@@ -0,0 +1,34 @@
+//! Conversion of extracted resources into commonly supported formats.
+
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use lithrez::{dtx, rez};
+
+
+/// Returns whether the given resource is a DTX texture, judging by its extension.
+pub fn is_dtx(res: &rez::Resource) -> bool {
+    res.extension.eq_ignore_ascii_case("dtx")
+}
+
+/// Decodes the DTX texture at the given path and writes it as a PNG image to the other given path.
+pub fn dtx_to_png(dtx_path: &Path, png_path: &Path) -> Result<(), String> {
+    let data = std::fs::read(dtx_path)
+        .map_err(|e| format!("failed to read {}: {}", dtx_path.display(), e))?;
+    let texture = dtx::decode(&data)
+        .map_err(|e| format!("failed to decode {}: {}", dtx_path.display(), e))?;
+    write_png(&texture, png_path)
+        .map_err(|e| format!("failed to write {}: {}", png_path.display(), e))
+}
+
+fn write_png(texture: &dtx::Texture, png_path: &Path) -> Result<(), png::EncodingError> {
+    let output = BufWriter::new(File::create(png_path)?);
+    let mut encoder = png::Encoder::new(output, texture.width, texture.height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&texture.rgba)?;
+    writer.finish()
+}
@@ -0,0 +1,90 @@
+//! Comparisons of the resources within two REZ files.
+
+
+use std::collections::BTreeMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hasher;
+use std::io::{self, Read, Seek, SeekFrom};
+
+use crate::positions::{collect_positions, Position};
+use crate::rez;
+
+
+/// A difference between two REZ files concerning the resource at the given path.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Change {
+    /// The resource only exists in the new REZ file.
+    Added { path: String, size: u32 },
+
+    /// The resource only exists in the old REZ file.
+    Removed { path: String, size: u32 },
+
+    /// The resource exists in both REZ files but its size or contents differ.
+    Modified { path: String, old_size: u32, new_size: u32 },
+}
+impl Change {
+    /// The path of the resource within the REZ files.
+    pub fn path(&self) -> &str {
+        match self {
+            Self::Added { path, .. } => path,
+            Self::Removed { path, .. } => path,
+            Self::Modified { path, .. } => path,
+        }
+    }
+}
+
+
+/// Compares the resources of two REZ files by their paths, returning the changes ordered by path.
+///
+/// Resources of the same size are considered unchanged unless `readers` is given, in which case
+/// their contents are compared using a hash. The readers must be those from which the old and the
+/// new REZ file have been read, in that order.
+pub fn diff_files<R: Read + Seek>(old_file: &rez::File, new_file: &rez::File, mut readers: Option<(&mut R, &mut R)>) -> io::Result<Vec<Change>> {
+    let old_positions: BTreeMap<String, Position> = collect_positions(old_file).into_iter()
+        .map(|p| (p.path.clone(), p))
+        .collect();
+    let mut new_positions: BTreeMap<String, Position> = collect_positions(new_file).into_iter()
+        .map(|p| (p.path.clone(), p))
+        .collect();
+
+    let mut changes = Vec::new();
+    for (path, old_position) in old_positions {
+        let Some(new_position) = new_positions.remove(&path) else {
+            changes.push(Change::Removed { path, size: old_position.length });
+            continue;
+        };
+
+        let modified = if old_position.length != new_position.length {
+            true
+        } else if let Some((old_reader, new_reader)) = &mut readers {
+            hash_data(*old_reader, &old_position)? != hash_data(*new_reader, &new_position)?
+        } else {
+            false
+        };
+        if modified {
+            changes.push(Change::Modified { path, old_size: old_position.length, new_size: new_position.length });
+        }
+    }
+    for (path, new_position) in new_positions {
+        changes.push(Change::Added { path, size: new_position.length });
+    }
+
+    changes.sort_by(|left, right| left.path().cmp(right.path()));
+    Ok(changes)
+}
+
+
+fn hash_data<R: Read + Seek>(reader: &mut R, position: &Position) -> io::Result<u64> {
+    reader.seek(SeekFrom::Start(position.offset))?;
+    let mut data = reader.take(position.length.into());
+    let mut hasher = DefaultHasher::new();
+    let mut buf = vec![0u8; 64*1024];
+    loop {
+        let read_count = data.read(&mut buf)?;
+        if read_count == 0 {
+            break;
+        }
+        hasher.write(&buf[..read_count]);
+    }
+    Ok(hasher.finish())
+}
@@ -0,0 +1,265 @@
+//! Decoding DTX files, the textures of the LithTech engine.
+//!
+//! Only textures of LithTech 2 and later (version -5) are supported. Of their mipmap levels, only
+//! the first (full-size) one is decoded. The supported pixel formats are 32-bit BGRA and the S3TC
+//! block compression formats DXT1, DXT3 and DXT5.
+
+
+use std::fmt;
+
+use from_to_repr::from_to_other;
+
+
+/// The length of the header of a DTX file, which is followed by the mipmap levels.
+pub const HEADER_LENGTH: usize = 164;
+
+/// The version number of DTX files written by LithTech 2 and later.
+pub const VERSION_LT2: i32 = -5;
+
+
+#[derive(Debug)]
+pub enum Error {
+    TooShort { length: usize },
+    UnsupportedVersion { version: i32 },
+    UnsupportedPixelFormat { pixel_format: PixelFormat },
+    Truncated { expected: usize, available: usize },
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooShort { length }
+                => write!(f, "DTX file is {} bytes long, which is shorter than its header", length),
+            Self::UnsupportedVersion { version }
+                => write!(f, "unsupported DTX version {} (expected {})", version, VERSION_LT2),
+            Self::UnsupportedPixelFormat { pixel_format }
+                => write!(f, "unsupported DTX pixel format {:?}", pixel_format),
+            Self::Truncated { expected, available }
+                => write!(f, "DTX pixel data is truncated (expected {} bytes, only {} available)", expected, available),
+        }
+    }
+}
+impl std::error::Error for Error {
+}
+
+
+/// The format of the pixels of a DTX texture, stored in the third extra byte of the header.
+#[derive(Clone, Copy, Debug, Default)]
+#[from_to_other(base_type = u8, derive_compare = "as_int")]
+pub enum PixelFormat {
+    #[default] Palette8 = 0,
+    Bgra8 = 1,
+    Bgr16 = 2,
+    Bgra32 = 3,
+    Dxt1 = 4,
+    Dxt3 = 5,
+    Dxt5 = 6,
+    Palette32 = 7,
+    Other(u8),
+}
+
+
+#[derive(Clone, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Header {
+    pub resource_type: u32,
+    pub version: i32,
+    pub width: u16,
+    pub height: u16,
+    pub mipmap_count: u16,
+    pub section_count: u16,
+    pub flags: u32,
+    pub user_flags: u32,
+    pub extra: [u8; 12],
+    pub command_string: Vec<u8>,
+}
+impl Header {
+    pub fn parse(data: &[u8]) -> Result<Self, Error> {
+        if data.len() < HEADER_LENGTH {
+            return Err(Error::TooShort { length: data.len() });
+        }
+        let u16_at = |offset: usize| u16::from_le_bytes(data[offset..offset+2].try_into().unwrap());
+        let u32_at = |offset: usize| u32::from_le_bytes(data[offset..offset+4].try_into().unwrap());
+
+        let command_string_bytes = &data[36..HEADER_LENGTH];
+        let command_string_length = command_string_bytes.iter()
+            .position(|b| *b == 0)
+            .unwrap_or(command_string_bytes.len());
+        Ok(Self {
+            resource_type: u32_at(0),
+            version: u32_at(4) as i32,
+            width: u16_at(8),
+            height: u16_at(10),
+            mipmap_count: u16_at(12),
+            section_count: u16_at(14),
+            flags: u32_at(16),
+            user_flags: u32_at(20),
+            extra: data[24..36].try_into().unwrap(),
+            command_string: command_string_bytes[..command_string_length].to_vec(),
+        })
+    }
+
+    /// Returns the format of the pixels of the texture.
+    pub fn pixel_format(&self) -> PixelFormat {
+        PixelFormat::from(self.extra[2])
+    }
+}
+
+
+/// An image decoded from a DTX texture.
+#[derive(Clone, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Texture {
+    pub width: u32,
+    pub height: u32,
+
+    /// The pixels, row by row from the top, each as the four bytes red, green, blue and alpha.
+    pub rgba: Vec<u8>,
+}
+
+
+/// Decodes the first mipmap level of the DTX texture stored in the given data.
+pub fn decode(data: &[u8]) -> Result<Texture, Error> {
+    let header = Header::parse(data)?;
+    if header.version != VERSION_LT2 {
+        return Err(Error::UnsupportedVersion { version: header.version });
+    }
+
+    let width = usize::from(header.width);
+    let height = usize::from(header.height);
+    let pixel_format = header.pixel_format();
+    let (block_dimension, block_length) = match pixel_format {
+        PixelFormat::Bgra32 => (1, 4),
+        PixelFormat::Dxt1 => (4, 8),
+        PixelFormat::Dxt3 | PixelFormat::Dxt5 => (4, 16),
+        _ => return Err(Error::UnsupportedPixelFormat { pixel_format }),
+    };
+    let blocks_across = width.div_ceil(block_dimension);
+    let blocks_down = height.div_ceil(block_dimension);
+    let expected = blocks_across * blocks_down * block_length;
+    let pixel_data = &data[HEADER_LENGTH..];
+    if pixel_data.len() < expected {
+        return Err(Error::Truncated { expected, available: pixel_data.len() });
+    }
+
+    let mut rgba = vec![0u8; width * height * 4];
+    for block_y in 0..blocks_down {
+        for block_x in 0..blocks_across {
+            let block_offset = (block_y * blocks_across + block_x) * block_length;
+            let block = &pixel_data[block_offset..block_offset+block_length];
+            let decoded = match pixel_format {
+                PixelFormat::Bgra32 => {
+                    let mut pixel = [[0u8; 4]; 16];
+                    pixel[0] = [block[2], block[1], block[0], block[3]];
+                    pixel
+                },
+                PixelFormat::Dxt1 => decode_color_block(block, true),
+                PixelFormat::Dxt3 => {
+                    let mut pixels = decode_color_block(&block[8..16], false);
+                    let alpha_bits = u64::from_le_bytes(block[0..8].try_into().unwrap());
+                    for (i, pixel) in pixels.iter_mut().enumerate() {
+                        // expand 4 bits to 8
+                        pixel[3] = ((alpha_bits >> (4 * i)) & 0xF) as u8 * 0x11;
+                    }
+                    pixels
+                },
+                PixelFormat::Dxt5 => {
+                    let mut pixels = decode_color_block(&block[8..16], false);
+                    let alphas = interpolate_alphas(block[0], block[1]);
+                    let mut index_bytes = [0u8; 8];
+                    index_bytes[0..6].copy_from_slice(&block[2..8]);
+                    let index_bits = u64::from_le_bytes(index_bytes);
+                    for (i, pixel) in pixels.iter_mut().enumerate() {
+                        pixel[3] = alphas[((index_bits >> (3 * i)) & 0x7) as usize];
+                    }
+                    pixels
+                },
+                _ => unreachable!(),
+            };
+
+            // copy the block into the image, clipping at the edges
+            for dy in 0..block_dimension {
+                let y = block_y * block_dimension + dy;
+                if y >= height {
+                    break;
+                }
+                for dx in 0..block_dimension {
+                    let x = block_x * block_dimension + dx;
+                    if x >= width {
+                        break;
+                    }
+                    let offset = (y * width + x) * 4;
+                    rgba[offset..offset+4].copy_from_slice(&decoded[dy * block_dimension + dx]);
+                }
+            }
+        }
+    }
+
+    Ok(Texture {
+        width: header.width.into(),
+        height: header.height.into(),
+        rgba,
+    })
+}
+
+/// Decodes an S3TC color block into its 16 pixels, row by row.
+///
+/// If `allow_transparency` is set (DXT1), blocks whose first color is not greater than their
+/// second use three colors and transparent black; otherwise, four colors are always used.
+fn decode_color_block(block: &[u8], allow_transparency: bool) -> [[u8; 4]; 16] {
+    let color0 = u16::from_le_bytes([block[0], block[1]]);
+    let color1 = u16::from_le_bytes([block[2], block[3]]);
+    let indexes = u32::from_le_bytes(block[4..8].try_into().unwrap());
+
+    let c0 = rgb_from_565(color0);
+    let c1 = rgb_from_565(color1);
+    let mix = |a: u8, b: u8, wa: u16, wb: u16| ((u16::from(a) * wa + u16::from(b) * wb) / (wa + wb)) as u8;
+    let palette = if color0 > color1 || !allow_transparency {
+        [
+            [c0[0], c0[1], c0[2], 255],
+            [c1[0], c1[1], c1[2], 255],
+            [mix(c0[0], c1[0], 2, 1), mix(c0[1], c1[1], 2, 1), mix(c0[2], c1[2], 2, 1), 255],
+            [mix(c0[0], c1[0], 1, 2), mix(c0[1], c1[1], 1, 2), mix(c0[2], c1[2], 1, 2), 255],
+        ]
+    } else {
+        [
+            [c0[0], c0[1], c0[2], 255],
+            [c1[0], c1[1], c1[2], 255],
+            [mix(c0[0], c1[0], 1, 1), mix(c0[1], c1[1], 1, 1), mix(c0[2], c1[2], 1, 1), 255],
+            [0, 0, 0, 0],
+        ]
+    };
+
+    let mut pixels = [[0u8; 4]; 16];
+    for (i, pixel) in pixels.iter_mut().enumerate() {
+        *pixel = palette[((indexes >> (2 * i)) & 0x3) as usize];
+    }
+    pixels
+}
+
+/// Returns the eight alpha values of a DXT5 alpha block with the given endpoints.
+fn interpolate_alphas(alpha0: u8, alpha1: u8) -> [u8; 8] {
+    let a0 = u16::from(alpha0);
+    let a1 = u16::from(alpha1);
+    let mut alphas = [alpha0, alpha1, 0, 0, 0, 0, 0, 255];
+    if alpha0 > alpha1 {
+        for i in 1..7 {
+            alphas[usize::from(i) + 1] = (((7 - i) * a0 + i * a1) / 7) as u8;
+        }
+    } else {
+        for i in 1..5 {
+            alphas[usize::from(i) + 1] = (((5 - i) * a0 + i * a1) / 5) as u8;
+        }
+    }
+    alphas
+}
+
+/// Expands a 16-bit color with 5 bits of red, 6 bits of green and 5 bits of blue to 8 bits per
+/// channel.
+fn rgb_from_565(color: u16) -> [u8; 3] {
+    let r = (color >> 11) & 0x1F;
+    let g = (color >> 5) & 0x3F;
+    let b = color & 0x1F;
+    [
+        ((r * 255 + 15) / 31) as u8,
+        ((g * 255 + 31) / 63) as u8,
+        ((b * 255 + 15) / 31) as u8,
+    ]
+}
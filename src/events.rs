@@ -0,0 +1,97 @@
+//! Events reporting the progress of an extraction, for programs that display it.
+//!
+//! Each event is a JSON object on a line of its own (newline-delimited JSON). The `event` key
+//! specifies the kind of event:
+//!
+//! ```text
+//! {"event":"start","total_files":N,"total_bytes":B}   before the first file is extracted
+//! {"event":"file","path":P,"bytes":B}                 after a file has been extracted
+//! {"event":"skip","path":P,"reason":R}                when a selected file is not extracted
+//! {"event":"done","files":N,"bytes":B}                after the last file has been handled
+//! ```
+//!
+//! Paths are the paths of the resources within the REZ file, with components separated by `/`.
+//! Byte counts are the sizes of the resources as stored in the REZ file.
+
+
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::Mutex;
+
+use serde_json::json;
+
+
+/// Writes extraction events; may be shared between threads.
+///
+/// Once writing an event has failed, no further events are written; the error is returned by
+/// [`EventWriter::done`].
+pub struct EventWriter {
+    inner: Mutex<EventWriterInner>,
+}
+
+struct EventWriterInner {
+    writer: Box<dyn Write + Send>,
+    files: u64,
+    bytes: u64,
+    error: Option<io::Error>,
+}
+
+impl EventWriter {
+    pub fn new(writer: Box<dyn Write + Send>) -> Self {
+        Self {
+            inner: Mutex::new(EventWriterInner { writer, files: 0, bytes: 0, error: None }),
+        }
+    }
+
+    pub fn start(&self, total_files: u64, total_bytes: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.emit(json!({"event": "start", "total_files": total_files, "total_bytes": total_bytes}));
+    }
+
+    pub fn file(&self, path: &str, bytes: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.files += 1;
+        inner.bytes += bytes;
+        inner.emit(json!({"event": "file", "path": path, "bytes": bytes}));
+    }
+
+    pub fn skip(&self, path: &str, reason: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.emit(json!({"event": "skip", "path": path, "reason": reason}));
+    }
+
+    /// Outputs the final event, which summarizes the `file` events output so far. Returns the error
+    /// that occurred when writing the first event that could not be written, if any.
+    pub fn done(&self) -> io::Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        let (files, bytes) = (inner.files, inner.bytes);
+        inner.emit(json!({"event": "done", "files": files, "bytes": bytes}));
+        match inner.error.take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Debug for EventWriter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventWriter")
+            .finish_non_exhaustive()
+    }
+}
+
+impl EventWriterInner {
+    fn emit(&mut self, event: serde_json::Value) {
+        if self.error.is_some() {
+            return;
+        }
+        // flush after every event so that readers see progress immediately
+        let result = serde_json::to_writer(&mut self.writer, &event)
+            .map_err(io::Error::from)
+            .and_then(|()| writeln!(self.writer))
+            .and_then(|()| self.writer.flush());
+        if let Err(e) = result {
+            self.error = Some(e);
+        }
+    }
+}
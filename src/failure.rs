@@ -0,0 +1,124 @@
+//! Errors that end the program and the exit codes reported for them.
+//!
+//! lithrez exits with one of the following codes:
+//!
+//! ```text
+//! 0  success
+//! 1  the command ran, but its answer is negative or it did not succeed for all inputs: find
+//!    matched nothing, has did not find the path, verify found errors, diff found differences,
+//!    some files could not be extracted, or extraction was refused (e.g. for lack of space)
+//! 2  the command line is invalid, including invalid patterns and options not supported by this
+//!    build
+//! 3  reading or writing a file failed
+//! 4  an input file is not a valid REZ (or ZIP) file or is damaged, e.g. truncated
+//! 5  a path given on the command line does not exist within the REZ file
+//! ```
+//!
+//! A panic, which exits with code 101, always indicates a bug in lithrez.
+
+
+use std::io;
+use std::path::Path;
+
+use lithrez::rez;
+
+
+/// A summary of the exit codes for the help output.
+pub const EXIT_CODES_HELP: &str = "\
+Exit codes:
+  0  success
+  1  negative answer (e.g. find matched nothing) or not all inputs were processed
+  2  invalid command line
+  3  reading or writing a file failed
+  4  invalid or damaged REZ file
+  5  path not found within the REZ file";
+
+
+/// The kind of a failure, which determines the exit code.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum FailureKind {
+    Unsuccessful,
+    Usage,
+    Io,
+    InvalidInput,
+    NotFound,
+}
+impl FailureKind {
+    /// Returns the exit code corresponding to this kind of failure.
+    pub fn exit_code(self) -> u8 {
+        match self {
+            Self::Unsuccessful => 1,
+            Self::Usage => 2,
+            Self::Io => 3,
+            Self::InvalidInput => 4,
+            Self::NotFound => 5,
+        }
+    }
+}
+
+/// A failure that ends the program, along with the message to output about it.
+#[derive(Debug)]
+pub struct Failure {
+    pub kind: FailureKind,
+
+    /// The message to output, or `None` if the output already explains the outcome, e.g. when
+    /// `find` matches nothing.
+    pub message: Option<String>,
+}
+impl Failure {
+    pub fn new<M: Into<String>>(kind: FailureKind, message: M) -> Self {
+        Self { kind, message: Some(message.into()) }
+    }
+
+    /// A negative answer, which is not accompanied by a message.
+    pub fn negative() -> Self {
+        Self { kind: FailureKind::Unsuccessful, message: None }
+    }
+
+    /// Doing something to the file at the given path failed, e.g. `Failure::io("create", path, e)`.
+    pub fn io(action: &str, path: &Path, error: io::Error) -> Self {
+        Self::new(FailureKind::Io, format!("failed to {} {}: {}", action, path.display(), error))
+    }
+
+    /// Reading the REZ file at the given path failed.
+    pub fn rez(path: &Path, error: rez::Error) -> Self {
+        let kind = if error.is_io() { FailureKind::Io } else { FailureKind::InvalidInput };
+        Self::new(kind, format!("failed to read REZ file {}: {}", path.display(), error))
+    }
+
+    /// Writing the REZ file at the given path failed. Errors that prevent the REZ file from
+    /// being written in the first place, such as duplicate entries, count as invalid input.
+    pub fn rez_write(path: &Path, error: rez::Error) -> Self {
+        let kind = if error.is_io() { FailureKind::Io } else { FailureKind::InvalidInput };
+        Self::new(kind, format!("failed to write REZ file {}: {}", path.display(), error))
+    }
+
+    /// Modifying the REZ file at the given path as requested is not possible, e.g. because the
+    /// entry to be removed does not exist or another entry is in the way.
+    pub fn rez_modify(path: &Path, error: rez::Error) -> Self {
+        let kind = match &error {
+            rez::Error::Io(_) => FailureKind::Io,
+            rez::Error::NoSuchEntry { .. } => FailureKind::NotFound,
+            _ => FailureKind::Unsuccessful,
+        };
+        Self::new(kind, format!("cannot modify REZ file {}: {}", path.display(), error))
+    }
+
+    /// Reading the data of the resource at the given path within the REZ file at the given path,
+    /// stored at the given offset, failed.
+    pub fn resource(rez_path: &Path, entry_path: &str, offset: u64, error: io::Error) -> Self {
+        let kind = match error.kind() {
+            io::ErrorKind::UnexpectedEof | io::ErrorKind::InvalidData => FailureKind::InvalidInput,
+            _ => FailureKind::Io,
+        };
+        Self::new(
+            kind,
+            format!("failed to read {} at offset {} in {}: {}", entry_path, offset, rez_path.display(), error),
+        )
+    }
+
+    /// The given path does not exist within the REZ file.
+    pub fn not_found(entry_path: &str) -> Self {
+        Self::new(FailureKind::NotFound, format!("{} not found in REZ file", entry_path))
+    }
+}
@@ -0,0 +1,432 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use clap::Args;
+use regex::{Regex, RegexBuilder};
+
+use crate::calendar::days_from_civil;
+use crate::rez;
+
+
+/// Options selecting a subset of the files in a REZ file.
+#[derive(Args, Clone, Debug, Default)]
+pub struct FilterOpts {
+    /// If given, limits the selection to files whose paths match this pattern. If given multiple
+    /// times, a file will be selected if it matches at least one of the patterns. Patterns starting
+    /// with `!` deselect the files they match instead.
+    #[arg(short, long = "filter")]
+    pub filters: Vec<String>,
+
+    /// If given, limits the selection to files whose paths (with slashes as separators) contain a
+    /// match of this regular expression; use `^` and `$` to match whole paths. May be given
+    /// multiple times and combined with `--filter`; a file will then be selected if it matches at
+    /// least one of the patterns or regular expressions.
+    #[arg(long = "regex", value_name = "REGEX")]
+    pub regexes: Vec<String>,
+
+    /// If given, removes files whose paths match this pattern from the selection, even if they
+    /// match `--filter` or `--regex` (e.g. `--exclude '**.wav'`). May be given multiple times.
+    /// Equivalent to `--filter` with a pattern starting with `!`.
+    #[arg(long = "exclude", value_name = "PATTERN")]
+    pub excludes: Vec<String>,
+
+    /// If given, only selects files with one of these extensions, compared without regard to case.
+    /// Multiple extensions may be separated by commas (e.g. `--ext dtx,wav`).
+    #[arg(long = "ext", value_name = "EXTENSIONS", value_delimiter = ',')]
+    pub extensions: Vec<String>,
+
+    /// If given, patterns and regular expressions ignore the case of the paths they are matched
+    /// against, like LithTech itself.
+    #[arg(short = 'i', long)]
+    pub ignore_case: bool,
+
+    /// If given, only selects files modified after this time. Takes a Unix timestamp or an
+    /// ISO 8601 date or date and time in UTC (e.g. `2003-11-24` or `2003-11-24T13:37:00`).
+    #[arg(long, visible_alias = "newer-than", value_parser = parse_timestamp)]
+    pub since: Option<u32>,
+
+    /// If given, only selects files modified at or before this time. Takes the same formats as
+    /// `--since`.
+    #[arg(long, visible_alias = "older-than", value_parser = parse_timestamp)]
+    pub until: Option<u32>,
+
+    /// If given, only selects files of at least this size. Takes a number of bytes, optionally
+    /// followed by `K`, `M` or `G` for multiples of 1024 bytes (e.g. `512K`).
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    pub min_size: Option<u32>,
+
+    /// If given, only selects files of at most this size. Takes the same formats as `--min-size`.
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    pub max_size: Option<u32>,
+
+    /// If given, only selects files with a non-empty description.
+    #[arg(long, conflicts_with = "no_description")]
+    pub has_description: bool,
+
+    /// If given, only selects files with an empty description.
+    #[arg(long)]
+    pub no_description: bool,
+
+    /// If given, only selects files whose ID lies within this inclusive range, given as `LOW..HIGH`
+    /// (e.g. `1000..1999`). If `--id` is also given, files matching either are selected.
+    #[arg(long, value_name = "LOW..HIGH", value_parser = parse_id_range)]
+    pub id_range: Option<RangeInclusive<u32>>,
+
+    /// If given, only selects files with one of these IDs. May be given multiple times, and
+    /// multiple IDs may be separated by commas (e.g. `--id 12,15`).
+    #[arg(long = "id", value_name = "IDS", value_delimiter = ',')]
+    pub ids: Vec<u32>,
+}
+
+
+/// The compiled form of [`FilterOpts`].
+#[derive(Clone, Debug, Default)]
+pub struct ResourceFilter {
+    globs: Vec<Regex>,
+    negated_globs: Vec<Regex>,
+    extensions: Vec<String>,
+    since: Option<u32>,
+    until: Option<u32>,
+    min_size: Option<u32>,
+    max_size: Option<u32>,
+    has_description: Option<bool>,
+    id_range: Option<RangeInclusive<u32>>,
+    ids: Vec<u32>,
+}
+impl ResourceFilter {
+    /// Compiles the given options, failing if any of the patterns is invalid.
+    pub fn from_opts(opts: &FilterOpts) -> Result<Self, GlobError> {
+        let mut globs = Vec::new();
+        let mut negated_globs = Vec::new();
+        for filter in &opts.filters {
+            match filter.strip_prefix('!') {
+                Some(negated) => negated_globs.push(try_glob_to_regex(negated, opts.ignore_case)?),
+                None => globs.push(try_glob_to_regex(filter, opts.ignore_case)?),
+            }
+        }
+        for exclude in &opts.excludes {
+            negated_globs.push(try_glob_to_regex(exclude, opts.ignore_case)?);
+        }
+        for regex in &opts.regexes {
+            globs.push(compile_regex(regex, regex, opts.ignore_case)?);
+        }
+        Ok(Self {
+            globs,
+            negated_globs,
+            extensions: opts.extensions
+                .iter()
+                .map(|e| e.trim_start_matches('.').to_owned())
+                .collect(),
+            since: opts.since,
+            until: opts.until,
+            min_size: opts.min_size,
+            max_size: opts.max_size,
+            has_description: if opts.has_description {
+                Some(true)
+            } else if opts.no_description {
+                Some(false)
+            } else {
+                None
+            },
+            id_range: opts.id_range.clone(),
+            ids: opts.ids.clone(),
+        })
+    }
+
+    /// Returns whether this filter selects every file.
+    pub fn is_unrestricted(&self) -> bool {
+        self.globs.is_empty()
+            && self.negated_globs.is_empty()
+            && self.extensions.is_empty()
+            && self.since.is_none()
+            && self.until.is_none()
+            && self.min_size.is_none()
+            && self.max_size.is_none()
+            && self.has_description.is_none()
+            && self.id_range.is_none()
+            && self.ids.is_empty()
+    }
+
+    /// Returns whether the given resource at the given path is selected by this filter.
+    pub fn matches(&self, entry_path: &str, res: &rez::Resource) -> bool {
+        if !self.globs.is_empty() && self.globs.iter().all(|g| !g.is_match(entry_path)) {
+            return false;
+        }
+        if self.negated_globs.iter().any(|g| g.is_match(entry_path)) {
+            return false;
+        }
+        if !self.extensions.is_empty() && !self.extensions.iter().any(|e| e.eq_ignore_ascii_case(&res.extension)) {
+            return false;
+        }
+        if let Some(since) = self.since {
+            if res.header.time <= since {
+                return false;
+            }
+        }
+        if let Some(until) = self.until {
+            if res.header.time > until {
+                return false;
+            }
+        }
+        if let Some(min_size) = self.min_size {
+            if res.header.size < min_size {
+                return false;
+            }
+        }
+        if let Some(max_size) = self.max_size {
+            if res.header.size > max_size {
+                return false;
+            }
+        }
+        if let Some(has_description) = self.has_description {
+            if res.description.is_empty() == has_description {
+                return false;
+            }
+        }
+        if self.id_range.is_some() || !self.ids.is_empty() {
+            let in_range = self.id_range.as_ref()
+                .map(|r| r.contains(&res.id))
+                .unwrap_or(false);
+            if !in_range && !self.ids.contains(&res.id) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+
+/// Parses a Unix timestamp or an ISO 8601 date (`YYYY-MM-DD`) or date and time
+/// (`YYYY-MM-DDTHH:MM:SS`, optionally followed by `Z`) in UTC.
+pub fn parse_timestamp(value: &str) -> Result<u32, String> {
+    if let Ok(timestamp) = value.parse::<u32>() {
+        return Ok(timestamp);
+    }
+
+    let invalid = || format!("{:?} is neither a Unix timestamp nor an ISO 8601 date", value);
+
+    let value = value.strip_suffix('Z').unwrap_or(value);
+    let (date_str, time_str) = match value.split_once(['T', ' ']) {
+        Some((d, t)) => (d, Some(t)),
+        None => (value, None),
+    };
+
+    let date_pieces: Vec<&str> = date_str.split('-').collect();
+    let [year_str, month_str, day_str] = date_pieces.as_slice() else { return Err(invalid()) };
+    let year: i64 = year_str.parse().map_err(|_| invalid())?;
+    let month: i64 = month_str.parse().map_err(|_| invalid())?;
+    let day: i64 = day_str.parse().map_err(|_| invalid())?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return Err(invalid());
+    }
+
+    let mut seconds_of_day = 0;
+    if let Some(time_str) = time_str {
+        let time_pieces: Vec<&str> = time_str.split(':').collect();
+        let (hour_str, minute_str, second_str) = match time_pieces.as_slice() {
+            [h, m] => (*h, *m, "0"),
+            [h, m, s] => (*h, *m, *s),
+            _ => return Err(invalid()),
+        };
+        let hour: i64 = hour_str.parse().map_err(|_| invalid())?;
+        let minute: i64 = minute_str.parse().map_err(|_| invalid())?;
+        let second: i64 = second_str.parse().map_err(|_| invalid())?;
+        if !(0..24).contains(&hour) || !(0..60).contains(&minute) || !(0..=60).contains(&second) {
+            return Err(invalid());
+        }
+        seconds_of_day = hour * 60 * 60 + minute * 60 + second;
+    }
+
+    let timestamp = days_from_civil(year, month, day) * 24 * 60 * 60 + seconds_of_day;
+    u32::try_from(timestamp)
+        .map_err(|_| format!("{:?} is outside the range of REZ timestamps", value))
+}
+
+/// Parses a size in bytes, optionally followed by `K`, `M` or `G` (case-insensitive, optionally
+/// followed by `B` or `iB`) for multiples of 1024.
+pub fn parse_size(value: &str) -> Result<u32, String> {
+    let invalid = || format!("{:?} is not a size", value);
+    let trimmed = value.trim();
+    let digits_end = trimmed.find(|c: char| !c.is_ascii_digit()).unwrap_or(trimmed.len());
+    let (number_str, unit) = trimmed.split_at(digits_end);
+    let number: u64 = number_str.parse().map_err(|_| invalid())?;
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1024,
+        "m" | "mb" | "mib" => 1024 * 1024,
+        "g" | "gb" | "gib" => 1024 * 1024 * 1024,
+        _ => return Err(invalid()),
+    };
+    number.checked_mul(multiplier)
+        .and_then(|size| u32::try_from(size).ok())
+        .ok_or_else(|| format!("{:?} is larger than any REZ resource can be", value))
+}
+
+/// Parses an inclusive range of resource IDs given as `LOW..HIGH`.
+pub fn parse_id_range(value: &str) -> Result<RangeInclusive<u32>, String> {
+    let invalid = || format!("{:?} is not a range of the form LOW..HIGH", value);
+    let (low_str, high_str) = value.split_once("..").ok_or_else(invalid)?;
+    let low: u32 = low_str.trim().parse().map_err(|_| invalid())?;
+    let high: u32 = high_str.trim().parse().map_err(|_| invalid())?;
+    if low > high {
+        return Err(format!("the range {:?} is empty since {} is greater than {}", value, low, high));
+    }
+    Ok(low..=high)
+}
+
+/// An error encountered when translating a glob pattern into a regular expression or compiling a
+/// regular expression given directly.
+#[derive(Debug)]
+pub enum GlobError {
+    /// The pattern is empty and would never match anything.
+    Empty,
+    /// A character class opened with `[` is never closed.
+    UnterminatedClass { pattern: String },
+    /// The regular expression given or translated from the pattern cannot be compiled, e.g.
+    /// because it is invalid or exceeds the size limit of the regex engine.
+    Regex { pattern: String, error: regex::Error },
+}
+impl fmt::Display for GlobError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty
+                => write!(f, "the pattern is empty"),
+            Self::UnterminatedClass { pattern }
+                => write!(f, "{:?}: unterminated character class", pattern),
+            Self::Regex { pattern, error }
+                => write!(f, "{:?}: {}", pattern, error),
+        }
+    }
+}
+impl std::error::Error for GlobError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Empty => None,
+            Self::UnterminatedClass { .. } => None,
+            Self::Regex { error, .. } => Some(error),
+        }
+    }
+}
+
+/// Translates a glob pattern into a regular expression matching whole paths.
+///
+/// `*` matches any sequence of characters except a slash, `**` any sequence including slashes and
+/// `?` any single character except a slash. `[abc]` matches any of the listed characters, which may
+/// include ranges such as `[a-z]`, and `[!abc]` (or `[^abc]`) any character except the listed ones
+/// and a slash; a `]` directly after the opening bracket is taken literally. Outside of character
+/// classes, backslashes are treated as path separators.
+///
+/// If `case_insensitive` is set, the regular expression ignores the case of the paths it matches.
+pub fn try_glob_to_regex(glob_pattern: &str, case_insensitive: bool) -> Result<Regex, GlobError> {
+    if glob_pattern.is_empty() {
+        return Err(GlobError::Empty);
+    }
+
+    // single asterisk: sequence of anything except a slash
+    const SINGLE_ASTERISK_MATCHER: &str = "[^/]+";
+    // multiple asterisks: sequence of anything including a slash
+    const MULTIPLE_ASTERISK_MATCHER: &str = ".+";
+    // question mark: anything except a slash
+    const QUESTION_MARK_MATCHER: &str = "[^/]";
+
+    let mut asterisk_counter = 0;
+    let mut regex_buf = String::with_capacity(glob_pattern.len() + 2);
+    regex_buf.push('^');
+
+    let mut chars = glob_pattern.chars();
+    while let Some(c) = chars.next() {
+        if c == '*' {
+            asterisk_counter += 1;
+            continue;
+        } else {
+            if asterisk_counter == 1 {
+                regex_buf.push_str(SINGLE_ASTERISK_MATCHER);
+            } else if asterisk_counter >= 2 {
+                regex_buf.push_str(MULTIPLE_ASTERISK_MATCHER);
+            }
+            asterisk_counter = 0;
+        }
+
+        if c == '\\' {
+            // normalize paths to forward slashes
+            regex_buf.push('/');
+            continue;
+        }
+
+        if c == '?' {
+            regex_buf.push_str(QUESTION_MARK_MATCHER);
+            continue;
+        }
+
+        if c == '[' {
+            let class = translate_class(&mut chars)
+                .ok_or_else(|| GlobError::UnterminatedClass { pattern: glob_pattern.to_owned() })?;
+            regex_buf.push_str(&class);
+            continue;
+        }
+
+        // everything else is a literal character; let the regex crate decide what needs escaping
+        // (note that `\<` and `\>` are word boundary assertions, so `<` and `>` must not be
+        // escaped with a backslash, and neither may most non-ASCII characters)
+        let mut char_buf = [0u8; 4];
+        regex_buf.push_str(&regex::escape(c.encode_utf8(&mut char_buf)));
+    }
+
+    // any trailing asterisks?
+    if asterisk_counter == 1 {
+        regex_buf.push_str(SINGLE_ASTERISK_MATCHER);
+    } else if asterisk_counter >= 2 {
+        regex_buf.push_str(MULTIPLE_ASTERISK_MATCHER);
+    }
+
+    regex_buf.push('$');
+
+    compile_regex(&regex_buf, glob_pattern, case_insensitive)
+}
+
+/// Compiles a regular expression, attributing errors to the given pattern from which it was
+/// obtained.
+fn compile_regex(regex: &str, pattern: &str, case_insensitive: bool) -> Result<Regex, GlobError> {
+    RegexBuilder::new(regex)
+        .case_insensitive(case_insensitive)
+        .build()
+        .map_err(|error| GlobError::Regex { pattern: pattern.to_owned(), error })
+}
+
+
+/// Translates the character class whose opening bracket has just been consumed from `chars` into a
+/// regular expression character class that never matches a slash. Returns `None` if the class is
+/// not closed.
+fn translate_class(chars: &mut std::str::Chars) -> Option<String> {
+    let mut items = String::new();
+    let mut negated = false;
+    let mut first = true;
+    loop {
+        let c = chars.next()?;
+        if first && (c == '!' || c == '^') && !negated {
+            negated = true;
+            continue;
+        }
+        if c == ']' && !first {
+            break;
+        }
+        first = false;
+
+        if c == '-' && !items.is_empty() && !chars.as_str().starts_with(']') {
+            // range between the previous and the next character
+            items.push('-');
+            continue;
+        }
+        if c.is_ascii_punctuation() {
+            // escaping any ASCII punctuation is allowed within regex classes
+            items.push('\\');
+        }
+        items.push(c);
+    }
+
+    if negated {
+        Some(format!("[^/{}]", items))
+    } else {
+        Some(format!("[{}&&[^/]]", items))
+    }
+}
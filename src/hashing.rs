@@ -0,0 +1,9 @@
+/// Calculates the 32-bit FNV-1a hash of the given bytes, which is stable across program runs.
+pub fn fnv1a_32(bytes: &[u8]) -> u32 {
+    let mut hash: u32 = 0x811C9DC5;
+    for &b in bytes {
+        hash ^= u32::from(b);
+        hash = hash.wrapping_mul(0x01000193);
+    }
+    hash
+}
@@ -1,9 +1,8 @@
-use std::io;
+use std::io::{self, Cursor};
 
 
 pub trait ReadExt {
     fn read_u32_le(&mut self) -> Result<u32, io::Error>;
-    fn read_nul_terminated_byte_string(&mut self) -> Result<Vec<u8>, io::Error>;
 }
 impl<R: io::Read> ReadExt for R {
     fn read_u32_le(&mut self) -> Result<u32, io::Error> {
@@ -11,17 +10,25 @@ impl<R: io::Read> ReadExt for R {
         self.read_exact(&mut buf)?;
         Ok(u32::from_le_bytes(buf))
     }
+}
+
 
-    fn read_nul_terminated_byte_string(&mut self) -> Result<Vec<u8>, io::Error> {
-        let mut buf = [0];
-        let mut ret = Vec::new();
-        loop {
-            self.read_exact(&mut buf)?;
-            if buf[0] == 0 {
-                break;
-            }
-            ret.push(buf[0]);
-        }
-        Ok(ret)
+/// Reading from cursors over in-memory data, scanning the remaining data directly instead of
+/// reading one byte at a time.
+pub trait SliceCursorExt<'a> {
+    /// Returns the bytes up to the next NUL byte and advances past the NUL byte.
+    fn read_nul_terminated_slice(&mut self) -> Result<&'a [u8], io::Error>;
+}
+impl<'a> SliceCursorExt<'a> for Cursor<&'a [u8]> {
+    fn read_nul_terminated_slice(&mut self) -> Result<&'a [u8], io::Error> {
+        let data: &'a [u8] = self.get_ref();
+        let start: usize = self.position().try_into().unwrap();
+        let remaining = data.get(start..).unwrap_or(&[]);
+        let Some(nul_index) = remaining.iter().position(|b| *b == 0x00) else {
+            self.set_position(data.len().try_into().unwrap());
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        };
+        self.set_position((start + nul_index + 1).try_into().unwrap());
+        Ok(&remaining[..nul_index])
     }
 }
@@ -0,0 +1,14 @@
+//! Reading and writing REZ files, the resource archives of the LithTech engine.
+//!
+//! The entry point is [`rez::File`], which reads the header and the directory tree of a REZ file;
+//! the data of the resources is read using [`rez::RezReader`] or streamed using
+//! [`rez::File::open_resource`]. [`rez::Archive`] combines both for looking up and reading
+//! resources by path. Textures stored within REZ files can
+//! be decoded using [`dtx::decode`].
+//!
+//! Diagnostic messages, such as warnings about unusual but readable headers, are emitted using the
+//! [`log`] crate; they only appear if the application installs a logger.
+
+pub mod dtx;
+pub mod io_ext;
+pub mod rez;
@@ -0,0 +1,94 @@
+//! Machine-readable listings of the contents of REZ files.
+//!
+//! The JSON format is an array of the root entries. Directories are objects with the keys `type`
+//! (always `"directory"`), `name`, `time` and `entries`, the latter being an array of the entries
+//! within the directory. Resources are objects with the keys `type` (always `"resource"`), `path`,
+//! `name`, `extension`, `id`, `description`, `offset`, `size`, `time` and `keys`. Offsets are
+//! counted from the start of the underlying file.
+//!
+//! The CSV and TSV formats contain one row per resource with the columns `path`, `extension`, `id`,
+//! `size`, `offset`, `time` and `description`, preceded by a row with the column names.
+
+
+use std::io::{self, Write};
+
+use serde_json::json;
+
+use crate::filter::ResourceFilter;
+use crate::rez;
+
+
+/// Writes the entries of the REZ file selected by the filter as a JSON tree.
+///
+/// Directories without any selected resources are left out, unless the filter is unrestricted.
+pub fn write_json<W: Write>(writer: &mut W, rez_file: &rez::File, filter: &ResourceFilter) -> io::Result<()> {
+    let tree = json_entries_recursive(&rez_file.root_entries, "", rez_file.base_offset, filter);
+    serde_json::to_writer_pretty(&mut *writer, &tree)?;
+    writeln!(writer)?;
+    writer.flush()
+}
+
+/// Writes the resources of the REZ file selected by the filter as a table, with the columns
+/// separated by the given delimiter (e.g. `b','` for CSV or `b'\t'` for TSV).
+pub fn write_delimited<W: Write>(writer: &mut W, rez_file: &rez::File, filter: &ResourceFilter, delimiter: u8) -> io::Result<()> {
+    let mut table = csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(writer);
+    table.write_record(["path", "extension", "id", "size", "offset", "time", "description"])?;
+    for (entry_path, entry) in rez_file.walk() {
+        let rez::Entry::Resource(res) = entry else { continue };
+        if !filter.matches(&entry_path, res) {
+            continue;
+        }
+        table.write_record([
+            entry_path,
+            res.extension.clone(),
+            res.id.to_string(),
+            res.header.size.to_string(),
+            rez_file.data_offset(res).to_string(),
+            res.header.time.to_string(),
+            res.description.clone(),
+        ])?;
+    }
+    table.flush()
+}
+
+fn json_entries_recursive(entries: &[rez::Entry], base_path: &str, base_offset: u64, filter: &ResourceFilter) -> Vec<serde_json::Value> {
+    let mut records = Vec::new();
+    for entry in entries {
+        let entry_path = rez::join_path(base_path, &entry.name());
+
+        match entry {
+            rez::Entry::Directory(dir) => {
+                let children = json_entries_recursive(&dir.entries, &entry_path, base_offset, filter);
+                if children.is_empty() && !filter.is_unrestricted() {
+                    continue;
+                }
+                records.push(json!({
+                    "type": "directory",
+                    "name": dir.name,
+                    "time": dir.header.time,
+                    "entries": children,
+                }));
+            },
+            rez::Entry::Resource(res) => {
+                if !filter.matches(&entry_path, res) {
+                    continue;
+                }
+                records.push(json!({
+                    "type": "resource",
+                    "path": entry_path,
+                    "name": res.name,
+                    "extension": res.extension,
+                    "id": res.id,
+                    "description": res.description,
+                    "offset": base_offset + u64::from(res.header.position),
+                    "size": res.header.size,
+                    "time": res.header.time,
+                    "keys": res.keys,
+                }));
+            },
+        }
+    }
+    records
+}
@@ -1,208 +1,3068 @@
-mod io_ext;
-mod rez;
+mod calendar;
+mod convert;
+mod diff;
+mod events;
+mod failure;
+mod filter;
+mod hashing;
+mod listing;
+mod merge;
+mod names;
+#[cfg(all(feature = "fuse", target_os = "linux"))]
+mod mount;
+mod pack;
+mod positions;
+mod progress;
+mod serve;
+mod tarfile;
+mod verify;
+mod zipfile;
 
 
-use std::fs::File;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{File, OpenOptions};
+use std::hash::Hasher;
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-use clap::Parser;
-use regex::Regex;
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
+use encoding_rs::Encoding;
+use lithrez::rez;
+use log::{debug, error, info, warn, LevelFilter};
+
+use crate::events::EventWriter;
+use crate::failure::{Failure, FailureKind};
+use crate::filter::{FilterOpts, ResourceFilter};
+use crate::progress::ExtractProgress;
+
+
+/// The size of the buffer through which resource data is copied into output files.
+const COPY_BUFFER_SIZE: usize = 4*1024*1024;
+
+/// The extension appended to the file name of an extracted resource to obtain the name of the file
+/// into which its description is written.
+const DESCRIPTION_SIDECAR_EXTENSION: &str = ".txt";
 
 
 #[derive(Parser)]
+#[command(after_help = failure::EXIT_CODES_HELP)]
+struct Cli {
+    #[command(flatten)]
+    pub verbosity: VerbosityOpts,
+
+    #[command(subcommand)]
+    pub mode: Mode,
+}
+
+#[derive(Args)]
+struct VerbosityOpts {
+    /// Output more diagnostic messages: debugging messages if given once, and tracing messages as
+    /// well if given twice. Takes precedence over the RUST_LOG environment variable.
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    pub verbose: u8,
+
+    /// Output fewer diagnostic messages: only warnings and errors if given once, only errors if
+    /// given twice and nothing at all if given three times. Takes precedence over the RUST_LOG
+    /// environment variable.
+    #[arg(short, long, action = ArgAction::Count, global = true, conflicts_with = "verbose")]
+    pub quiet: u8,
+}
+impl VerbosityOpts {
+    /// Returns the level of diagnostic messages requested on the command line, or `None` if
+    /// neither `--verbose` nor `--quiet` has been given.
+    pub fn level_filter(&self) -> Option<LevelFilter> {
+        let level = match (self.verbose, self.quiet) {
+            (0, 0) => return None,
+            (1, _) => LevelFilter::Debug,
+            (v, _) if v > 1 => LevelFilter::Trace,
+            (_, 1) => LevelFilter::Warn,
+            (_, 2) => LevelFilter::Error,
+            _ => LevelFilter::Off,
+        };
+        Some(level)
+    }
+}
+
+#[derive(Subcommand)]
 enum Mode {
     /// List the contents of a REZ file.
     List(ListOpts),
 
-    /// Extract the files in a REZ file to a specific directory.
-    Extract(ExtractOpts),
+    /// Extract the files in a REZ file to a specific directory.
+    Extract(Box<ExtractOpts>),
+
+    /// Check a REZ file for structural problems.
+    Verify(VerifyOpts),
+
+    /// Output the paths of the files in a REZ file matching specific patterns.
+    ///
+    /// Like grep, exits with status 0 if at least one file matches and 1 otherwise.
+    Find(FindOpts),
+
+    /// Output a hex dump of a file in a REZ file.
+    Hexdump(HexdumpOpts),
+
+    /// Write the contents of a file in a REZ file to standard output.
+    Cat(CatOpts),
+
+    /// Check whether a REZ file contains a specific file.
+    ///
+    /// Outputs nothing; exits with status 0 if the file exists and 1 otherwise.
+    Has(HasOpts),
+
+    /// Pack a directory tree into a new REZ file.
+    #[command(visible_alias = "create")]
+    Pack(PackOpts),
+
+    /// Output the file type and version of each of the given files, reading only their headers.
+    ///
+    /// Files that are not REZ files are reported as such instead of causing an error.
+    Identify(IdentifyOpts),
+
+    /// Output which file in a REZ file contains the byte at a specific offset.
+    ///
+    /// Exits with status 1 if the offset is not within any file.
+    Whatis(WhatisOpts),
+
+    /// Write a table of the offsets and lengths of the files in a REZ file, for programs that read
+    /// the REZ file directly without parsing its directory.
+    Positions(PositionsOpts),
+
+    /// Output the fields of the header of a REZ file and general information about its contents.
+    Info(InfoOpts),
+
+    /// Output the total size of the files within each directory of a REZ file, largest first.
+    Du(DuOpts),
+
+    /// Add files and directory trees to an existing REZ file.
+    ///
+    /// The added files are assigned IDs above the highest ID in the REZ file.
+    Add(AddOpts),
+
+    /// Remove files and directories from a REZ file.
+    Rm(RmOpts),
+
+    /// Rename a file or directory within a REZ file or move it to another directory.
+    Mv(MvOpts),
+
+    /// Compare the files in two REZ files by path, outputting those that have been added (A),
+    /// deleted (D) or modified (M).
+    ///
+    /// Like diff, exits with status 0 if there are no differences and 1 otherwise.
+    Diff(DiffOpts),
+
+    /// Combine multiple REZ files into a new REZ file.
+    Merge(MergeOpts),
+
+    /// Mount a REZ file as a read-only file system using FUSE, allowing its files to be opened by
+    /// other programs without extracting them.
+    ///
+    /// Runs until the file system is unmounted, e.g. using `fusermount -u`. Only available on Linux
+    /// in builds with the "fuse" feature.
+    Mount(MountOpts),
+
+    /// Serve the contents of a REZ file over HTTP, with an index page for each directory.
+    ///
+    /// Runs until interrupted.
+    Serve(ServeOpts),
+
+    /// Convert a REZ file into a ZIP archive, preserving the paths and modification times of its
+    /// files.
+    ToZip(ToZipOpts),
+
+    /// Write the files of a REZ file as a tar stream, e.g. to pipe them into `tar` or a compressor.
+    ToTar(ToTarOpts),
+
+    /// Pack the contents of a ZIP archive into a new REZ file, preserving the directory structure
+    /// and the modification times.
+    FromZip(FromZipOpts),
+}
+
+#[derive(Parser)]
+struct ListOpts {
+    #[command(flatten)]
+    pub input: RezInputOpts,
+
+    #[command(flatten)]
+    pub filter: FilterOpts,
+
+    /// Which kinds of entries to output. With `resource`, the resources are output as a flat list
+    /// of full paths; with `directory`, only the directory skeleton is output.
+    #[arg(long = "type", value_enum, default_value_t)]
+    pub entry_type: EntryTypeOpt,
+
+    #[command(flatten)]
+    pub ext_order: ExtOrderOpts,
+
+    #[command(flatten)]
+    pub text_style: TextStyleOpts,
+
+    /// Instead of the listing, output the debug representation of the parsed header and file
+    /// structures, e.g. for bug reports.
+    #[arg(long, hide = true)]
+    pub debug_dump: bool,
+
+    /// Output the directory tree as JSON, including all the information stored about each entry.
+    #[arg(long, conflicts_with_all = ["entry_type", "debug_dump"])]
+    pub json: bool,
+
+    /// Output a table with one row per file in CSV format, including all the information stored
+    /// about each file except its keys.
+    #[arg(long, conflicts_with_all = ["entry_type", "debug_dump", "json"])]
+    pub csv: bool,
+
+    /// Like `--csv`, but with the columns separated by tab characters.
+    #[arg(long, conflicts_with_all = ["entry_type", "debug_dump", "json", "csv"])]
+    pub tsv: bool,
+
+    /// Only output the number of entries that would be listed, taking `--type` and the filters
+    /// into account.
+    #[arg(short, long, conflicts_with_all = ["debug_dump", "json", "csv", "tsv"])]
+    pub count: bool,
+
+    /// Map the REZ file into memory and read it from there instead of issuing a system call for each
+    /// read. Faster for huge REZ files; the file must not be modified while it is being read.
+    #[arg(long)]
+    pub mmap: bool,
+}
+
+#[derive(Parser)]
+struct ExtractOpts {
+    #[command(flatten)]
+    pub filter: FilterOpts,
+
+    #[command(flatten)]
+    pub input: RezInputOpts,
+
+    /// If given, the description of each extracted resource with a non-empty description is
+    /// written into a sidecar file named like the resource with an additional `.txt` extension.
+    #[arg(long)]
+    pub descriptions: bool,
+
+    /// If given, each extracted DTX texture is additionally decoded and written as a PNG image
+    /// named like the resource with the extension replaced by `.png`.
+    #[arg(long)]
+    pub convert_dtx: bool,
+
+    /// If given, the file stem of the REZ file is inserted as the first path component of every
+    /// extracted file. Useful to extract multiple REZ files into the same output directory.
+    #[arg(long)]
+    pub prefix_archive_name: bool,
+
+    /// If given, directories corresponding to REZ directories that are empty after extraction are
+    /// removed from the output directory.
+    #[arg(long)]
+    pub prune_empty_dirs: bool,
+
+    /// If given, the modification times of the extracted files and directories are left at the
+    /// time of extraction. By default, they are set to the times stored in the REZ file.
+    #[arg(long)]
+    pub no_preserve_times: bool,
+
+    /// If given, each extracted file is read back after writing and its length and contents are
+    /// compared to the data in the REZ file. If any file differs, the exit status is nonzero.
+    #[arg(long)]
+    pub verify_after_extract: bool,
+
+    /// The number of threads with which to extract the files of each directory. Directories are
+    /// still processed one after the other in archive order, and all files of a directory are
+    /// extracted before descending into its subdirectories.
+    #[arg(long, default_value = "1")]
+    pub dir_jobs: usize,
+
+    /// The number of threads with which to extract files. Unlike `--dir-jobs`, files from all
+    /// directories are extracted concurrently, which keeps all threads busy even if the directories
+    /// contain few files each.
+    #[arg(short, long, default_value = "1", conflicts_with = "dir_jobs")]
+    pub jobs: usize,
+
+    /// Map the REZ file into memory and read it from there instead of issuing a system call for each
+    /// read. Faster for huge REZ files; the file must not be modified while it is being read.
+    #[arg(long)]
+    pub mmap: bool,
+
+    /// The maximum length of the name of an extracted file, in bytes. Longer names are truncated,
+    /// keeping the extension and appending a hash of the full name to keep names unique. With
+    /// `--descriptions`, the limit also applies to the names of the description sidecar files.
+    #[arg(long)]
+    pub max_name_length: Option<usize>,
+
+    /// Changes the extension of extracted files from OLD to NEW, given as `OLD=NEW`, e.g.
+    /// `--ext-map dtx=dds`. OLD is matched case-insensitively. May be given multiple times; the
+    /// first matching mapping applies. This only affects the names of the extracted files, not the
+    /// paths matched by filters or written to manifests.
+    #[arg(long, value_name = "OLD=NEW", value_parser = parse_ext_mapping)]
+    pub ext_map: Vec<(String, String)>,
+
+    #[command(flatten)]
+    pub ext_order: ExtOrderOpts,
+
+    /// The extensions of text files whose contents are converted from `--from-encoding` to
+    /// `--to-encoding` during extraction, separated by commas (e.g. `txt,cfg`). Files with other
+    /// extensions are extracted unchanged.
+    #[arg(long, value_name = "EXTENSIONS", value_delimiter = ',')]
+    pub transcode_text_ext: Vec<String>,
+
+    /// The encoding of the text files selected by `--transcode-text-ext` within the REZ file.
+    #[arg(long, value_name = "ENCODING", default_value = "windows-1252", value_parser = parse_encoding)]
+    pub from_encoding: &'static Encoding,
+
+    /// The encoding into which the text files selected by `--transcode-text-ext` are converted.
+    #[arg(long, value_name = "ENCODING", default_value = "utf-8", value_parser = parse_output_encoding)]
+    pub to_encoding: &'static Encoding,
+
+    /// The compression with which the files are stored in the REZ file. Base REZ files are not
+    /// compressed, but some game-specific variants are.
+    #[arg(long, value_enum, default_value_t)]
+    pub decompress: DecompressOpt,
+
+    /// If given, only extracts the files within this directory of the REZ file and its
+    /// subdirectories, keeping their full paths. May be given multiple times.
+    #[arg(long)]
+    pub only: Vec<String>,
+
+    /// If given, writes a manifest of the IDs of the extracted files into this file, which can be
+    /// used with `pack --id-strategy from-manifest` to keep the IDs when repacking.
+    #[arg(long)]
+    pub write_manifest: Option<PathBuf>,
+
+    /// Output events reporting the progress of the extraction in the given format, for programs
+    /// that display it. With `ndjson`, each event is a JSON object on a line of its own; the `event`
+    /// key is one of `start`, `file`, `skip` and `done`.
+    #[arg(long, value_enum)]
+    pub events: Option<EventsFormatOpt>,
+
+    /// The file into which to write the events requested with `--events`. By default, they are
+    /// written to standard output.
+    #[arg(long, requires = "events")]
+    pub events_file: Option<PathBuf>,
+
+    /// If given, a directory whose only entry is a single subdirectory is extracted as one
+    /// directory whose name joins both names with an underscore. This repeats along the chain, so
+    /// `a/b/c/file.txt` becomes `a_b_c/file.txt` if `a` only contains `b` and `b` only contains `c`.
+    /// Directories containing any resource or more than one subdirectory are never collapsed.
+    #[arg(long)]
+    pub collapse_single_dirs: bool,
+
+    /// If given, all selected resources are extracted directly into the output directory instead of
+    /// recreating the directory hierarchy of the REZ file.
+    #[arg(long, conflicts_with = "collapse_single_dirs")]
+    pub flatten: bool,
+
+    /// How to handle resources from different directories that have the same name when extracting
+    /// with `--flatten`: append the ID of each such resource to its name (e.g. `boom~2.wav`), or
+    /// treat them like existing files according to `--skip-existing`, `--overwrite`,
+    /// `--rename-duplicates` and `--prompt`. Names are compared without regard to case.
+    #[arg(long, value_enum, default_value_t, requires = "flatten")]
+    pub flatten_collisions: FlattenCollisionsOpt,
+
+    /// If given, the names of the extracted files and directories are converted to lowercase.
+    /// Directories whose names only differ in case are merged.
+    #[arg(long)]
+    pub lowercase: bool,
+
+    /// If given, the names of entries are used as they are stored in the REZ file. By default,
+    /// names that contain path separators or control characters, consist of `.` or `..`, or are
+    /// Windows device names such as `CON` are rewritten so that every file is extracted into the
+    /// output directory; only use this option with REZ files from trusted sources.
+    #[arg(long)]
+    pub trust_names: bool,
+
+    /// If given, everything in the output directory is deleted before extracting. A directory that
+    /// is not empty is only cleaned if `--force` is given as well, and a directory containing the
+    /// REZ file itself is never cleaned.
+    #[arg(long)]
+    pub clean: bool,
+
+    /// Allows `--clean` to delete the contents of a directory that is not empty, and extraction to
+    /// start even if the file system of the output directory appears to lack the space for the
+    /// extracted files.
+    #[arg(long)]
+    pub force: bool,
+
+    /// If given, nothing is extracted unless the file type stored in the header of the REZ file is
+    /// exactly this string (ignoring trailing spaces). An empty string expects the file type to be
+    /// missing.
+    #[arg(long)]
+    pub expect_type: Option<String>,
+
+    /// If given, every extracted file whose contents are identical to those of a previously
+    /// extracted file is replaced by a link to that file, keeping the directory layout of the REZ
+    /// file. Hard links are used unless `--dedup-links=symbolic` is given.
+    #[arg(long, value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "hard")]
+    pub dedup_links: Option<LinkKindOpt>,
+
+    /// How many more times to try writing an extracted file if writing it fails, e.g. on a network
+    /// file system with transient errors. Errors reading the REZ file are never retried.
+    #[arg(long, default_value = "0")]
+    pub retries: u32,
+
+    /// The delay before the first retry, in milliseconds. The delay doubles with every further
+    /// retry.
+    #[arg(long, default_value = "500")]
+    pub retry_delay: u64,
+
+    /// What to do with a file whose data extends past the end of the REZ file: skip it, extract
+    /// only the data that is available, or stop with an error.
+    #[arg(long, value_enum, default_value_t)]
+    pub on_short_read: ShortReadOpt,
+
+    /// If given, nothing is written; instead, each file that would be extracted is output along
+    /// with the path into which it would be written. Files that would overwrite existing files or
+    /// each other are pointed out.
+    #[arg(long, conflicts_with_all = ["clean", "write_manifest", "events"])]
+    pub dry_run: bool,
+
+    /// If given, files that already exist are left alone and the corresponding resources are not
+    /// extracted.
+    #[arg(long, conflicts_with_all = ["overwrite", "rename_duplicates", "prompt"])]
+    pub skip_existing: bool,
+
+    /// If given, existing files are overwritten. This is the default.
+    #[arg(long, conflicts_with_all = ["rename_duplicates", "prompt"])]
+    pub overwrite: bool,
+
+    /// If given, a resource that would overwrite an existing file or a file extracted earlier is
+    /// extracted under a new name instead, with `~1`, `~2` and so on appended to its name.
+    #[arg(long, conflicts_with = "prompt")]
+    pub rename_duplicates: bool,
+
+    /// If given, asks on the terminal what to do with each file that already exists: overwrite it,
+    /// skip it or extract the resource under a new name as with `--rename-duplicates`, either once
+    /// or for all further files.
+    #[arg(long)]
+    pub prompt: bool,
+
+    /// The directory into which to extract the REZ file contents. Unless `--clean` is given, the
+    /// extracted files are merged into the existing contents of the directory: files with the same
+    /// name are handled according to `--skip-existing`, `--overwrite`, `--rename-duplicates` and
+    /// `--prompt`, and all other files are left alone.
+    pub output_directory: PathBuf,
+}
+impl ExtractOpts {
+    pub fn existing_file_policy(&self) -> ExistingFilePolicy {
+        if self.skip_existing {
+            ExistingFilePolicy::Skip
+        } else if self.rename_duplicates {
+            ExistingFilePolicy::Rename
+        } else if self.prompt {
+            ExistingFilePolicy::Prompt
+        } else {
+            ExistingFilePolicy::Overwrite
+        }
+    }
+}
+
+#[derive(Parser)]
+struct VerifyOpts {
+    #[command(flatten)]
+    pub input: RezInputOpts,
+
+    /// The format in which to output the problems found. With `json`, an array of objects with the
+    /// keys `severity` (`error` or `warning`), `kind`, `path` and `detail` is output.
+    #[arg(long, value_enum, default_value_t)]
+    pub report: ReportFormatOpt,
+
+    /// Only check that all directory blocks and the data of all files lie within the REZ file.
+    /// Much faster than a full verification since names are not decoded, which makes it suitable
+    /// for screening many REZ files.
+    #[arg(long)]
+    pub offsets_only: bool,
+}
+
+#[derive(Parser)]
+struct FindOpts {
+    #[command(flatten)]
+    pub input: RezInputOpts,
+
+    #[command(flatten)]
+    pub filter: FilterOpts,
+
+    /// Only output the number of matching files instead of their paths.
+    #[arg(short, long)]
+    pub count: bool,
+
+    /// Which kinds of entries to find. Directories are found if they contain at least one
+    /// selected resource.
+    #[arg(long = "type", value_enum, default_value_t = EntryTypeOpt::Resource)]
+    pub entry_type: EntryTypeOpt,
+
+    #[command(flatten)]
+    pub ext_order: ExtOrderOpts,
+
+    #[command(flatten)]
+    pub text_style: TextStyleOpts,
+}
+
+#[derive(Parser)]
+struct HexdumpOpts {
+    #[command(flatten)]
+    pub input: RezInputOpts,
+
+    /// The maximum number of bytes to dump.
+    #[arg(short = 'n', long)]
+    pub length: Option<u64>,
+
+    /// The path of the file to dump within the REZ file.
+    pub path: String,
+}
+
+#[derive(Parser)]
+struct CatOpts {
+    #[command(flatten)]
+    pub input: RezInputOpts,
+
+    /// The path of the file to output within the REZ file.
+    pub path: String,
+}
+
+#[derive(Parser)]
+struct HasOpts {
+    #[command(flatten)]
+    pub input: RezInputOpts,
+
+    /// The path of the file within the REZ file.
+    pub path: String,
+}
+#[derive(Parser)]
+struct IdentifyOpts {
+    /// Also output the number of files in each REZ file. This requires reading the whole
+    /// directory tree.
+    #[arg(short, long)]
+    pub count: bool,
+
+    /// The files to identify.
+    #[arg(required = true)]
+    pub files: Vec<PathBuf>,
+}
+
+#[derive(Parser)]
+struct WhatisOpts {
+    #[command(flatten)]
+    pub input: RezInputOpts,
+
+    /// The byte offset from the start of the given file, in decimal or in hexadecimal with a `0x`
+    /// prefix. With `--base-offset`, this includes the position of the REZ file.
+    #[arg(value_parser = parse_offset)]
+    pub offset: u64,
+}
+
+#[derive(Parser)]
+struct MountOpts {
+    #[command(flatten)]
+    pub input: RezInputOpts,
+
+    /// The directory on which to mount the REZ file.
+    pub mountpoint: PathBuf,
+}
+
+#[derive(Parser)]
+struct ServeOpts {
+    #[command(flatten)]
+    pub input: RezInputOpts,
+
+    /// The address and port on which to listen. Port 0 chooses a free port.
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    pub address: String,
+}
+
+#[derive(Parser)]
+struct ToZipOpts {
+    #[command(flatten)]
+    pub input: RezInputOpts,
+
+    #[command(flatten)]
+    pub filter: FilterOpts,
+
+    /// Store the files in the ZIP archive without compressing them.
+    #[arg(long)]
+    pub store: bool,
+
+    /// What to do with a file whose data extends past the end of the REZ file: leave it out, store
+    /// only the data that is available, or stop with an error before writing anything.
+    #[arg(long, value_enum, default_value_t)]
+    pub on_short_read: ShortReadOpt,
+
+    /// The ZIP archive to create.
+    pub zip_file: PathBuf,
+}
+
+#[derive(Parser)]
+struct ToTarOpts {
+    #[command(flatten)]
+    pub input: RezInputOpts,
+
+    #[command(flatten)]
+    pub filter: FilterOpts,
+
+    /// What to do with a file whose data extends past the end of the REZ file: leave it out, store
+    /// only the data that is available, or stop with an error before writing anything.
+    #[arg(long, value_enum, default_value_t)]
+    pub on_short_read: ShortReadOpt,
+
+    /// The tar file to create, or `-` to write the stream to standard output.
+    #[arg(default_value = "-")]
+    pub tar_file: PathBuf,
+}
+
+#[derive(Parser)]
+struct PositionsOpts {
+    /// Write the table as JSON instead of the compact binary format. The binary format is a u32
+    /// record count followed by records of u32 offset, u32 length and NUL-terminated path, all
+    /// little-endian.
+    #[arg(long)]
+    pub json: bool,
+
+    #[command(flatten)]
+    pub input: RezInputOpts,
+
+    /// The file into which to write the table.
+    pub positions_file: PathBuf,
+}
+
+#[derive(Parser)]
+struct InfoOpts {
+    /// Also output how many files have each extension, sorted by extension.
+    #[arg(long)]
+    pub with_extensions: bool,
+
+    /// Only read the header, skipping the directory tree and the information derived from it.
+    /// Useful with damaged REZ files.
+    #[arg(long, conflicts_with = "with_extensions")]
+    pub header_only: bool,
+
+    #[command(flatten)]
+    pub input: RezInputOpts,
+}
+
+#[derive(Parser)]
+struct DuOpts {
+    #[command(flatten)]
+    pub input: RezInputOpts,
+}
+
+#[derive(Parser)]
+struct AddOpts {
+    /// The directory within the REZ file into which to add the files. Created if it does not exist
+    /// yet.
+    #[arg(long, default_value = "")]
+    pub directory: String,
+
+    /// The REZ file to modify.
+    pub rez_file: PathBuf,
+
+    /// The files and directories to add. Directories are added along with their contents.
+    #[arg(required = true)]
+    pub files: Vec<PathBuf>,
+}
+
+#[derive(Parser)]
+struct RmOpts {
+    /// Also move the data of the remaining files together, reclaiming the space of the removed
+    /// files and of previous directory blocks. Otherwise, only the directory is rewritten.
+    #[arg(long)]
+    pub compact: bool,
+
+    /// The REZ file to modify.
+    pub rez_file: PathBuf,
+
+    /// The paths of the files and directories to remove. Directories are removed along with their
+    /// contents.
+    #[arg(required = true)]
+    pub paths: Vec<String>,
+}
+
+#[derive(Parser)]
+struct MvOpts {
+    /// The REZ file to modify.
+    pub rez_file: PathBuf,
+
+    /// The path of the file or directory to rename or move.
+    pub from: String,
+
+    /// The new path of the file or directory, or the path of an existing directory into which to
+    /// move it.
+    pub to: String,
+}
+
+#[derive(Parser)]
+struct DiffOpts {
+    /// Also compare the contents of files of the same size. Otherwise, files are only compared by
+    /// size.
+    #[arg(long)]
+    pub content: bool,
+
+    /// The position of the old REZ file within the given file, in decimal or in hexadecimal with a
+    /// `0x` prefix.
+    #[arg(long, default_value_t = 0, value_parser = parse_offset)]
+    pub old_base_offset: u64,
+
+    /// The position of the new REZ file within the given file, in decimal or in hexadecimal with a
+    /// `0x` prefix.
+    #[arg(long, default_value_t = 0, value_parser = parse_offset)]
+    pub new_base_offset: u64,
+
+    /// The old REZ file.
+    pub old_rez_file: PathBuf,
+
+    /// The new REZ file.
+    pub new_rez_file: PathBuf,
+}
+
+#[derive(Parser)]
+struct MergeOpts {
+    /// Which file to keep if multiple REZ files contain a file at the same path.
+    #[arg(long, value_enum, default_value_t)]
+    pub on_conflict: merge::ConflictPolicy,
+
+    /// The REZ file to create. Its header strings are taken from the first input REZ file.
+    #[arg(short, long)]
+    pub output: PathBuf,
+
+    /// The positions of the REZ files within the given files, in decimal or in hexadecimal with a
+    /// `0x` prefix and separated by commas. If given, one position must be given for each REZ
+    /// file, in the same order; otherwise, all REZ files start at the beginning of their files.
+    #[arg(long = "base-offset", value_name = "BASE_OFFSETS", value_delimiter = ',', value_parser = parse_offset)]
+    pub base_offsets: Vec<u64>,
+
+    /// The REZ files to combine, in the order in which they would be loaded.
+    #[arg(required = true)]
+    pub rez_files: Vec<PathBuf>,
+}
+
+#[derive(Parser)]
+struct PackOpts {
+    #[command(flatten)]
+    pub new_rez: NewRezOpts,
+
+    /// The directory whose contents to pack.
+    pub input_directory: PathBuf,
+
+    /// The REZ file to create.
+    pub rez_file: PathBuf,
+}
+
+#[derive(Parser)]
+struct FromZipOpts {
+    #[command(flatten)]
+    pub new_rez: NewRezOpts,
+
+    /// The ZIP archive whose contents to pack.
+    pub zip_file: PathBuf,
+
+    /// The REZ file to create.
+    pub rez_file: PathBuf,
+}
+
+#[derive(Args)]
+struct NewRezOpts {
+    /// The file type string to write into the header.
+    #[arg(long, default_value = rez::DEFAULT_FILE_TYPE)]
+    pub file_type: String,
+
+    /// The user title string to write into the header.
+    #[arg(long, default_value = "")]
+    pub user_title: String,
+
+    /// Sort the entries of each directory by name and mark the REZ file as sorted.
+    #[arg(long)]
+    pub sorted: bool,
+
+    /// How to assign IDs to the packed files.
+    #[arg(long, value_enum, default_value_t)]
+    pub id_strategy: pack::IdStrategy,
+
+    /// The manifest from which to take the IDs with `--id-strategy from-manifest`, as written by
+    /// `extract --write-manifest`.
+    #[arg(long, required_if_eq("id_strategy", "from-manifest"))]
+    pub manifest: Option<PathBuf>,
+}
+
+/// The REZ file to read and where it is located within the given file.
+#[derive(Args)]
+struct RezInputOpts {
+    /// The position of the REZ file within the given file, in decimal or in hexadecimal with a `0x`
+    /// prefix. Allows reading REZ files embedded in or appended to other files.
+    #[arg(long, default_value_t = 0, value_parser = parse_offset)]
+    pub base_offset: u64,
+
+    /// The file containing the REZ file.
+    pub rez_file: PathBuf,
+}
+
+/// How the extensions of the resources in the REZ file are decoded.
+#[derive(Args)]
+struct ExtOrderOpts {
+    /// The order in which the extension bytes are stored in the REZ file. Standard REZ files store
+    /// them reversed; some variants store them in reading order.
+    #[arg(long, value_enum, default_value_t)]
+    pub ext_order: ExtOrderOpt,
+}
+impl ExtOrderOpts {
+    pub fn to_extension_order(&self) -> rez::ExtensionOrder {
+        self.ext_order.to_extension_order()
+    }
+}
+
+#[derive(Args)]
+struct TextStyleOpts {
+    /// The separator to place between path components in the output. `auto` uses the native
+    /// separator of the platform.
+    #[arg(long, value_enum, default_value_t)]
+    pub path_sep: PathSeparatorOpt,
+
+    /// The line ending to output. `auto` uses the native line ending of the platform.
+    #[arg(long, value_enum, default_value_t)]
+    pub line_ending: LineEndingOpt,
+}
+impl TextStyleOpts {
+    pub fn path_separator(&self) -> char {
+        match self.path_sep {
+            PathSeparatorOpt::Auto => std::path::MAIN_SEPARATOR,
+            PathSeparatorOpt::Slash => '/',
+            PathSeparatorOpt::Backslash => '\\',
+        }
+    }
+
+    pub fn line_ending(&self) -> &'static str {
+        match self.line_ending {
+            LineEndingOpt::Auto => if cfg!(windows) { "\r\n" } else { "\n" },
+            LineEndingOpt::Lf => "\n",
+            LineEndingOpt::Crlf => "\r\n",
+        }
+    }
+
+    /// Converts a path with forward slashes as separators to one with the chosen separator.
+    pub fn convert_path<'a>(&self, path: &'a str) -> Cow<'a, str> {
+        let separator = self.path_separator();
+        if separator == '/' {
+            Cow::Borrowed(path)
+        } else {
+            Cow::Owned(path.replace('/', separator.encode_utf8(&mut [0u8; 4])))
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd, ValueEnum)]
+enum DecompressOpt {
+    #[default] None,
+    Zlib,
+}
+impl DecompressOpt {
+    pub fn to_decompressor(self) -> Result<Option<Box<dyn rez::Decompressor>>, Failure> {
+        match self {
+            Self::None => Ok(None),
+            #[cfg(feature = "zlib")]
+            Self::Zlib => Ok(Some(Box::new(rez::ZlibDecompressor))),
+            #[cfg(not(feature = "zlib"))]
+            Self::Zlib => Err(Failure::new(
+                FailureKind::Usage,
+                "this build of lithrez does not support zlib decompression; build it with the \"zlib\" feature",
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd, ValueEnum)]
+enum ExtOrderOpt {
+    #[default] Reversed,
+    Normal,
+}
+impl ExtOrderOpt {
+    pub fn to_extension_order(self) -> rez::ExtensionOrder {
+        match self {
+            Self::Reversed => rez::ExtensionOrder::Reversed,
+            Self::Normal => rez::ExtensionOrder::Normal,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, ValueEnum)]
+enum EventsFormatOpt {
+    Ndjson,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd, ValueEnum)]
+enum ReportFormatOpt {
+    #[default] Text,
+    Json,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd, ValueEnum)]
+enum PathSeparatorOpt {
+    #[default] Auto,
+    Slash,
+    Backslash,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd, ValueEnum)]
+enum LineEndingOpt {
+    #[default] Auto,
+    Lf,
+    Crlf,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, ValueEnum)]
+enum LinkKindOpt {
+    Hard,
+    Symbolic,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd, ValueEnum)]
+enum ShortReadOpt {
+    Skip,
+    Truncate,
+    #[default] Error,
+}
+
+/// What to do when a resource would be extracted into a file that already exists.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+enum ExistingFilePolicy {
+    #[default] Overwrite,
+    Skip,
+    Rename,
+    Prompt,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd, ValueEnum)]
+enum FlattenCollisionsOpt {
+    #[default] SuffixId,
+    Existing,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd, ValueEnum)]
+enum EntryTypeOpt {
+    Resource,
+    Directory,
+    #[default] All,
+}
+impl EntryTypeOpt {
+    pub fn includes(self, entry: &rez::Entry) -> bool {
+        matches!(
+            (self, entry),
+            (Self::All, _)
+                | (Self::Resource, rez::Entry::Resource(_))
+                | (Self::Directory, rez::Entry::Directory(_))
+        )
+    }
+}
+
+
+/// The outcome of extracting a single resource.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+enum ExtractOutcome {
+    Extracted,
+    /// The resource was skipped because of `--on-short-read skip`.
+    Skipped,
+    VerificationFailed,
+    WriteFailed,
+}
+
+/// State accumulated during extraction.
+#[derive(Debug, Default)]
+struct ExtractState {
+    /// Files whose contents on disk did not match the REZ file after extraction.
+    pub verification_failures: Vec<PathBuf>,
+
+    /// Files that could not be written, even after retrying.
+    pub write_failures: Vec<PathBuf>,
+
+    /// The paths within the REZ file and IDs of the extracted resources.
+    pub extracted_ids: Vec<(String, u32)>,
+
+    /// The paths of the files into which the resources have been extracted.
+    pub extracted_files: Vec<PathBuf>,
+
+    /// The location of the REZ file within the underlying file.
+    pub rez_extent: RezExtent,
+
+    /// Where to report the progress of the extraction, if anywhere.
+    pub events: Option<EventWriter>,
+
+    /// The progress bar, if the extraction is in progress.
+    pub progress: Option<ExtractProgress>,
+
+    /// What to do with files that already exist. Answering a prompt for all further files replaces
+    /// [`ExistingFilePolicy::Prompt`] with the chosen policy.
+    pub existing_file_policy: ExistingFilePolicy,
+
+    /// The paths of the files into which resources are being extracted, which count as existing
+    /// even if they have not been written yet.
+    pub claimed_paths: HashSet<PathBuf>,
+
+    /// The lowercased names of the files that multiple resources would be extracted into when
+    /// flattening, which are suffixed with the IDs of the resources.
+    pub flatten_collisions: HashSet<String>,
+}
+
+/// The location of a REZ file within the underlying file.
+#[derive(Clone, Copy, Debug, Default)]
+struct RezExtent {
+    /// The position at which the REZ file starts; see [`rez::File::base_offset`].
+    pub base_offset: u64,
+
+    /// The length of the REZ file, against which the extents of the resources are checked.
+    pub size: u64,
+}
+
+
+fn output_rez_entries_recursive(entries: &[rez::Entry], entry_base_path: &str, indent: usize, filter: &ResourceFilter, entry_type: EntryTypeOpt, text_style: &TextStyleOpts) {
+    for entry in entries {
+        let entry_path = rez::join_path(entry_base_path, &entry.name());
+
+        match entry {
+            rez::Entry::Directory(dir) => {
+                if !filter.is_unrestricted() && !has_matching_resource(&dir.entries, &entry_path, filter) {
+                    continue;
+                }
+                if entry_type == EntryTypeOpt::Resource {
+                    // flat list; no directory lines and no indentation
+                    output_rez_entries_recursive(&dir.entries, &entry_path, indent, filter, entry_type, text_style);
+                    continue;
+                }
+                output_directory_line(dir, indent, text_style);
+                output_rez_entries_recursive(&dir.entries, &entry_path, indent + 1, filter, entry_type, text_style);
+            },
+            rez::Entry::Resource(res) => {
+                if !entry_type.includes(entry) || !filter.matches(&entry_path, res) {
+                    continue;
+                }
+                output_resource_line(res, &res.extension, &entry_path, indent, entry_type, text_style);
+            },
+        }
+    }
+}
+
+/// Returns the paths of the entries of the given kind that are selected by the filter, in the order
+/// of [`rez::File::walk`]. Directories are selected if they contain at least one selected resource.
+fn matching_entry_paths(rez_file: &rez::File, entry_type: EntryTypeOpt, filter: &ResourceFilter) -> Vec<String> {
+    rez_file.walk()
+        .filter(|(entry_path, entry)| entry_type.includes(entry) && match entry {
+            rez::Entry::Directory(dir) => filter.is_unrestricted() || has_matching_resource(&dir.entries, entry_path, filter),
+            rez::Entry::Resource(res) => filter.matches(entry_path, res),
+        })
+        .map(|(entry_path, _entry)| entry_path)
+        .collect()
+}
+
+/// Outputs the entries of a REZ file as they are being read, without keeping the directory tree in
+/// memory. The output is the same as that of [`output_rez_entries_recursive`] with an unrestricted
+/// filter.
+fn output_rez_entries_streaming<R: Read + Seek>(reader: &mut R, entry_type: EntryTypeOpt, ext_order: rez::ExtensionOrder, text_style: &TextStyleOpts) -> Result<rez::File, rez::Error> {
+    rez::File::try_read_streaming(reader, |dir_path, entry| {
+        match entry {
+            rez::Entry::Directory(dir) => {
+                if entry_type != EntryTypeOpt::Resource {
+                    output_directory_line(dir, dir_path.len(), text_style);
+                }
+            },
+            rez::Entry::Resource(res) => {
+                if !entry_type.includes(entry) {
+                    return;
+                }
+                let mut entry_path = dir_path.join("/");
+                if !entry_path.is_empty() {
+                    entry_path.push('/');
+                }
+                let extension = ext_order.decode(&res.extension_bytes);
+                entry_path.push_str(&format!("{}.{}", res.name, extension));
+                let indent = if entry_type == EntryTypeOpt::Resource { 0 } else { dir_path.len() };
+                output_resource_line(res, &extension, &entry_path, indent, entry_type, text_style);
+            },
+        }
+    })
+}
+
+fn output_directory_line(dir: &rez::Directory, indent: usize, text_style: &TextStyleOpts) {
+    output_indent(indent);
+    print!("{} ({}){}{}", dir.name, dir.header.time, text_style.path_separator(), text_style.line_ending());
+}
+
+fn output_resource_line(res: &rez::Resource, extension: &str, entry_path: &str, indent: usize, entry_type: EntryTypeOpt, text_style: &TextStyleOpts) {
+    output_indent(indent);
+    if entry_type == EntryTypeOpt::Resource {
+        print!("{} [{}", text_style.convert_path(entry_path), res.id);
+    } else {
+        print!("{}.{} [{}", res.name, extension, res.id);
+    }
+    if !res.description.is_empty() {
+        print!(", {}", res.description);
+    }
+    print!("] ({}, {}+{} bytes){}", res.header.time, res.header.position, res.header.size, text_style.line_ending());
+}
+
+fn output_indent(indent: usize) {
+    for _ in 0..indent {
+        print!("  ");
+    }
+}
+
+/// Returns whether any resource within the given entries or their subdirectories is selected by
+/// the filter.
+fn has_matching_resource(entries: &[rez::Entry], entry_base_path: &str, filter: &ResourceFilter) -> bool {
+    rez::walk_entries(entries, entry_base_path)
+        .any(|(entry_path, entry)| entry.as_resource().is_some_and(|res| filter.matches(&entry_path, res)))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn extract_rez_entries_recursive<R, F>(rez_file: &mut R, open_reader: &F, entries: &[rez::Entry], entry_base_path: &str, extract_base_path: &Path, filter: &ResourceFilter, opts: &ExtractOpts, state: &mut ExtractState) -> Result<(), Failure>
+    where
+        R: Read + Seek,
+        F: Fn() -> std::io::Result<R> + Sync,
+{
+    if opts.dir_jobs > 1 {
+        // extract all the files in this directory before descending
+        let mut resources = Vec::new();
+        for entry in entries {
+            if let rez::Entry::Resource(res) = entry {
+                let entry_path = rez::join_path(entry_base_path, &entry.name());
+                if filter.matches(&entry_path, res) {
+                    resources.push((entry_path, res, extract_base_path.to_owned()));
+                }
+            }
+        }
+        extract_resources_parallel(&resources, open_reader, opts.dir_jobs, opts, state)?;
+    }
+
+    for entry in entries {
+        let entry_path = rez::join_path(entry_base_path, &entry.name());
+
+        match entry {
+            rez::Entry::Directory(dir) => {
+                // descend
+                let (extract_name, entry_path, dir) = extract_dir_name(dir, entry_path, opts);
+                let extract_sub_path = if opts.flatten {
+                    extract_base_path.to_owned()
+                } else {
+                    extract_base_path.join(&extract_name)
+                };
+
+                extract_rez_entries_recursive(rez_file, open_reader, &dir.entries, &entry_path, &extract_sub_path, filter, opts, state)?;
+            },
+            rez::Entry::Resource(res) => {
+                if opts.dir_jobs > 1 {
+                    // already extracted above
+                    continue;
+                }
+
+                if !filter.matches(&entry_path, res) {
+                    // skip
+                    continue;
+                }
+
+                let Some(extract_file_path) = resolve_extract_path(resource_extract_path(res, extract_base_path, opts, &state.flatten_collisions), state) else {
+                    report_skipped_existing(state.events.as_ref(), state.progress.as_ref(), &entry_path, res);
+                    continue;
+                };
+                output_extraction_message(&entry_path, res, &extract_file_path, opts);
+                state.extracted_ids.push((entry_path.clone(), res.id));
+                state.extracted_files.push(extract_file_path.clone());
+                let outcome = extract_resource(rez_file, state.rez_extent, &entry_path, res, extract_base_path, &extract_file_path, opts)?;
+                report_extract_outcome(state.events.as_ref(), state.progress.as_ref(), &entry_path, res, outcome);
+                match outcome {
+                    ExtractOutcome::Extracted | ExtractOutcome::Skipped => {},
+                    ExtractOutcome::VerificationFailed => state.verification_failures.push(extract_file_path),
+                    ExtractOutcome::WriteFailed => state.write_failures.push(extract_file_path),
+                }
+            },
+        }
+    }
+    Ok(())
+}
+
+/// Collects the resources in the given entries and all their subdirectories that are selected by
+/// the filter, along with their paths and the directories into which to extract them.
+fn collect_selected_resources_recursive<'a>(entries: &'a [rez::Entry], entry_base_path: &str, extract_base_path: &Path, filter: &ResourceFilter, opts: &ExtractOpts, resources: &mut Vec<(String, &'a rez::Resource, PathBuf)>) {
+    for entry in entries {
+        let entry_path = rez::join_path(entry_base_path, &entry.name());
+        match entry {
+            rez::Entry::Directory(dir) => {
+                let (extract_name, entry_path, dir) = extract_dir_name(dir, entry_path, opts);
+                let extract_sub_path = if opts.flatten {
+                    extract_base_path.to_owned()
+                } else {
+                    extract_base_path.join(&extract_name)
+                };
+                collect_selected_resources_recursive(&dir.entries, &entry_path, &extract_sub_path, filter, opts, resources);
+            },
+            rez::Entry::Resource(res) => {
+                if filter.matches(&entry_path, res) {
+                    resources.push((entry_path, res, extract_base_path.to_owned()));
+                }
+            },
+        }
+    }
+}
+
+/// Extracts the given resources using the given number of threads, each with its own reader
+/// obtained from `open_reader`. Each resource is accompanied by its path and the directory into which to extract
+/// it.
+///
+/// Progress is output in the order of the resources before extraction starts. If a failure ends
+/// the extraction, the threads stop taking on further resources and the first failure is returned.
+fn extract_resources_parallel<R, F>(resources: &[(String, &rez::Resource, PathBuf)], open_reader: &F, jobs: usize, opts: &ExtractOpts, state: &mut ExtractState) -> Result<(), Failure>
+    where
+        R: Read + Seek,
+        F: Fn() -> std::io::Result<R> + Sync,
+{
+    // decide on the output files up front, which also keeps any prompts in order
+    let mut extract_file_paths = Vec::with_capacity(resources.len());
+    for (entry_path, res, extract_base_path) in resources {
+        let extract_file_path = resolve_extract_path(resource_extract_path(res, extract_base_path, opts, &state.flatten_collisions), state);
+        match &extract_file_path {
+            Some(path) => {
+                output_extraction_message(entry_path, res, path, opts);
+                state.extracted_ids.push((entry_path.clone(), res.id));
+                state.extracted_files.push(path.clone());
+            },
+            None => report_skipped_existing(state.events.as_ref(), state.progress.as_ref(), entry_path, res),
+        }
+        extract_file_paths.push(extract_file_path);
+    }
+
+    let rez_extent = state.rez_extent;
+    let events = state.events.as_ref();
+    let progress = state.progress.as_ref();
+    let next_index = AtomicUsize::new(0);
+    let failed_indexes = Mutex::new(Vec::new());
+    let failure = Mutex::new(None);
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.min(resources.len()) {
+            scope.spawn(|| {
+                let mut rez_file = match open_reader() {
+                    Ok(r) => r,
+                    Err(e) => {
+                        failure.lock().unwrap().get_or_insert(Failure::io("open", &opts.input.rez_file, e));
+                        return;
+                    },
+                };
+                loop {
+                    if failure.lock().unwrap().is_some() {
+                        break;
+                    }
+                    let index = next_index.fetch_add(1, Ordering::SeqCst);
+                    let Some((entry_path, res, extract_base_path)) = resources.get(index) else { break };
+                    let Some(extract_file_path) = &extract_file_paths[index] else { continue };
+                    let outcome = match extract_resource(&mut rez_file, rez_extent, entry_path, res, extract_base_path, extract_file_path, opts) {
+                        Ok(o) => o,
+                        Err(f) => {
+                            failure.lock().unwrap().get_or_insert(f);
+                            break;
+                        },
+                    };
+                    report_extract_outcome(events, progress, entry_path, res, outcome);
+                    if matches!(outcome, ExtractOutcome::VerificationFailed | ExtractOutcome::WriteFailed) {
+                        failed_indexes.lock().unwrap().push((index, outcome));
+                    }
+                }
+            });
+        }
+    });
+
+    if let Some(failure) = failure.into_inner().unwrap() {
+        return Err(failure);
+    }
+
+    let mut failed_indexes = failed_indexes.into_inner().unwrap();
+    failed_indexes.sort_unstable();
+    for (index, outcome) in failed_indexes {
+        let extract_file_path = extract_file_paths[index].clone().unwrap();
+        if outcome == ExtractOutcome::WriteFailed {
+            state.write_failures.push(extract_file_path);
+        } else {
+            state.verification_failures.push(extract_file_path);
+        }
+    }
+    Ok(())
+}
+
+/// Outputs the files into which the given resources would be extracted, including sidecar files,
+/// and warns about files that would be overwritten.
+///
+/// Existing files are handled according to `--skip-existing` and `--rename-duplicates`; with
+/// `--prompt`, they are reported as if they were to be overwritten.
+fn output_dry_run(resources: &[(String, &rez::Resource, PathBuf)], flatten_collisions: HashSet<String>, opts: &ExtractOpts) {
+    let mut state = ExtractState {
+        existing_file_policy: match opts.existing_file_policy() {
+            ExistingFilePolicy::Prompt => ExistingFilePolicy::Overwrite,
+            other => other,
+        },
+        flatten_collisions,
+        ..ExtractState::default()
+    };
+    // lowercased output path -> (output path, path within REZ file)
+    let mut planned: HashMap<String, (PathBuf, String)> = HashMap::new();
+    let mut extracted_count: usize = 0;
+    for (entry_path, res, extract_base_path) in resources {
+        let Some(extract_file_path) = resolve_extract_path(resource_extract_path(res, extract_base_path, opts, &state.flatten_collisions), &mut state) else {
+            continue;
+        };
+        extracted_count += 1;
+        let mut outputs = vec![(extract_file_path.clone(), entry_path.clone())];
+        if opts.descriptions && !res.description.is_empty() {
+            let mut description_file_path = extract_file_path.clone();
+            description_file_path.as_mut_os_string().push(DESCRIPTION_SIDECAR_EXTENSION);
+            outputs.push((description_file_path, format!("{} (description)", entry_path)));
+        }
+        if opts.convert_dtx && convert::is_dtx(res) {
+            outputs.push((extract_file_path.with_extension("png"), format!("{} (PNG)", entry_path)));
+        }
+
+        for (output_path, source) in outputs {
+            println!("{} -> {}", source, output_path.display());
+            let key = output_path.to_string_lossy().to_lowercase();
+            if let Some((earlier_path, earlier_source)) = planned.get(&key) {
+                if *earlier_path == output_path {
+                    warn!("{} would overwrite {} in {}", source, earlier_source, output_path.display());
+                } else {
+                    warn!(
+                        "{} and {} would be written to {} and {}, which are the same file on case-insensitive file systems",
+                        earlier_source, source, earlier_path.display(), output_path.display(),
+                    );
+                }
+            } else if output_path.exists() {
+                warn!("{} would overwrite the existing file {}", source, output_path.display());
+            }
+            planned.insert(key, (output_path, source));
+        }
+    }
+    info!("{} file(s) would be extracted", extracted_count);
+}
+
+/// Outputs the event corresponding to the outcome of extracting a resource, if events are requested,
+/// and advances the progress bar.
+fn report_extract_outcome(events: Option<&EventWriter>, progress: Option<&ExtractProgress>, entry_path: &str, res: &rez::Resource, outcome: ExtractOutcome) {
+    if let Some(progress) = progress {
+        progress.file_done(res.header.size.into());
+    }
+    let Some(events) = events else { return };
+    match outcome {
+        ExtractOutcome::Extracted => events.file(entry_path, res.header.size.into()),
+        ExtractOutcome::Skipped => events.skip(entry_path, "the REZ file ends before the end of the data"),
+        ExtractOutcome::VerificationFailed => events.skip(entry_path, "verification failed"),
+        ExtractOutcome::WriteFailed => events.skip(entry_path, "failed to write"),
+    }
+}
+
+/// Outputs the event for a resource that is not extracted since its file already exists, if events
+/// are requested, and advances the progress bar.
+fn report_skipped_existing(events: Option<&EventWriter>, progress: Option<&ExtractProgress>, entry_path: &str, res: &rez::Resource) {
+    if let Some(progress) = progress {
+        progress.file_done(res.header.size.into());
+    }
+    if let Some(events) = events {
+        events.skip(entry_path, "the file already exists");
+    }
+}
+
+/// Counts the resources in the given entries and all their subdirectories that are selected by the
+/// filter, returning their number and total size.
+fn count_selected_resources(entries: &[rez::Entry], entry_base_path: &str, filter: &ResourceFilter) -> (u64, u64) {
+    rez::walk_entries(entries, entry_base_path)
+        .filter_map(|(entry_path, entry)| entry.as_resource().filter(|res| filter.matches(&entry_path, res)))
+        .fold((0, 0), |(count, size), res| (count + 1, size + u64::from(res.header.size)))
+}
+
+/// Fails if the file system of the output directory has less space available than the given
+/// number of bytes, unless forced.
+///
+/// The sizes of the resources as stored in the REZ file are used; decompressed or transcoded files
+/// may take up more space.
+fn check_free_space(output_directory: &Path, required: u64, force: bool) -> Result<(), Failure> {
+    // the output directory might not exist yet
+    let Some(existing) = output_directory.ancestors()
+        .map(|p| if p.as_os_str().is_empty() { Path::new(".") } else { p })
+        .find(|p| p.is_dir())
+        else { return Ok(()) };
+    let Some(available) = available_space(existing) else {
+        warn!("cannot determine the free space available in {}", existing.display());
+        return Ok(());
+    };
+    if available >= required {
+        return Ok(());
+    }
+    if force {
+        warn!(
+            "extracting {} bytes although only {} bytes are available in {}",
+            required, available, existing.display(),
+        );
+        Ok(())
+    } else {
+        Err(Failure::new(
+            FailureKind::Unsuccessful,
+            format!(
+                "refusing to extract {} bytes since only {} bytes are available in {}; pass --force to try anyway",
+                required, available, existing.display(),
+            ),
+        ))
+    }
+}
+
+/// Returns the number of bytes available to unprivileged users on the file system containing the
+/// given path.
+#[cfg(unix)]
+fn available_space(path: &Path) -> Option<u64> {
+    let stats = rustix::fs::statvfs(path).ok()?;
+    Some(stats.f_bavail.saturating_mul(stats.f_frsize))
+}
+
+#[cfg(not(unix))]
+fn available_space(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Returns the name of the directory into which the given directory found at the given path within
+/// the REZ file is extracted, along with the path within the REZ file and the directory whose
+/// entries are extracted into it, taking `--collapse-single-dirs`, `--lowercase` and
+/// `--trust-names` into account.
+fn extract_dir_name<'d>(dir: &'d rez::Directory, entry_path: String, opts: &ExtractOpts) -> (String, String, &'d rez::Directory) {
+    let (extract_name, entry_path, dir) = if opts.collapse_single_dirs {
+        collapse_single_dir_chain(dir, entry_path)
+    } else {
+        (dir.name.clone(), entry_path, dir)
+    };
+    let extract_name = if opts.lowercase { extract_name.to_lowercase() } else { extract_name };
+    if opts.trust_names {
+        (extract_name, entry_path, dir)
+    } else {
+        (names::sanitize_name(&extract_name).into_owned(), entry_path, dir)
+    }
+}
+
+/// Returns the entries of the directory at the given path within the REZ file, which must exist,
+/// along with their path within the REZ file and the directory into which they are extracted. The
+/// directories along the path are named as in a full extraction, see [`extract_dir_name`].
+fn only_subtree<'r>(root_entries: &'r Vec<rez::Entry>, only_path: &str, output_directory: &Path, opts: &ExtractOpts) -> (&'r Vec<rez::Entry>, String, PathBuf) {
+    let mut entries = root_entries;
+    let mut entry_path = String::new();
+    let mut extract_path = output_directory.to_path_buf();
+    let mut components = only_path.split(['/', '\\']).filter(|c| !c.is_empty());
+    while let Some(component) = components.next() {
+        let dir = entries.iter()
+            .filter_map(|entry| entry.as_directory())
+            .find(|dir| dir.name == component)
+            .expect("the path has been looked up");
+        let dir_path = rez::join_path(&entry_path, &dir.name);
+        let (extract_name, collapsed_path, collapsed_dir) = extract_dir_name(dir, dir_path.clone(), opts);
+        // the path may continue through the collapsed chain of single subdirectories
+        let chain_length = collapsed_path.split('/').count() - dir_path.split('/').count();
+        for _ in 0..chain_length {
+            components.next();
+        }
+        if !opts.flatten {
+            extract_path.push(extract_name);
+        }
+        entries = &collapsed_dir.entries;
+        entry_path = collapsed_path;
+    }
+    (entries, entry_path, extract_path)
+}
+
+/// Follows a chain of directories that each contain nothing but a single subdirectory, starting
+/// at the given directory found at the given path within the REZ file. Returns the name under
+/// which the chain is extracted, the path of the last directory of the chain within the REZ file
+/// and the last directory itself.
+fn collapse_single_dir_chain(dir: &rez::Directory, entry_path: String) -> (String, String, &rez::Directory) {
+    let mut extract_name = dir.name.clone();
+    let mut entry_path = entry_path;
+    let mut dir = dir;
+    while let [rez::Entry::Directory(child)] = dir.entries.as_slice() {
+        extract_name.push('_');
+        extract_name.push_str(&child.name);
+        entry_path.push('/');
+        entry_path.push_str(&child.name);
+        dir = child;
+    }
+    (extract_name, entry_path, dir)
+}
+
+/// Returns the path of the file into which the resource is extracted. If the lowercased name of
+/// the file is among `flatten_collisions`, the ID of the resource is appended to its name.
+fn resource_extract_path(res: &rez::Resource, extract_base_path: &Path, opts: &ExtractOpts, flatten_collisions: &HashSet<String>) -> PathBuf {
+    let mut file_name = resource_file_name(res, opts, false);
+    if flatten_collisions.contains(&file_name.to_lowercase()) {
+        file_name = resource_file_name(res, opts, true);
+    }
+    extract_base_path.join(file_name)
+}
+
+/// Returns the name of the file into which the resource is extracted, optionally with the ID of the
+/// resource appended to its name.
+///
+/// If the name is longer than the maximum name length, the resource name is truncated and a hash
+/// of the full name is appended to keep the names unique.
+fn resource_file_name(res: &rez::Resource, opts: &ExtractOpts, with_id: bool) -> String {
+    let name = if with_id {
+        Cow::Owned(format!("{}~{}", res.name, res.id))
+    } else {
+        Cow::Borrowed(res.name.as_str())
+    };
+    let mut extension = Cow::Borrowed(output_extension(res, opts));
+    let mut name = name;
+    if opts.lowercase {
+        name = Cow::Owned(name.to_lowercase());
+        extension = Cow::Owned(extension.to_lowercase());
+    }
+    if !opts.trust_names {
+        name = Cow::Owned(names::sanitize_name(&name).into_owned());
+        extension = Cow::Owned(names::replace_forbidden_chars(&extension).into_owned());
+    }
+    let full_name = format!("{}.{}", name, extension);
+    let Some(max_name_length) = max_resource_name_length(opts) else { return full_name };
+    if full_name.len() <= max_name_length {
+        return full_name;
+    }
+
+    // validate_max_name_length guarantees that at least the hash fits; the extension is clamped
+    let hash = format!("~{:08x}", hashing::fnv1a_32(full_name.as_bytes()));
+    let extension = floor_char_boundary(&extension, max_name_length - hash.len() - 1);
+    let suffix = format!("{}.{}", hash, extension);
+    let prefix = floor_char_boundary(&name, max_name_length.saturating_sub(suffix.len()));
+    format!("{}{}", prefix, suffix)
+}
+
+/// Returns the longest prefix of the given string that is at most `max_length` bytes long and does
+/// not split a character.
+fn floor_char_boundary(s: &str, max_length: usize) -> &str {
+    let mut length = max_length.min(s.len());
+    while !s.is_char_boundary(length) {
+        length -= 1;
+    }
+    &s[..length]
+}
+
+/// Returns the maximum length of the name of an extracted resource, leaving room for the `.txt`
+/// extension of the description sidecar if descriptions are written.
+fn max_resource_name_length(opts: &ExtractOpts) -> Option<usize> {
+    let max_name_length = opts.max_name_length?;
+    if opts.descriptions {
+        Some(max_name_length.saturating_sub(DESCRIPTION_SIDECAR_EXTENSION.len()))
+    } else {
+        Some(max_name_length)
+    }
+}
+
+/// Ensures that `--max-name-length` leaves room for the hash that is appended to truncated names
+/// along with the separating dot, as well as for the extension of the description sidecar.
+fn validate_max_name_length(opts: &ExtractOpts) -> Result<(), Failure> {
+    const MIN_RESOURCE_NAME_LENGTH: usize = "~01234567.".len();
+    match max_resource_name_length(opts) {
+        Some(length) if length < MIN_RESOURCE_NAME_LENGTH => {
+            let sidecar_length = if opts.descriptions { DESCRIPTION_SIDECAR_EXTENSION.len() } else { 0 };
+            Err(Failure::new(
+                FailureKind::Usage,
+                format!(
+                    "--max-name-length must be at least {}{}",
+                    MIN_RESOURCE_NAME_LENGTH + sidecar_length,
+                    if opts.descriptions { " with --descriptions" } else { "" },
+                ),
+            ))
+        },
+        _ => Ok(()),
+    }
+}
+
+/// Warns about the names of the given resources and the directories containing them that will be
+/// rewritten since they are unsafe to use as file names.
+fn warn_about_unsafe_names(resources: &[(String, &rez::Resource, PathBuf)]) {
+    let mut warned_dirs = HashSet::new();
+    for (entry_path, res, _extract_base_path) in resources {
+        let dir_path = entry_path.rsplit_once('/').map(|(dir_path, _name)| dir_path).unwrap_or("");
+        for (index, component) in dir_path.split('/').enumerate().filter(|(_, c)| !c.is_empty()) {
+            let component_path = dir_path.split('/').take(index + 1).collect::<Vec<_>>().join("/");
+            if matches!(names::sanitize_name(component), Cow::Owned(_)) && warned_dirs.insert(component_path.clone()) {
+                warn!("rewriting the unsafe directory name {:?} in {:?}", component, component_path);
+            }
+        }
+        let name_is_unsafe = matches!(names::sanitize_name(&res.name), Cow::Owned(_))
+            || matches!(names::replace_forbidden_chars(&res.extension), Cow::Owned(_));
+        if name_is_unsafe {
+            warn!("rewriting the unsafe file name of {:?}", entry_path);
+        }
+    }
+}
+
+/// Returns the lowercased names of the files into which more than one of the given resources would
+/// be extracted when flattening.
+fn find_flatten_collisions(resources: &[(String, &rez::Resource, PathBuf)], opts: &ExtractOpts) -> HashSet<String> {
+    let mut seen = HashSet::new();
+    let mut collisions = HashSet::new();
+    for (_entry_path, res, _extract_base_path) in resources {
+        let file_name = resource_file_name(res, opts, false).to_lowercase();
+        if !seen.insert(file_name.clone()) {
+            collisions.insert(file_name);
+        }
+    }
+    collisions
+}
+
+/// Returns the extension of the file into which the resource is extracted, taking `--ext-map` into
+/// account.
+fn output_extension<'a>(res: &'a rez::Resource, opts: &'a ExtractOpts) -> &'a str {
+    opts.ext_map.iter()
+        .find(|(old, _new)| old.eq_ignore_ascii_case(&res.extension))
+        .map(|(_old, new)| new.as_str())
+        .unwrap_or(&res.extension)
+}
+
+/// Decides into which file to extract a resource that would be extracted into the given file,
+/// according to the policy for existing files. Returns `None` if the resource is to be skipped.
+///
+/// Files claimed by earlier resources count as existing; the returned file is claimed as well.
+fn resolve_extract_path(extract_file_path: PathBuf, state: &mut ExtractState) -> Option<PathBuf> {
+    let exists = |path: &Path, claimed_paths: &HashSet<PathBuf>| claimed_paths.contains(path) || path.exists();
+    let mut policy = state.existing_file_policy;
+    if exists(&extract_file_path, &state.claimed_paths) {
+        if policy == ExistingFilePolicy::Prompt {
+            let (answer, apply_to_all) = progress::suspend(|| prompt_existing_file(&extract_file_path));
+            if apply_to_all {
+                state.existing_file_policy = answer;
+            }
+            policy = answer;
+        }
+        match policy {
+            ExistingFilePolicy::Overwrite | ExistingFilePolicy::Prompt => {},
+            ExistingFilePolicy::Skip => {
+                info!("skipping {} since it already exists", extract_file_path.display());
+                return None;
+            },
+            ExistingFilePolicy::Rename => {
+                let renamed = (1..)
+                    .map(|n| numbered_path(&extract_file_path, n))
+                    .find(|p| !exists(p, &state.claimed_paths))
+                    .unwrap();
+                info!("{} already exists; extracting into {} instead", extract_file_path.display(), renamed.display());
+                state.claimed_paths.insert(renamed.clone());
+                return Some(renamed);
+            },
+        }
+    }
+    state.claimed_paths.insert(extract_file_path.clone());
+    Some(extract_file_path)
+}
+
+/// Asks on the terminal what to do with the given existing file, returning the chosen policy and
+/// whether it applies to all further files. Skips the file if no answer can be read.
+fn prompt_existing_file(path: &Path) -> (ExistingFilePolicy, bool) {
+    loop {
+        eprint!(
+            "{} already exists. [o]verwrite, [s]kip, [r]ename (uppercase: for all further files)? ",
+            path.display(),
+        );
+        let mut answer = String::new();
+        match std::io::stdin().read_line(&mut answer) {
+            Ok(0) | Err(_) => {
+                eprintln!();
+                warn!("no answer; skipping {}", path.display());
+                return (ExistingFilePolicy::Skip, false);
+            },
+            Ok(_) => {},
+        }
+        match answer.trim() {
+            "o" => return (ExistingFilePolicy::Overwrite, false),
+            "O" => return (ExistingFilePolicy::Overwrite, true),
+            "s" => return (ExistingFilePolicy::Skip, false),
+            "S" => return (ExistingFilePolicy::Skip, true),
+            "r" => return (ExistingFilePolicy::Rename, false),
+            "R" => return (ExistingFilePolicy::Rename, true),
+            _ => {},
+        }
+    }
+}
+
+/// Returns the given path with `~` and the given number appended to the file stem.
+fn numbered_path(path: &Path, number: u64) -> PathBuf {
+    let mut file_name = path.file_stem().unwrap_or_default().to_owned();
+    file_name.push(format!("~{}", number));
+    if let Some(extension) = path.extension() {
+        file_name.push(".");
+        file_name.push(extension);
+    }
+    path.with_file_name(file_name)
+}
+
+/// Outputs the progress message for the extraction of a resource.
+fn output_extraction_message(entry_path: &str, res: &rez::Resource, extract_file_path: &Path, opts: &ExtractOpts) {
+    let mut full_name = format!("{}.{}", res.name, output_extension(res, opts));
+    if opts.lowercase {
+        full_name = full_name.to_lowercase();
+    }
+    let file_name = resource_file_name(res, opts, false);
+    if file_name != full_name {
+        warn!("shortening name {:?} to {:?}", full_name, file_name);
+    }
+    info!("extracting {} as {}", entry_path, extract_file_path.display());
+}
+
+/// Extracts a single resource, found at the given path within the REZ file, into the given file
+/// within the given directory.
+///
+/// Problems with the output file are reported in the outcome; problems with the REZ file end the
+/// extraction and are returned as a failure.
+#[allow(clippy::too_many_arguments)]
+fn extract_resource<R: Read + Seek>(rez_file: &mut R, rez_extent: RezExtent, entry_path: &str, res: &rez::Resource, extract_base_path: &Path, extract_file_path: &Path, opts: &ExtractOpts) -> Result<ExtractOutcome, Failure> {
+    let extract_file_path = extract_file_path.to_owned();
+
+    // does the resource extend past the end of the REZ file?
+    let available = rez_extent.size.saturating_sub(res.header.position.into());
+    let truncated_res;
+    let res = if u64::from(res.header.size) <= available {
+        res
+    } else {
+        match opts.on_short_read {
+            ShortReadOpt::Skip => {
+                warn!(
+                    "skipping {}: the REZ file ends {} bytes before the end of its data",
+                    extract_file_path.display(), u64::from(res.header.size) - available,
+                );
+                return Ok(ExtractOutcome::Skipped);
+            },
+            ShortReadOpt::Truncate => {
+                warn!(
+                    "truncating {} to {} bytes: the REZ file ends {} bytes before the end of its data",
+                    extract_file_path.display(), available, u64::from(res.header.size) - available,
+                );
+                let mut r = res.clone();
+                r.header.size = available.try_into().unwrap();
+                truncated_res = r;
+                &truncated_res
+            },
+            ShortReadOpt::Error => {
+                return Err(Failure::new(
+                    FailureKind::InvalidInput,
+                    format!(
+                        "cannot extract {} stored at offset {} in {}: the REZ file ends {} bytes before the end of its data; consider using --on-short-read",
+                        entry_path, res.header.position, opts.input.rez_file.display(), u64::from(res.header.size) - available,
+                    ),
+                ));
+            },
+        }
+    };
+
+    // only errors writing the output are retried; problems with the REZ file are fatal
+    let mut attempt: u32 = 0;
+    let (written_length, written_hash) = loop {
+        match write_resource_file(rez_file, rez_extent.base_offset, entry_path, res, extract_base_path, &extract_file_path, opts)? {
+            Ok(written) => break written,
+            Err(e) if attempt < opts.retries => {
+                let delay = opts.retry_delay.saturating_mul(1 << attempt.min(16));
+                attempt += 1;
+                warn!(
+                    "failed to write {}: {}; retrying in {} ms (attempt {} of {})",
+                    extract_file_path.display(), e, delay, attempt, opts.retries,
+                );
+                std::thread::sleep(std::time::Duration::from_millis(delay));
+            },
+            Err(e) => {
+                error!("failed to write {}: {}", extract_file_path.display(), e);
+                let _ = std::fs::remove_file(&extract_file_path);
+                return Ok(ExtractOutcome::WriteFailed);
+            },
+        }
+    };
+
+    if !opts.no_preserve_times {
+        if let Err(e) = set_modified_time(&extract_file_path, res.header.time) {
+            warn!("failed to set the modification time of {}: {}", extract_file_path.display(), e);
+        }
+    }
+
+    let mut verified = true;
+    if opts.verify_after_extract {
+        let (on_disk_length, on_disk_hash) = hash_file(&extract_file_path)
+            .map_err(|e| Failure::io("read back", &extract_file_path, e))?;
+        if on_disk_length != written_length {
+            error!(
+                "verification failed: {} has {} bytes instead of {}",
+                extract_file_path.display(), on_disk_length, written_length,
+            );
+            verified = false;
+        } else if on_disk_hash != written_hash {
+            error!("verification failed: contents of {} differ", extract_file_path.display());
+            verified = false;
+        }
+    }
+
+    if opts.descriptions && !res.description.is_empty() {
+        let mut description_file_path = extract_file_path.clone();
+        description_file_path.as_mut_os_string().push(DESCRIPTION_SIDECAR_EXTENSION);
+        std::fs::write(&description_file_path, &res.description)
+            .map_err(|e| Failure::io("write", &description_file_path, e))?;
+    }
+
+    if opts.convert_dtx && convert::is_dtx(res) {
+        let png_file_path = extract_file_path.with_extension("png");
+        if let Err(e) = convert::dtx_to_png(&extract_file_path, &png_file_path) {
+            warn!("not converting {}: {}", extract_file_path.display(), e);
+        }
+    }
+
+    if verified {
+        Ok(ExtractOutcome::Extracted)
+    } else {
+        Ok(ExtractOutcome::VerificationFailed)
+    }
+}
+
+/// Writes the data of a resource into the given file, returning the number of bytes written and a
+/// hash of them.
+///
+/// Failing to read from the REZ file is returned as a failure, which ends the extraction; failing
+/// to write the output file is returned as the inner error so that the write can be retried.
+#[allow(clippy::too_many_arguments)]
+fn write_resource_file<R: Read + Seek>(rez_file: &mut R, base_offset: u64, entry_path: &str, res: &rez::Resource, extract_base_path: &Path, extract_file_path: &Path, opts: &ExtractOpts) -> Result<std::io::Result<(u64, u64)>, Failure> {
+    let data_offset = base_offset + u64::from(res.header.position);
+    let read_failure = |e| Failure::resource(&opts.input.rez_file, entry_path, data_offset, e);
+    if let Err(e) = std::fs::create_dir_all(extract_base_path) {
+        return write_error("create", extract_base_path, e);
+    }
+
+    let mut written_hasher = DefaultHasher::new();
+    let mut written_length: u64 = 0;
+    let transcode = opts.transcode_text_ext.iter()
+        .any(|ext| ext.eq_ignore_ascii_case(&res.extension));
+    let mut rez_reader = rez::RezReader::new(&mut *rez_file);
+    rez_reader.set_base_offset(base_offset);
+    rez_reader.set_decompressor(opts.decompress.to_decompressor()?);
+    if transcode {
+        let data = rez_reader.read_resource(res)
+            .map_err(read_failure)?;
+        let data = transcode_text(&data, opts.from_encoding, opts.to_encoding, extract_file_path);
+        if let Err(e) = std::fs::write(extract_file_path, &data) {
+            return write_error("create", extract_file_path, e);
+        }
+        written_hasher.write(&data);
+        written_length = data.len().try_into().unwrap();
+    } else {
+        let mut output_file = match File::create(extract_file_path) {
+            Ok(f) => f,
+            Err(e) => return write_error("create", extract_file_path, e),
+        };
+        // read and write errors are reported differently, so this cannot use io::copy
+        let mut data = rez_reader.resource_data_reader(res)
+            .map_err(read_failure)?;
+        let mut buf = vec![0u8; COPY_BUFFER_SIZE.min(res.header.size.try_into().unwrap_or(usize::MAX))];
+        loop {
+            let read_count = match data.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    // do not leave a partial file behind
+                    drop(output_file);
+                    let _ = std::fs::remove_file(extract_file_path);
+                    return Err(read_failure(e));
+                },
+            };
+            if let Err(e) = output_file.write_all(&buf[0..read_count]) {
+                return Ok(Err(e));
+            }
+            if opts.verify_after_extract {
+                written_hasher.write(&buf[0..read_count]);
+            }
+            written_length += u64::try_from(read_count).unwrap();
+        }
+        if let Err(e) = output_file.flush() {
+            return Ok(Err(e));
+        }
+    }
+    Ok(Ok((written_length, written_hasher.finish())))
+}
+
+/// Returns the given error from creating the given file or directory in the form returned by
+/// [`write_resource_file`]. Names that are too long for the file system end the extraction, since
+/// retrying cannot help; other errors can be retried.
+fn write_error(verb: &str, path: &Path, e: std::io::Error) -> Result<std::io::Result<(u64, u64)>, Failure> {
+    if e.kind() == std::io::ErrorKind::InvalidFilename {
+        Err(Failure::new(
+            FailureKind::Io,
+            format!(
+                "failed to {} {}: the name is too long for the file system; consider using --max-name-length",
+                verb, path.display(),
+            ),
+        ))
+    } else {
+        Ok(Err(e))
+    }
 }
 
-#[derive(Parser)]
-struct ListOpts {
-    /// The REZ file whose contents to list.
-    pub rez_file: PathBuf,
+/// Re-encodes text from one encoding into another. Byte order marks are neither interpreted nor
+/// added.
+fn transcode_text(data: &[u8], from_encoding: &'static Encoding, to_encoding: &'static Encoding, extract_file_path: &Path) -> Vec<u8> {
+    let (text, had_errors) = from_encoding.decode_without_bom_handling(data);
+    if had_errors {
+        warn!("{} is not valid {}; replacing invalid sequences", extract_file_path.display(), from_encoding.name());
+    }
+    // the encoder of encoding_rs only produces UTF-8 for UTF-16, which can represent everything
+    if to_encoding == encoding_rs::UTF_16LE {
+        return text.encode_utf16().flat_map(u16::to_le_bytes).collect();
+    }
+    if to_encoding == encoding_rs::UTF_16BE {
+        return text.encode_utf16().flat_map(u16::to_be_bytes).collect();
+    }
+    let (encoded, _actual_encoding, had_errors) = to_encoding.encode(&text);
+    if had_errors {
+        warn!("{} contains characters not representable in {}; replacing them", extract_file_path.display(), to_encoding.name());
+    }
+    encoded.into_owned()
 }
 
-#[derive(Parser)]
-struct ExtractOpts {
-    /// If given, limits the the extraction to files whose paths match this pattern. If given
-    /// multiple times, a file will be extracted if it matches at least one of the patterns.
-    #[arg(short, long = "filter")]
-    pub filters: Vec<String>,
+/// Replaces each of the given files whose contents are identical to those of an earlier file in
+/// the list by a link to the earlier file.
+fn dedup_extracted_files(files: &[PathBuf], link_kind: LinkKindOpt) -> Result<(), Failure> {
+    // (length, hash) -> files with different contents but this length and hash
+    let mut originals: HashMap<(u64, u64), Vec<&Path>> = HashMap::new();
+    let mut original_count: usize = 0;
+    let mut link_count: usize = 0;
+    for file in files {
+        let key = match hash_file(file) {
+            Ok(k) => k,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue, // was not extracted
+            Err(e) => return Err(Failure::io("read back", file, e)),
+        };
 
-    /// The REZ file whose contents to extract.
-    pub rez_file: PathBuf,
+        let candidates = originals.entry(key).or_default();
+        let mut original = None;
+        for candidate in candidates.iter() {
+            if *candidate == file.as_path() {
+                // extracted twice into the same file
+                original = Some(*candidate);
+                break;
+            }
+            let same = files_have_same_contents(candidate, file)
+                .map_err(|e| Failure::io("compare", file, e))?;
+            if same {
+                original = Some(*candidate);
+                break;
+            }
+        }
+        let Some(original) = original else {
+            candidates.push(file);
+            original_count += 1;
+            continue;
+        };
+        if original == file.as_path() {
+            continue;
+        }
 
-    /// The directory into which to extract the REZ file contents.
-    pub output_directory: PathBuf,
+        info!("linking {} to identical {}", file.display(), original.display());
+        std::fs::remove_file(file)
+            .map_err(|e| Failure::io("remove duplicate file", file, e))?;
+        match link_kind {
+            LinkKindOpt::Hard => std::fs::hard_link(original, file)
+                .map_err(|e| Failure::io("create hard link", file, e))?,
+            LinkKindOpt::Symbolic => {
+                let target = original.canonicalize()
+                    .map_err(|e| Failure::io("resolve the path of", original, e))?;
+                #[cfg(unix)]
+                std::os::unix::fs::symlink(&target, file)
+                    .map_err(|e| Failure::io("create symbolic link", file, e))?;
+                #[cfg(windows)]
+                std::os::windows::fs::symlink_file(&target, file)
+                    .map_err(|e| Failure::io("create symbolic link", file, e))?;
+            },
+        }
+        link_count += 1;
+    }
+    info!("{} file(s) with unique contents, {} file(s) replaced by links", original_count, link_count);
+    Ok(())
 }
 
+/// Returns whether the two files have exactly the same contents.
+fn files_have_same_contents(left: &Path, right: &Path) -> Result<bool, std::io::Error> {
+    let mut left_file = File::open(left)?;
+    let mut right_file = File::open(right)?;
+    let mut left_buf = vec![0u8; 64*1024];
+    let mut right_buf = vec![0u8; 64*1024];
+    loop {
+        let left_count = read_fully(&mut left_file, &mut left_buf)?;
+        let right_count = read_fully(&mut right_file, &mut right_buf)?;
+        if left_buf[..left_count] != right_buf[..right_count] {
+            return Ok(false);
+        }
+        if left_count == 0 {
+            return Ok(true);
+        }
+    }
+}
 
-fn output_rez_entries_recursive(entries: &[rez::Entry], indent: usize) {
-    for entry in entries {
-        for _ in 0..indent {
-            print!("  ");
+/// Reads until the buffer is full or the end of the file is reached; returns the number of bytes
+/// read.
+fn read_fully<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+    let mut total = 0;
+    while total < buf.len() {
+        let read_count = reader.read(&mut buf[total..])?;
+        if read_count == 0 {
+            break;
         }
+        total += read_count;
+    }
+    Ok(total)
+}
 
-        match entry {
-            rez::Entry::Directory(dir) => {
-                println!("{} ({})/", dir.name, dir.header.time);
-                output_rez_entries_recursive(&dir.entries, indent + 1);
-            },
-            rez::Entry::Resource(res) => {
-                print!("{}.{} [{}", res.name, res.extension, res.id);
-                if res.description.len() > 0 {
-                    print!(", {}", res.description);
-                }
-                println!("] ({}, {}+{} bytes)", res.header.time, res.header.position, res.header.size);
-            },
+/// Reads the file at the given path, returning its length and a hash of its contents.
+fn hash_file(path: &Path) -> Result<(u64, u64), std::io::Error> {
+    let mut file = File::open(path)?;
+    let mut hasher = DefaultHasher::new();
+    let mut length = 0;
+    let mut buf = vec![0u8; 4*1024*1024];
+    loop {
+        let read_count = file.read(&mut buf)?;
+        if read_count == 0 {
+            break;
         }
+        hasher.write(&buf[0..read_count]);
+        length += u64::try_from(read_count).unwrap();
     }
+    Ok((length, hasher.finish()))
 }
 
-fn extract_rez_entries_recursive(rez_file: &mut File, entries: &[rez::Entry], entry_base_path: &str, extract_base_path: &Path, filters: &[Regex]) {
+fn prune_empty_dirs_recursive(entries: &[rez::Entry], extract_base_path: &Path, opts: &ExtractOpts) -> Result<(), Failure> {
     for entry in entries {
-        let entry_path = if entry_base_path.len() > 0 {
-            format!("{}/{}", entry_base_path, entry.name())
-        } else {
-            entry.name().into_owned()
-        };
+        if let rez::Entry::Directory(dir) = entry {
+            let (extract_name, _entry_path, dir) = extract_dir_name(dir, String::new(), opts);
+            let mut extract_sub_path = extract_base_path.to_owned();
+            extract_sub_path.push(&extract_name);
 
-        match entry {
-            rez::Entry::Directory(dir) => {
-                // descend
-                let mut extract_sub_path = extract_base_path.to_owned();
-                extract_sub_path.push(&dir.name);
+            // children first
+            prune_empty_dirs_recursive(&dir.entries, &extract_sub_path, opts)?;
 
-                extract_rez_entries_recursive(rez_file, &dir.entries, &entry_path, &extract_sub_path, filters);
-            },
-            rez::Entry::Resource(res) => {
-                // check if a filter matches
-                if filters.len() > 0 && filters.iter().all(|f| !f.is_match(&entry_path)) {
-                    // skip
-                    continue;
-                }
+            let is_empty = match std::fs::read_dir(&extract_sub_path) {
+                Ok(mut dir_entries) => dir_entries.next().is_none(),
+                Err(_) => false, // does not exist or is not a directory
+            };
+            if is_empty {
+                info!("removing empty directory {}", extract_sub_path.display());
+                std::fs::remove_dir(&extract_sub_path)
+                    .map_err(|e| Failure::io("remove empty directory", &extract_sub_path, e))?;
+            }
+        }
+    }
+    Ok(())
+}
 
-                let mut extract_file_path = extract_base_path.to_owned();
-                extract_file_path.push(format!("{}.{}", res.name, res.extension));
-
-                println!("extracting {} as {}", entry_path, extract_file_path.display());
-
-                std::fs::create_dir_all(extract_base_path)
-                    .expect("failed to create directory tree");
-                rez_file.seek(SeekFrom::Start(res.header.position.into()))
-                    .expect("failed to seek within rez file");
-
-                {
-                    let mut output_file = File::create(&extract_file_path)
-                        .expect("failed to open output file");
-                    let mut remain_length: u64 = res.header.size.into();
-                    while remain_length > 0 {
-                        let mut buf = vec![0u8; 4*1024*1024];
-                        let read_count: usize = remain_length.min(buf.len().try_into().unwrap()).try_into().unwrap();
-                        rez_file.read_exact(&mut buf[0..read_count])
-                            .expect("failed to read");
-                        output_file.write_all(&buf[0..read_count])
-                            .expect("failed to write");
-                        remain_length -= u64::try_from(read_count).unwrap();
-                    }
-                    output_file.flush()
-                        .expect("failed to flush output");
-                }
-            },
+/// Sets the modification times of the directories corresponding to REZ directories that exist in
+/// the output directory to those stored in the REZ file.
+fn set_dir_times_recursive(entries: &[rez::Entry], extract_base_path: &Path, opts: &ExtractOpts) {
+    for entry in entries {
+        if let rez::Entry::Directory(dir) = entry {
+            let (extract_name, _entry_path, dir) = extract_dir_name(dir, String::new(), opts);
+            let extract_sub_path = extract_base_path.join(&extract_name);
+            if !extract_sub_path.is_dir() {
+                continue;
+            }
+
+            set_dir_times_recursive(&dir.entries, &extract_sub_path, opts);
+            if let Err(e) = set_modified_time(&extract_sub_path, dir.header.time) {
+                warn!("failed to set the modification time of {}: {}", extract_sub_path.display(), e);
+            }
         }
     }
 }
 
-fn glob_pattern_to_regex(glob_pattern: &str) -> Regex {
-    // single asterisk: sequence of anything except a slash
-    const SINGLE_ASTERISK_MATCHER: &str = "[^/]+";
-    // multiple asterisks: sequence of anything including a slash
-    const MULTIPLE_ASTERISK_MATCHER: &str = ".+";
-    // question mark: anything except a slash
-    const QUESTION_MARK_MATCHER: &str = "[^/]";
+/// Sets the modification time of the given file or directory to the given Unix timestamp.
+fn set_modified_time(path: &Path, time: u32) -> std::io::Result<()> {
+    // directories cannot be opened as files everywhere (e.g. on Windows), so go by path
+    filetime::set_file_mtime(path, filetime::FileTime::from_unix_time(time.into(), 0))
+}
 
-    let mut asterisk_counter = 0;
-    let mut regex_buf = String::with_capacity(glob_pattern.len() + 2);
-    regex_buf.push('^');
+/// Deletes the contents of the output directory, refusing to do so if it is not empty and `force`
+/// is false or if it contains the REZ file.
+fn clean_output_directory(output_directory: &Path, rez_file_path: &Path, force: bool) -> Result<(), Failure> {
+    let mut dir_entries = match std::fs::read_dir(output_directory) {
+        Ok(de) => de.peekable(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()), // nothing to clean
+        Err(e) => return Err(Failure::io("read output directory", output_directory, e)),
+    };
+    if dir_entries.peek().is_none() {
+        return Ok(());
+    }
 
-    for c in glob_pattern.chars() {
-        if c == '*' {
-            asterisk_counter += 1;
-            continue;
+    let output_directory_canon = output_directory.canonicalize()
+        .map_err(|e| Failure::io("resolve the path of", output_directory, e))?;
+    let rez_file_canon = rez_file_path.canonicalize()
+        .map_err(|e| Failure::io("resolve the path of", rez_file_path, e))?;
+    if rez_file_canon.starts_with(&output_directory_canon) {
+        return Err(Failure::new(
+            FailureKind::Unsuccessful,
+            format!("refusing to clean {} because it contains the REZ file", output_directory.display()),
+        ));
+    }
+    if !force {
+        return Err(Failure::new(
+            FailureKind::Unsuccessful,
+            format!("refusing to clean {} because it is not empty; pass --force to delete its contents", output_directory.display()),
+        ));
+    }
+
+    info!("cleaning {}", output_directory.display());
+    for dir_entry in dir_entries {
+        let dir_entry = dir_entry
+            .map_err(|e| Failure::io("read output directory", output_directory, e))?;
+        let path = dir_entry.path();
+        let file_type = dir_entry.file_type()
+            .map_err(|e| Failure::io("obtain the file type of", &path, e))?;
+        if file_type.is_dir() {
+            std::fs::remove_dir_all(&path)
+                .map_err(|e| Failure::io("remove directory", &path, e))?;
         } else {
-            if asterisk_counter == 1 {
-                regex_buf.push_str(SINGLE_ASTERISK_MATCHER);
-            } else if asterisk_counter >= 2 {
-                regex_buf.push_str(MULTIPLE_ASTERISK_MATCHER);
+            std::fs::remove_file(&path)
+                .map_err(|e| Failure::io("remove file", &path, e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Outputs a hex dump of the given data in the classic "offset, hex bytes, ASCII" format.
+fn output_hex_dump<R: Read>(reader: &mut R, length: u64) -> std::io::Result<()> {
+    const BYTES_PER_LINE: usize = 16;
+
+    let mut offset: u64 = 0;
+    let mut line_buf = [0u8; BYTES_PER_LINE];
+    while offset < length {
+        let line_length: usize = (length - offset).min(BYTES_PER_LINE as u64).try_into().unwrap();
+        reader.read_exact(&mut line_buf[0..line_length])?;
+
+        print!("{:08x} ", offset);
+        for (i, b) in line_buf[0..line_length].iter().enumerate() {
+            if i % 8 == 0 {
+                print!(" ");
+            }
+            print!("{:02x} ", b);
+        }
+        for i in line_length..BYTES_PER_LINE {
+            if i % 8 == 0 {
+                print!(" ");
+            }
+            print!("   ");
+        }
+        print!(" |");
+        for &b in &line_buf[0..line_length] {
+            if b.is_ascii_graphic() || b == b' ' {
+                print!("{}", char::from(b));
+            } else {
+                print!(".");
             }
-            asterisk_counter = 0;
         }
+        println!("|");
 
-        if c == '\\' {
-            // normalize paths to forward slashes
-            regex_buf.push('/');
-            continue;
+        offset += u64::try_from(line_length).unwrap();
+    }
+    println!("{:08x}", offset);
+    Ok(())
+}
+
+/// Looks up a text encoding by one of its WHATWG labels, e.g. `utf-8`, `cp1252` or `latin1`.
+fn parse_encoding(label: &str) -> Result<&'static Encoding, String> {
+    Encoding::for_label(label.as_bytes())
+        .ok_or_else(|| format!("unknown encoding {:?}", label))
+}
+
+/// Looks up a text encoding into which text can be converted, as with [`parse_encoding`].
+fn parse_output_encoding(label: &str) -> Result<&'static Encoding, String> {
+    let encoding = parse_encoding(label)?;
+    // the replacement encoding only exists to prevent decoding
+    if encoding == encoding_rs::REPLACEMENT {
+        return Err(format!("text cannot be converted into the encoding {:?}", label));
+    }
+    Ok(encoding)
+}
+
+/// Parses an `OLD=NEW` extension mapping.
+fn parse_ext_mapping(value: &str) -> Result<(String, String), String> {
+    let Some((old, new)) = value.split_once('=') else {
+        return Err(format!("{:?} is not of the form OLD=NEW", value));
+    };
+    Ok((old.to_owned(), new.to_owned()))
+}
+
+/// Parses a decimal or `0x`-prefixed hexadecimal offset.
+fn parse_offset(value: &str) -> Result<u64, String> {
+    let result = match value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+    result.map_err(|_| format!("{:?} is not a valid offset", value))
+}
+
+/// Returns a short description of the REZ file read by the given reader, or `None` if it is not a
+/// REZ file.
+fn identify_rez_file<R: Read + Seek>(reader: &mut R, count: bool) -> Result<Option<String>, rez::Error> {
+    let header = match rez::FileHeader::try_read(reader) {
+        Ok(h) => h,
+        Err(e) if e.is_format_error() => return Ok(None),
+        Err(rez::Error::Io(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let mut description = if header.file_type.is_empty() {
+        format!("(no file type) v{}", header.version)
+    } else {
+        format!("{} v{}", escape_control_chars(&rez::iso88591_bytes_to_string(&header.file_type)), header.version)
+    };
+    if count {
+        reader.seek(SeekFrom::Start(0))?;
+        let rez_file = rez::File::try_read(reader)?;
+        let resource_count = rez_file.walk().filter(|(_path, entry)| entry.is_resource()).count();
+        description.push_str(&format!(" ({} files)", resource_count));
+    }
+    Ok(Some(description))
+}
+
+/// Replaces control characters, which might wreak havoc on a terminal, with `\xNN` escapes.
+///
+/// Header strings are decoded as ISO-8859-1, so binary data in them turns into control characters.
+fn escape_control_chars(text: &str) -> Cow<'_, str> {
+    if !text.chars().any(|c| c.is_control()) {
+        return Cow::Borrowed(text);
+    }
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_control() {
+            escaped.push_str(&format!("\\x{:02X}", u32::from(c)));
+        } else {
+            escaped.push(c);
         }
+    }
+    Cow::Owned(escaped)
+}
 
-        if c == '?' {
-            regex_buf.push_str(QUESTION_MARK_MATCHER);
+/// Compiles the filter options, failing if any pattern is invalid.
+fn resource_filter(opts: &FilterOpts) -> Result<ResourceFilter, Failure> {
+    ResourceFilter::from_opts(opts)
+        .map_err(|e| Failure::new(FailureKind::Usage, format!("invalid pattern: {}", e)))
+}
+
+/// Fails if the data of any resource selected by the filter extends past the end of the REZ file,
+/// as with `--on-short-read error` during extraction.
+fn check_short_resources(rez_file: &rez::File, filter: &ResourceFilter, rez_path: &Path) -> Result<(), Failure> {
+    for (entry_path, entry) in rez_file.walk() {
+        let Some(res) = entry.as_resource() else { continue };
+        if !filter.matches(&entry_path, res) {
             continue;
         }
+        let available = rez_file.file_size.saturating_sub(res.header.position.into());
+        if u64::from(res.header.size) > available {
+            return Err(Failure::new(
+                FailureKind::InvalidInput,
+                format!(
+                    "cannot convert {} stored at offset {} in {}: the REZ file ends {} bytes before the end of its data; consider using --on-short-read",
+                    entry_path, res.header.position, rez_path.display(), u64::from(res.header.size) - available,
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
 
-        if (c >= '0' && c <= '9') || (c >= 'A' && c <= 'Z') || (c >= 'a' && c <= 'z') || c == '<' || c == '>' {
-            // spit it out verbatim
-            regex_buf.push(c);
-        } else {
-            // escape it
-            regex_buf.push('\\');
-            regex_buf.push(c);
+/// Outputs the listing of the REZ file read from the given reader according to the options.
+fn list_rez_file<R: Read + Seek>(reader: &mut R, opts: &ListOpts, filter: &ResourceFilter) -> Result<(), Failure> {
+    let rez_failure = |e| Failure::rez(&opts.input.rez_file, e);
+    reader.seek(SeekFrom::Start(opts.input.base_offset))
+        .map_err(|e| Failure::io("seek within", &opts.input.rez_file, e))?;
+    if opts.debug_dump {
+        let header = rez::FileHeader::try_read(reader)
+            .map_err(rez_failure)?;
+        println!("{:#?}", header);
+        reader.seek(SeekFrom::Start(opts.input.base_offset))
+            .map_err(|e| Failure::io("seek within", &opts.input.rez_file, e))?;
+        let mut rez_file = rez::File::try_read(reader)
+            .map_err(rez_failure)?;
+        rez_file.redecode_extensions(opts.ext_order.to_extension_order());
+        println!("{:#?}", rez_file);
+    } else if filter.is_unrestricted() && !(opts.json || opts.csv || opts.tsv || opts.count) {
+        // nothing to look ahead for; output the entries as they are read
+        output_rez_entries_streaming(reader, opts.entry_type, opts.ext_order.to_extension_order(), &opts.text_style)
+            .map_err(rez_failure)?;
+    } else {
+        let mut rez_file = rez::File::try_read(reader)
+            .map_err(rez_failure)?;
+        rez_file.redecode_extensions(opts.ext_order.to_extension_order());
+        list_rez_tree(&rez_file, opts, filter)?;
+    }
+    Ok(())
+}
+
+/// Outputs the listing of the given directory tree, whose extensions have already been decoded,
+/// according to the options other than `--debug-dump`.
+fn list_rez_tree(rez_file: &rez::File, opts: &ListOpts, filter: &ResourceFilter) -> Result<(), Failure> {
+    let output_failure = |e| Failure::new(FailureKind::Io, format!("failed to write listing: {}", e));
+    if opts.json {
+        listing::write_json(&mut std::io::stdout().lock(), rez_file, filter)
+            .map_err(output_failure)?;
+    } else if opts.csv || opts.tsv {
+        let delimiter = if opts.tsv { b'\t' } else { b',' };
+        listing::write_delimited(&mut std::io::stdout().lock(), rez_file, filter, delimiter)
+            .map_err(output_failure)?;
+    } else if opts.count {
+        let count = matching_entry_paths(rez_file, opts.entry_type, filter).len();
+        print!("{}{}", count, opts.text_style.line_ending());
+        if count == 0 {
+            return Err(Failure::negative());
+        }
+    } else {
+        output_rez_entries_recursive(&rez_file.root_entries, "", 0, filter, opts.entry_type, &opts.text_style);
+    }
+    Ok(())
+}
+
+/// Extracts the given REZ file, whose extensions have already been decoded, according to the
+/// options, reading the resource data from the given reader. If files are extracted using multiple
+/// threads, each thread obtains its own reader by calling `open_reader`.
+fn extract_rez_file<R, F>(reader: &mut R, open_reader: F, rez_file: &rez::File, opts: &ExtractOpts, filter: &ResourceFilter) -> Result<(), Failure>
+    where
+        R: Read + Seek,
+        F: Fn() -> std::io::Result<R> + Sync,
+{
+    if let Some(expect_type) = &opts.expect_type {
+        let expect_type = expect_type.trim_end_matches(' ');
+        let file_type = rez_file.file_type();
+        let matches = match file_type {
+            Some(ft) => ft == expect_type,
+            None => expect_type.is_empty(),
+        };
+        if !matches {
+            let actual = match file_type {
+                Some(ft) => format!("file type {:?}", ft),
+                None => "no file type".to_owned(),
+            };
+            return Err(Failure::new(
+                FailureKind::InvalidInput,
+                format!("REZ file {} has {} instead of the expected {:?}", opts.input.rez_file.display(), actual, expect_type),
+            ));
+        }
+    }
+
+    let mut output_directory = opts.output_directory.clone();
+    if opts.prefix_archive_name {
+        let Some(archive_stem) = opts.input.rez_file.file_stem() else {
+            return Err(Failure::new(
+                FailureKind::Usage,
+                format!("the REZ file path {} has no file name", opts.input.rez_file.display()),
+            ));
+        };
+        output_directory.push(archive_stem);
+    }
+
+    if opts.clean {
+        clean_output_directory(&output_directory, &opts.input.rez_file, opts.force)?;
+    }
+
+    // which subtrees to extract: (entries, path within REZ file, output directory)
+    let mut subtrees = Vec::new();
+    if opts.only.is_empty() {
+        subtrees.push((&rez_file.root_entries, String::new(), output_directory.clone()));
+    } else {
+        for only_path in &opts.only {
+            if !matches!(rez_file.entry_by_path(only_path), Some(rez::Entry::Directory(_))) {
+                return Err(Failure::new(FailureKind::NotFound, format!("{} is not a directory in the REZ file", only_path)));
+            }
+            subtrees.push(only_subtree(&rez_file.root_entries, only_path, &output_directory, opts));
+        }
+    }
+
+    let (total_files, total_bytes) = subtrees.iter()
+        .map(|(entries, entry_base_path, _)| count_selected_resources(entries, entry_base_path, filter))
+        .fold((0, 0), |(files, bytes), (f, b)| (files + f, bytes + b));
+    debug!("{} files with a total size of {} bytes selected for extraction", total_files, total_bytes);
+
+    let mut selected_resources = Vec::new();
+    for (entries, entry_base_path, extract_base_path) in &subtrees {
+        collect_selected_resources_recursive(entries, entry_base_path, extract_base_path, filter, opts, &mut selected_resources);
+    }
+    if !opts.trust_names {
+        warn_about_unsafe_names(&selected_resources);
+    }
+    let flatten_collisions = if opts.flatten && opts.flatten_collisions == FlattenCollisionsOpt::SuffixId {
+        find_flatten_collisions(&selected_resources, opts)
+    } else {
+        HashSet::new()
+    };
+
+    if opts.dry_run {
+        output_dry_run(&selected_resources, flatten_collisions, opts);
+        return Ok(());
+    }
+    check_free_space(&output_directory, total_bytes, opts.force)?;
+
+    let events = match opts.events {
+        Some(EventsFormatOpt::Ndjson) => {
+            let writer: Box<dyn Write + Send> = match &opts.events_file {
+                Some(path) => Box::new(File::create(path).map_err(|e| Failure::io("create", path, e))?),
+                None => Box::new(std::io::stdout()),
+            };
+            Some(EventWriter::new(writer))
+        },
+        None => None,
+    };
+
+    // events on standard output would be interleaved with the progress bar on a terminal
+    let progress = if opts.events.is_some() && opts.events_file.is_none() {
+        None
+    } else {
+        Some(ExtractProgress::new(total_files, total_bytes))
+    };
+
+    let mut state = ExtractState {
+        rez_extent: RezExtent {
+            base_offset: rez_file.base_offset,
+            size: rez_file.file_size,
+        },
+        events,
+        progress,
+        existing_file_policy: opts.existing_file_policy(),
+        flatten_collisions,
+        ..ExtractState::default()
+    };
+    if let Some(events) = &state.events {
+        events.start(total_files, total_bytes);
+    }
+    let extracted = if opts.jobs > 1 {
+        extract_resources_parallel(&selected_resources, &open_reader, opts.jobs, opts, &mut state)
+    } else {
+        subtrees.iter().try_for_each(|(entries, entry_base_path, extract_base_path)| {
+            extract_rez_entries_recursive(
+                reader,
+                &open_reader,
+                entries,
+                entry_base_path,
+                extract_base_path,
+                filter,
+                opts,
+                &mut state,
+            )
+        })
+    };
+
+    if let Some(progress) = &state.progress {
+        progress.finish();
+    }
+    extracted?;
+
+    if let Some(link_kind) = opts.dedup_links {
+        dedup_extracted_files(&state.extracted_files, link_kind)?;
+    }
+    if let Some(events) = &state.events {
+        events.done()
+            .map_err(|e| Failure::new(FailureKind::Io, format!("failed to write events: {}", e)))?;
+    }
+
+    if opts.prune_empty_dirs && !opts.flatten {
+        prune_empty_dirs_recursive(&rez_file.root_entries, &output_directory, opts)?;
+    }
+    if !opts.no_preserve_times && !opts.flatten {
+        // only now, since adding files to a directory changes its modification time
+        set_dir_times_recursive(&rez_file.root_entries, &output_directory, opts);
+    }
+
+    if let Some(manifest_path) = &opts.write_manifest {
+        pack::write_manifest(manifest_path, &state.extracted_ids)
+            .map_err(|e| Failure::io("write manifest", manifest_path, e))?;
+    }
+
+    if !state.write_failures.is_empty() {
+        error!("{} file(s) could not be written:", state.write_failures.len());
+        for path in &state.write_failures {
+            error!("  {}", path.display());
         }
     }
+    if !state.verification_failures.is_empty() {
+        error!("{} file(s) failed verification", state.verification_failures.len());
+    }
+    if !state.write_failures.is_empty() || !state.verification_failures.is_empty() {
+        return Err(Failure::negative());
+    }
+    Ok(())
+}
 
-    // any trailing asterisks?
-    if asterisk_counter == 1 {
-        regex_buf.push_str(SINGLE_ASTERISK_MATCHER);
-    } else if asterisk_counter > 2 {
-        regex_buf.push_str(MULTIPLE_ASTERISK_MATCHER);
+/// Assigns IDs to the given entries and writes them into a new REZ file according to the options.
+/// The data of each resource is obtained by calling `open_data` with its path.
+fn write_new_rez_file<F, D>(mut entries: Vec<rez::Entry>, opts: &NewRezOpts, rez_path: &Path, mut open_data: F) -> Result<(), Failure>
+    where
+        F: FnMut(&str) -> std::io::Result<D>,
+        D: Read,
+{
+    if opts.sorted {
+        rez::sort_entries_recursive(&mut entries);
     }
 
-    regex_buf.push('$');
+    let manifest = match &opts.manifest {
+        Some(manifest_path) => pack::read_manifest(manifest_path)
+            .map_err(|e| Failure::io("read manifest", manifest_path, e))?,
+        None => HashMap::new(),
+    };
+    pack::assign_ids(&mut entries, opts.id_strategy, &manifest)
+        .map_err(|e| Failure::new(FailureKind::InvalidInput, e))?;
+
+    let time = SystemTime::now().duration_since(UNIX_EPOCH)
+        .ok()
+        .and_then(|d| u32::try_from(d.as_secs()).ok())
+        .unwrap_or(0);
+    let mut rez_file = rez::File {
+        file_type: opts.file_type.clone(),
+        user_title: opts.user_title.clone(),
+        version: 1,
+        time,
+        is_sorted: opts.sorted,
+        file_size: 0,
+        base_offset: 0,
+        root_entries: entries,
+    };
+
+    let mut output = File::create(rez_path)
+        .map_err(|e| Failure::io("create", rez_path, e))?;
+    rez_file.write(&mut output, |path, _res| open_data(path))
+        .map_err(|e| Failure::rez_write(rez_path, e))
+}
+
+/// Maps the REZ file at the given path, which has been opened as the given file, into memory and
+/// reads its directory tree, starting at the given offset.
+///
+/// REZ files are not expected to be modified while they are being read.
+#[cfg(feature = "mmap")]
+fn map_rez_file(file: &File, path: &Path, base_offset: u64) -> Result<rez::MappedFile, Failure> {
+    rez::MappedFile::open(file, base_offset)
+        .map_err(|e| Failure::rez(path, e))
+}
+
+/// Fails because this build does not support memory mapping.
+#[cfg(not(feature = "mmap"))]
+fn map_rez_file(file: &File, path: &Path, base_offset: u64) -> Result<NoMappedFile, Failure> {
+    let _ = (file, path, base_offset);
+    Err(Failure::new(
+        FailureKind::Usage,
+        "this build of lithrez does not support memory mapping; build it with the \"mmap\" feature",
+    ))
+}
+
+/// Stands in for [`rez::MappedFile`] in builds without memory mapping support, in which it can
+/// never be obtained.
+#[cfg(not(feature = "mmap"))]
+enum NoMappedFile {}
+#[cfg(not(feature = "mmap"))]
+impl NoMappedFile {
+    fn rez_file(&self) -> &rez::File { match *self {} }
+    fn rez_file_mut(&mut self) -> &mut rez::File { match *self {} }
+    fn reader(&self) -> std::io::Cursor<&[u8]> { match *self {} }
+}
+
+/// Opens the REZ file at the given path and reads its directory tree, starting at the given
+/// offset.
+fn open_rez_file(path: &Path, base_offset: u64) -> Result<(File, rez::File), Failure> {
+    let mut file = File::open(path)
+        .map_err(|e| Failure::io("open", path, e))?;
+    file.seek(SeekFrom::Start(base_offset))
+        .map_err(|e| Failure::io("seek within", path, e))?;
+    let rez_file = rez::File::try_read(&mut file)
+        .map_err(|e| Failure::rez(path, e))?;
+    Ok((file, rez_file))
+}
+
+/// Opens the REZ file at the given path for modification.
+fn open_rez_writer(path: &Path) -> Result<rez::Writer<File>, Failure> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .map_err(|e| Failure::io("open", path, e))?;
+    rez::Writer::open(file)
+        .map_err(|e| Failure::rez(path, e))
+}
 
-    Regex::new(&regex_buf)
-        .expect("failed to compile glob pattern to regex")
+/// Writes the directory of the modified REZ file at the given path and truncates it to its new
+/// length.
+fn finish_rez_writer(writer: rez::Writer<File>, path: &Path) -> Result<(), Failure> {
+    let (file, rez_file) = writer.finish()
+        .map_err(|e| Failure::rez_write(path, e))?;
+    file.set_len(rez_file.base_offset + rez_file.file_size)
+        .map_err(|e| Failure::io("truncate", path, e))
 }
 
 
-fn main() {
-    let mode = Mode::parse();
+fn main() -> std::process::ExitCode {
+    let cli = Cli::parse();
+
+    let mut logger_builder = match cli.verbosity.level_filter() {
+        Some(level) => {
+            // messages from other crates are only made more verbose by RUST_LOG
+            let mut builder = env_logger::Builder::new();
+            builder
+                .filter_level(level.min(LevelFilter::Info))
+                .filter_module("lithrez", level);
+            builder
+        },
+        None => env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")),
+    };
+    let logger = logger_builder
+        .format_timestamp(None)
+        .format_target(false)
+        .build();
+    progress::init_logger(logger);
+
+    match run(cli.mode) {
+        Ok(()) => std::process::ExitCode::SUCCESS,
+        Err(failure) => {
+            if let Some(message) = &failure.message {
+                error!("{}", message);
+            }
+            std::process::ExitCode::from(failure.kind.exit_code())
+        },
+    }
+}
+
+/// Runs the given command. See the [`failure`] module for the exit codes corresponding to the
+/// failures.
+fn run(mode: Mode) -> Result<(), Failure> {
     match mode {
         Mode::List(opts) => {
-            let mut file = File::open(&opts.rez_file)
-                .expect("failed to open REZ file");
-            let rez_file = rez::File::try_read(&mut file)
-                .expect("failed to read REZ directory");
-            output_rez_entries_recursive(&rez_file.root_entries, 0);
+            let filter = resource_filter(&opts.filter)?;
+            let mut file = File::open(&opts.input.rez_file)
+                .map_err(|e| Failure::io("open", &opts.input.rez_file, e))?;
+            if opts.mmap && !opts.debug_dump {
+                // the directory tree has already been read from the mapped bytes
+                let mut mapped = map_rez_file(&file, &opts.input.rez_file, opts.input.base_offset)?;
+                mapped.rez_file_mut().redecode_extensions(opts.ext_order.to_extension_order());
+                list_rez_tree(mapped.rez_file(), &opts, &filter)?;
+            } else {
+                list_rez_file(&mut file, &opts, &filter)?;
+            }
         },
         Mode::Extract(opts) => {
-            let mut file = File::open(&opts.rez_file)
-                .expect("failed to open REZ file");
+            let filter = resource_filter(&opts.filter)?;
+            // deduplication removes each duplicate before linking it, so check up front
+            if opts.dedup_links == Some(LinkKindOpt::Symbolic) && !cfg!(any(unix, windows)) {
+                return Err(Failure::new(FailureKind::Usage, "symbolic links are not supported on this platform".to_owned()));
+            }
+            validate_max_name_length(&opts)?;
+            if opts.mmap {
+                let file = File::open(&opts.input.rez_file)
+                    .map_err(|e| Failure::io("open", &opts.input.rez_file, e))?;
+                let mut mapped = map_rez_file(&file, &opts.input.rez_file, opts.input.base_offset)?;
+                mapped.rez_file_mut().redecode_extensions(opts.ext_order.to_extension_order());
+                extract_rez_file(&mut mapped.reader(), || Ok(mapped.reader()), mapped.rez_file(), &opts, &filter)?;
+            } else {
+                let (mut file, mut rez_file) = open_rez_file(&opts.input.rez_file, opts.input.base_offset)?;
+                rez_file.redecode_extensions(opts.ext_order.to_extension_order());
+                let open_reader = || File::open(&opts.input.rez_file);
+                extract_rez_file(&mut file, open_reader, &rez_file, &opts, &filter)?;
+            }
+        },
+        Mode::Verify(opts) => {
+            let mut file = File::open(&opts.input.rez_file)
+                .map_err(|e| Failure::io("open", &opts.input.rez_file, e))?;
+            file.seek(SeekFrom::Start(opts.input.base_offset))
+                .map_err(|e| Failure::io("seek within", &opts.input.rez_file, e))?;
+            let problems = if opts.offsets_only {
+                let findings = rez::File::validate_offsets_only(&mut file)
+                    .map_err(|e| Failure::rez(&opts.input.rez_file, e))?;
+                verify::problems_from_out_of_bounds(&findings)
+            } else {
+                match rez::File::try_read(&mut file) {
+                    Ok(rez_file) => {
+                        file.seek(SeekFrom::Start(opts.input.base_offset))
+                            .map_err(|e| Failure::io("seek within", &opts.input.rez_file, e))?;
+                        let header = rez::FileHeader::try_read(&mut file)
+                            .map_err(|e| Failure::rez(&opts.input.rez_file, e))?;
+                        verify::verify_file(&rez_file, &header)
+                    },
+                    Err(e) => match verify::problem_from_read_error(&e) {
+                        Some(problem) => vec![problem],
+                        None => return Err(Failure::rez(&opts.input.rez_file, e)),
+                    },
+                }
+            };
+
+            match opts.report {
+                ReportFormatOpt::Text => {
+                    for problem in &problems {
+                        match problem.severity {
+                            verify::Severity::Error => println!("{}: {}", problem.path, problem.description),
+                            verify::Severity::Warning => println!("{}: warning: {}", problem.path, problem.description),
+                        }
+                    }
+                    let error_count = problems.iter().filter(|p| p.severity == verify::Severity::Error).count();
+                    let warning_count = problems.len() - error_count;
+                    println!("{} errors, {} warnings", error_count, warning_count);
+                },
+                ReportFormatOpt::Json => {
+                    let stdout = std::io::stdout();
+                    verify::write_json(&mut stdout.lock(), &problems)
+                        .map_err(|e| Failure::new(FailureKind::Io, format!("failed to output report: {}", e)))?;
+                },
+            }
+            if problems.iter().any(|p| p.severity == verify::Severity::Error) {
+                return Err(Failure::negative());
+            }
+        },
+        Mode::Find(opts) => {
+            let filter = resource_filter(&opts.filter)?;
+            let (_file, mut rez_file) = open_rez_file(&opts.input.rez_file, opts.input.base_offset)?;
+            rez_file.redecode_extensions(opts.ext_order.to_extension_order());
+
+            let found_paths = matching_entry_paths(&rez_file, opts.entry_type, &filter);
+            let line_ending = opts.text_style.line_ending();
+            if opts.count {
+                print!("{}{}", found_paths.len(), line_ending);
+            } else {
+                for path in &found_paths {
+                    print!("{}{}", opts.text_style.convert_path(path), line_ending);
+                }
+            }
+            if found_paths.is_empty() {
+                return Err(Failure::negative());
+            }
+        },
+        Mode::Hexdump(opts) => {
+            let (mut file, rez_file) = open_rez_file(&opts.input.rez_file, opts.input.base_offset)?;
+            let Some(res) = rez_file.resource_by_path(&opts.path) else {
+                return Err(Failure::not_found(&opts.path));
+            };
+
+            let mut length = u64::from(res.header.size);
+            if let Some(max_length) = opts.length {
+                length = length.min(max_length);
+            }
+
+            output_hex_dump(&mut rez_file.open_resource(&mut file, res), length)
+                .map_err(|e| Failure::resource(&opts.input.rez_file, &opts.path, rez_file.data_offset(res), e))?;
+        },
+        Mode::Cat(opts) => {
+            let (mut file, rez_file) = open_rez_file(&opts.input.rez_file, opts.input.base_offset)?;
+            let Some(res) = rez_file.resource_by_path(&opts.path) else {
+                return Err(Failure::not_found(&opts.path));
+            };
+
+            let mut data = rez_file.open_resource(&mut file, res);
+            let mut stdout = std::io::stdout().lock();
+            // reading fails if the REZ file is truncated; writing fails harmlessly once the reader
+            // has had enough, e.g. `head`
+            let mut buf = vec![0u8; 64*1024];
+            let written = loop {
+                let read_count = data.read(&mut buf)
+                    .map_err(|e| Failure::resource(&opts.input.rez_file, &opts.path, rez_file.data_offset(res), e))?;
+                if read_count == 0 {
+                    break stdout.flush();
+                }
+                if let Err(e) = stdout.write_all(&buf[..read_count]) {
+                    break Err(e);
+                }
+            };
+            match written {
+                Ok(()) => {},
+                Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => {},
+                Err(e) => {
+                    return Err(Failure::new(FailureKind::Io, format!("failed to output {}: {}", opts.path, e)));
+                },
+            }
+        },
+        Mode::Identify(opts) => {
+            let mut any_failed = false;
+            for path in &opts.files {
+                let result = File::open(path)
+                    .map_err(rez::Error::from)
+                    .and_then(|mut file| identify_rez_file(&mut file, opts.count));
+                match result {
+                    Ok(Some(description)) => println!("{}: {}", path.display(), description),
+                    Ok(None) => println!("{}: not a REZ file", path.display()),
+                    Err(e) => {
+                        error!("{}: {}", path.display(), e);
+                        any_failed = true;
+                    },
+                }
+            }
+            if any_failed {
+                return Err(Failure::negative());
+            }
+        },
+        Mode::Whatis(opts) => {
+            let (_file, rez_file) = open_rez_file(&opts.input.rez_file, opts.input.base_offset)?;
+
+            match rez_file.resource_at_offset(opts.offset) {
+                Some((path, res)) => {
+                    let offset_in_resource = opts.offset - rez_file.data_offset(res);
+                    println!(
+                        "{} (byte {} of {}, stored at {}+{})",
+                        path, offset_in_resource, res.header.size, res.header.position, res.header.size,
+                    );
+                },
+                None => {
+                    println!("offset {} (0x{:X}) is not within any file", opts.offset, opts.offset);
+                    return Err(Failure::negative());
+                },
+            }
+        },
+        Mode::Positions(opts) => {
+            let (_file, rez_file) = open_rez_file(&opts.input.rez_file, opts.input.base_offset)?;
+
+            let positions = positions::collect_positions(&rez_file);
+            let mut output = std::io::BufWriter::new(
+                File::create(&opts.positions_file)
+                    .map_err(|e| Failure::io("create", &opts.positions_file, e))?
+            );
+            let result = if opts.json {
+                positions::write_json(&mut output, &positions)
+            } else {
+                positions::write_binary(&mut output, &positions)
+            };
+            result.and_then(|()| output.flush())
+                .map_err(|e| Failure::io("write", &opts.positions_file, e))?;
+        },
+        Mode::Info(opts) => {
+            let mut file = File::open(&opts.input.rez_file)
+                .map_err(|e| Failure::io("open", &opts.input.rez_file, e))?;
+            file.seek(SeekFrom::Start(opts.input.base_offset))
+                .map_err(|e| Failure::io("seek within", &opts.input.rez_file, e))?;
+            let header = rez::FileHeader::try_read(&mut file)
+                .map_err(|e| Failure::rez(&opts.input.rez_file, e))?;
+            // like rez::File::file_size, counted from the base offset
+            let file_size = file.seek(SeekFrom::End(0))
+                .map_err(|e| Failure::io("obtain the size of", &opts.input.rez_file, e))?
+                .saturating_sub(opts.input.base_offset);
+
+            let file_type = rez::iso88591_bytes_to_string(&header.file_type);
+            let file_type = if file_type.is_empty() { Cow::Borrowed("(none)") } else { escape_control_chars(&file_type) };
+            println!("file type: {}", file_type);
+            println!("user title: {}", escape_control_chars(&rez::iso88591_bytes_to_string(&header.user_title)));
+            println!("version: {}", header.version);
+            println!("time: {}", header.time);
+            println!("sorted: {}", if header.is_sorted { "yes" } else { "no" });
+            println!("size: {} bytes", file_size);
+            println!("root directory: {}+{} bytes", header.root_dir_position, header.root_dir_size);
+            println!("root directory time: {}", header.root_dir_time);
+            println!("next write position: {}", header.next_write_pos);
+            println!("largest key array: {}", header.largest_key_ary);
+            println!("largest directory name size: {}", header.largest_dir_name_size);
+            println!("largest file name size: {}", header.largest_rez_name_size);
+            println!("largest description size: {}", header.largest_comment_size);
+            if opts.header_only {
+                return Ok(());
+            }
+
+            file.seek(SeekFrom::Start(opts.input.base_offset))
+                .map_err(|e| Failure::io("seek within", &opts.input.rez_file, e))?;
             let rez_file = rez::File::try_read(&mut file)
-                .expect("failed to read REZ directory");
-            let filters: Vec<Regex> = opts.filters
-                .iter()
-                .map(|s| glob_pattern_to_regex(s))
-                .collect();
+                .map_err(|e| Failure::rez(&opts.input.rez_file, e))?;
+            println!("files: {}", rez_file.walk().filter(|(_path, entry)| entry.is_resource()).count());
 
-            extract_rez_entries_recursive(
-                &mut file,
-                &rez_file.root_entries,
-                "",
-                &opts.output_directory,
-                &filters,
+            if opts.with_extensions {
+                let mut extension_counts = BTreeMap::new();
+                for res in rez_file.walk().filter_map(|(_path, entry)| entry.as_resource()) {
+                    *extension_counts.entry(res.extension.clone()).or_insert(0) += 1;
+                }
+                println!("extensions:");
+                for (extension, count) in &extension_counts {
+                    let extension = if extension.is_empty() { "(none)" } else { extension.as_str() };
+                    println!("  {}: {}", extension, count);
+                }
+            }
+        },
+        Mode::Du(opts) => {
+            let (_file, rez_file) = open_rez_file(&opts.input.rez_file, opts.input.base_offset)?;
+
+            let mut sizes = rez_file.directory_sizes();
+            // largest first; ties by path, which puts parents before their children
+            sizes.sort_by(|(left_path, left_size), (right_path, right_size)|
+                right_size.cmp(left_size).then_with(|| left_path.cmp(right_path))
             );
+            for (path, size) in &sizes {
+                let path = if path.is_empty() { "/" } else { path.as_str() };
+                println!("{}\t{}", size, path);
+            }
+        },
+        Mode::Add(opts) => {
+            let mut writer = open_rez_writer(&opts.rez_file)?;
+
+            let mut trees = Vec::with_capacity(opts.files.len());
+            for path in &opts.files {
+                let tree = pack::scan_path(path)
+                    .map_err(|e| Failure::new(FailureKind::Io, e))?;
+                trees.push(tree);
+            }
+
+            let mut next_id = pack::highest_id(&writer.file().root_entries).checked_add(1);
+            let mut failure = None;
+            for tree in &trees {
+                if let Err(e) = pack::add_tree(&mut writer, tree, &opts.directory, &mut next_id) {
+                    failure = Some(Failure::new(FailureKind::Unsuccessful, e));
+                    break;
+                }
+            }
+
+            // the directory blocks are written even after a failure, as the new data may already
+            // have overwritten the previous ones
+            finish_rez_writer(writer, &opts.rez_file)?;
+            if let Some(failure) = failure {
+                return Err(failure);
+            }
+        },
+        Mode::Rm(opts) => {
+            let mut writer = open_rez_writer(&opts.rez_file)?;
+
+            // nothing is written before all the paths have been found
+            for path in &opts.paths {
+                writer.remove(path)
+                    .map_err(|e| Failure::rez_modify(&opts.rez_file, e))?;
+            }
+            if opts.compact {
+                writer.compact()
+                    .map_err(|e| Failure::rez_write(&opts.rez_file, e))?;
+            }
+
+            finish_rez_writer(writer, &opts.rez_file)?;
+        },
+        Mode::Mv(opts) => {
+            let mut writer = open_rez_writer(&opts.rez_file)?;
+            writer.rename(&opts.from, &opts.to)
+                .map_err(|e| Failure::rez_modify(&opts.rez_file, e))?;
+
+            finish_rez_writer(writer, &opts.rez_file)?;
+        },
+        Mode::Diff(opts) => {
+            let (mut old_file, old_rez_file) = open_rez_file(&opts.old_rez_file, opts.old_base_offset)?;
+            let (mut new_file, new_rez_file) = open_rez_file(&opts.new_rez_file, opts.new_base_offset)?;
+
+            let readers = if opts.content { Some((&mut old_file, &mut new_file)) } else { None };
+            let changes = diff::diff_files(&old_rez_file, &new_rez_file, readers)
+                .map_err(|e| Failure::new(
+                    FailureKind::Io,
+                    format!("failed to compare {} and {}: {}", opts.old_rez_file.display(), opts.new_rez_file.display(), e),
+                ))?;
+            for change in &changes {
+                match change {
+                    diff::Change::Added { path, size } => println!("A {} ({} bytes)", path, size),
+                    diff::Change::Removed { path, size } => println!("D {} ({} bytes)", path, size),
+                    diff::Change::Modified { path, old_size, new_size } => println!("M {} ({} -> {} bytes)", path, old_size, new_size),
+                }
+            }
+            if !changes.is_empty() {
+                return Err(Failure::negative());
+            }
+        },
+        Mode::Merge(opts) => {
+            if !opts.base_offsets.is_empty() && opts.base_offsets.len() != opts.rez_files.len() {
+                return Err(Failure::new(
+                    FailureKind::Usage,
+                    format!("{} base offsets given for {} REZ files", opts.base_offsets.len(), opts.rez_files.len()),
+                ));
+            }
+            let mut inputs = Vec::with_capacity(opts.rez_files.len());
+            for (index, path) in opts.rez_files.iter().enumerate() {
+                let base_offset = opts.base_offsets.get(index).copied().unwrap_or(0);
+                let (file, rez_file) = open_rez_file(path, base_offset)?;
+                inputs.push((rez_file, file));
+            }
+
+            let time = SystemTime::now().duration_since(UNIX_EPOCH)
+                .ok()
+                .and_then(|d| u32::try_from(d.as_secs()).ok())
+                .unwrap_or(0);
+            let output = File::create(&opts.output)
+                .map_err(|e| Failure::io("create", &opts.output, e))?;
+            let mut writer = rez::Writer::new(output, &inputs[0].0.file_type, &inputs[0].0.user_title, time)
+                .map_err(|e| Failure::rez_write(&opts.output, e))?;
+            merge::merge_files(&mut inputs, &mut writer, opts.on_conflict)
+                .map_err(|e| {
+                    let kind = if e.is_io() { FailureKind::Io } else { FailureKind::Unsuccessful };
+                    Failure::new(kind, format!("failed to merge REZ files: {}", e))
+                })?;
+            writer.finish()
+                .map_err(|e| Failure::rez_write(&opts.output, e))?;
+        },
+        Mode::Mount(opts) => {
+            #[cfg(all(feature = "fuse", target_os = "linux"))]
+            {
+                let (file, rez_file) = open_rez_file(&opts.input.rez_file, opts.input.base_offset)?;
+                let fs = mount::RezFs::new(file, &rez_file);
+                let device = mount::mount(&opts.mountpoint, &mount::fs_name(&opts.input.rez_file))
+                    .map_err(|e| Failure::io("mount", &opts.mountpoint, e))?;
+                info!("mounted {} on {}", opts.input.rez_file.display(), opts.mountpoint.display());
+                fs.serve(&device)
+                    .map_err(|e| Failure::new(FailureKind::Io, format!("failed to serve FUSE requests: {}", e)))?;
+            }
+            #[cfg(not(all(feature = "fuse", target_os = "linux")))]
+            {
+                let _ = opts;
+                return Err(Failure::new(
+                    FailureKind::Usage,
+                    "this build of lithrez does not support mounting; build it on Linux with the \"fuse\" feature",
+                ));
+            }
+        },
+        Mode::Serve(opts) => {
+            let (file, rez_file) = open_rez_file(&opts.input.rez_file, opts.input.base_offset)?;
+            let server = tiny_http::Server::http(&opts.address)
+                .map_err(|e| Failure::new(FailureKind::Io, format!("failed to listen on {}: {}", opts.address, e)))?;
+            info!("serving {} at http://{}/", opts.input.rez_file.display(), server.server_addr());
+            serve::serve(&server, &file, &rez_file);
+        },
+        Mode::ToZip(opts) => {
+            let filter = resource_filter(&opts.filter)?;
+            let (mut file, rez_file) = open_rez_file(&opts.input.rez_file, opts.input.base_offset)?;
+            if opts.on_short_read == ShortReadOpt::Error {
+                check_short_resources(&rez_file, &filter, &opts.input.rez_file)?;
+            }
+            let output = File::create(&opts.zip_file)
+                .map_err(|e| Failure::io("create", &opts.zip_file, e))?;
+            if let Err(e) = zipfile::write_zip(&mut file, &rez_file, &filter, output, !opts.store, opts.on_short_read == ShortReadOpt::Truncate) {
+                let _ = std::fs::remove_file(&opts.zip_file);
+                return Err(Failure::new(FailureKind::Io, format!("failed to write ZIP file {}: {}", opts.zip_file.display(), e)));
+            }
+        },
+        Mode::Has(opts) => {
+            let (_file, rez_file) = open_rez_file(&opts.input.rez_file, opts.input.base_offset)?;
+            if !rez_file.contains(&opts.path) {
+                return Err(Failure::negative());
+            }
+        },
+        Mode::Pack(opts) => {
+            let tree = pack::scan_directory(&opts.input_directory)
+                .map_err(|e| Failure::new(FailureKind::Io, e))?;
+            write_new_rez_file(tree.entries, &opts.new_rez, &opts.rez_file, |path| {
+                info!("packing {}", path);
+                File::open(&tree.source_paths[path])
+            })?;
+        },
+        Mode::ToTar(opts) => {
+            let filter = resource_filter(&opts.filter)?;
+            let (mut file, rez_file) = open_rez_file(&opts.input.rez_file, opts.input.base_offset)?;
+            if opts.on_short_read == ShortReadOpt::Error {
+                check_short_resources(&rez_file, &filter, &opts.input.rez_file)?;
+            }
+            let truncate_short = opts.on_short_read == ShortReadOpt::Truncate;
+            let result = if opts.tar_file.as_os_str() == "-" {
+                tarfile::write_tar(&mut file, &rez_file, &filter, std::io::stdout().lock(), truncate_short)
+                    .and_then(|mut stdout| stdout.flush())
+            } else {
+                let output = File::create(&opts.tar_file)
+                    .map_err(|e| Failure::io("create", &opts.tar_file, e))?;
+                tarfile::write_tar(&mut file, &rez_file, &filter, std::io::BufWriter::new(output), truncate_short)
+                    .and_then(|mut output| output.flush())
+            };
+            match result {
+                Ok(()) => {},
+                // e.g. the consumer of the stream has stopped reading
+                Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => {},
+                Err(e) => {
+                    return Err(Failure::new(FailureKind::Io, format!("failed to write tar stream: {}", e)));
+                },
+            }
+        },
+        Mode::FromZip(opts) => {
+            let zip_file = File::open(&opts.zip_file)
+                .map_err(|e| Failure::io("open", &opts.zip_file, e))?;
+            let mut archive = zip::ZipArchive::new(zip_file)
+                .map_err(|e| Failure::new(FailureKind::InvalidInput, format!("failed to read ZIP file {}: {}", opts.zip_file.display(), e)))?;
+            let (entries, indexes) = zipfile::scan_zip(&mut archive)
+                .map_err(|e| Failure::new(FailureKind::InvalidInput, e))?;
+            write_new_rez_file(entries, &opts.new_rez, &opts.rez_file, |path| {
+                info!("packing {}", path);
+                zipfile::read_zip_entry(&mut archive, indexes[path])
+            })?;
         },
     }
+    Ok(())
 }
@@ -0,0 +1,97 @@
+//! Combining multiple REZ files into one, as LithTech games layer the REZ files they load.
+
+
+use std::collections::HashMap;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use clap::ValueEnum;
+use log::info;
+
+use crate::rez;
+
+
+/// Which resource to keep if multiple REZ files contain a resource at the same path.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd, ValueEnum)]
+pub enum ConflictPolicy {
+    /// Keep the resource from the REZ file given first.
+    FirstWins,
+
+    /// Keep the resource from the REZ file given last, as if the REZ files were loaded in order
+    /// with later ones overriding earlier ones.
+    #[default] LastWins,
+}
+
+
+/// Writes the entries of all the given REZ files into the writer.
+///
+/// Each input consists of a REZ file and the reader from which it has been read. Directories are
+/// merged; of the resources at the same path, only one is kept according to the policy.
+pub fn merge_files<R, W>(inputs: &mut [(rez::File, R)], writer: &mut rez::Writer<W>, policy: ConflictPolicy) -> Result<(), rez::Error>
+    where
+        R: Read + Seek,
+        W: Write + Seek,
+{
+    // decide which input provides each resource
+    let mut chosen_inputs = HashMap::new();
+    for (input_index, (rez_file, _reader)) in inputs.iter().enumerate() {
+        choose_inputs(rez_file, input_index, policy, &mut chosen_inputs);
+    }
+
+    for (input_index, (rez_file, reader)) in inputs.iter_mut().enumerate() {
+        let base_offset = rez_file.base_offset;
+        add_entries_recursive(&rez_file.root_entries, "", input_index, &chosen_inputs, base_offset, reader, writer)?;
+    }
+    Ok(())
+}
+
+
+fn choose_inputs(rez_file: &rez::File, input_index: usize, policy: ConflictPolicy, chosen_inputs: &mut HashMap<String, usize>) {
+    for (entry_path, entry) in rez_file.walk() {
+        if !entry.is_resource() {
+            continue;
+        }
+        match policy {
+            ConflictPolicy::FirstWins => {
+                chosen_inputs.entry(entry_path).or_insert(input_index);
+            },
+            ConflictPolicy::LastWins => {
+                chosen_inputs.insert(entry_path, input_index);
+            },
+        }
+    }
+}
+
+fn add_entries_recursive<R, W>(
+    entries: &[rez::Entry],
+    base_path: &str,
+    input_index: usize,
+    chosen_inputs: &HashMap<String, usize>,
+    base_offset: u64,
+    reader: &mut R,
+    writer: &mut rez::Writer<W>,
+) -> Result<(), rez::Error>
+    where
+        R: Read + Seek,
+        W: Write + Seek,
+{
+    for entry in entries {
+        let entry_path = rez::join_path(base_path, &entry.name());
+        match entry {
+            rez::Entry::Directory(dir) => {
+                writer.add_directory(&entry_path, dir.header.time)?;
+                add_entries_recursive(&dir.entries, &entry_path, input_index, chosen_inputs, base_offset, reader, writer)?;
+            },
+            rez::Entry::Resource(res) => {
+                if chosen_inputs.get(&entry_path) != Some(&input_index) {
+                    continue;
+                }
+                info!("merging {}", entry_path);
+                reader.seek(SeekFrom::Start(base_offset + u64::from(res.header.position)))?;
+                let mut data = (&mut *reader).take(res.header.size.into());
+                writer.add_resource(base_path, res.clone(), &mut data)?;
+            },
+        }
+    }
+    Ok(())
+}
+
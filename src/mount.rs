@@ -0,0 +1,602 @@
+//! Exposes a REZ file as a read-only file system using FUSE (Filesystem in Userspace).
+//!
+//! The FUSE protocol is spoken directly with the kernel through `/dev/fuse`. The file system is
+//! mounted using `mount(2)` if the process has the necessary privileges and using `fusermount3`
+//! (or `fusermount`) otherwise. Each directory and resource becomes an inode; the data of the
+//! resources is read from the REZ file on demand.
+
+
+use std::ffi::CString;
+use std::fs::{File, OpenOptions};
+use std::io::{self, IoSliceMut};
+use std::mem::MaybeUninit;
+use std::os::fd::{AsFd, AsRawFd, OwnedFd};
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+use std::process::Command;
+
+use lithrez::rez;
+use log::warn;
+use rustix::io::{Errno, FdFlags};
+use rustix::mount::MountFlags;
+use rustix::net::{AddressFamily, RecvAncillaryBuffer, RecvAncillaryMessage, RecvFlags, SocketFlags, SocketType};
+
+
+const KERNEL_VERSION: u32 = 7;
+const KERNEL_MINOR_VERSION: u32 = 31;
+
+const OPCODE_LOOKUP: u32 = 1;
+const OPCODE_FORGET: u32 = 2;
+const OPCODE_GETATTR: u32 = 3;
+const OPCODE_OPEN: u32 = 14;
+const OPCODE_READ: u32 = 15;
+const OPCODE_STATFS: u32 = 17;
+const OPCODE_RELEASE: u32 = 18;
+const OPCODE_FLUSH: u32 = 25;
+const OPCODE_INIT: u32 = 26;
+const OPCODE_OPENDIR: u32 = 27;
+const OPCODE_READDIR: u32 = 28;
+const OPCODE_RELEASEDIR: u32 = 29;
+const OPCODE_ACCESS: u32 = 34;
+const OPCODE_INTERRUPT: u32 = 36;
+const OPCODE_DESTROY: u32 = 38;
+const OPCODE_BATCH_FORGET: u32 = 42;
+
+const IN_HEADER_LENGTH: usize = 40;
+const OUT_HEADER_LENGTH: usize = 16;
+const MAX_WRITE: u32 = 128 * 1024;
+const BUFFER_SIZE: usize = MAX_WRITE as usize + 4096;
+const FOPEN_KEEP_CACHE: u32 = 1 << 1;
+const O_ACCMODE: u32 = 0o3;
+const DT_DIR: u32 = 4;
+const DT_REG: u32 = 8;
+const BLOCK_SIZE: u64 = 512;
+const ROOT_INODE: u64 = 1;
+
+/// How long the kernel may cache names and attributes, in seconds. The file system never changes,
+/// so this can be long.
+const VALID_SECONDS: u64 = 3600;
+
+
+#[derive(Clone, Debug)]
+enum NodeKind {
+    Directory { children: Vec<u64> },
+    Resource { offset: u64, size: u64 },
+}
+
+#[derive(Clone, Debug)]
+struct Node {
+    parent: u64,
+    name: Vec<u8>,
+    time: u32,
+    kind: NodeKind,
+}
+
+
+/// The file system exposing the contents of a REZ file. Inode `n` is the node at index `n - 1`.
+#[derive(Debug)]
+pub struct RezFs {
+    file: File,
+    nodes: Vec<Node>,
+    total_size: u64,
+    uid: u32,
+    gid: u32,
+}
+impl RezFs {
+    /// Creates a file system exposing the given REZ file, whose data is read from the given file.
+    pub fn new(file: File, rez_file: &rez::File) -> Self {
+        let root = Node {
+            parent: ROOT_INODE,
+            name: Vec::new(),
+            time: rez_file.time,
+            kind: NodeKind::Directory { children: Vec::new() },
+        };
+        let mut fs = Self {
+            file,
+            nodes: vec![root],
+            total_size: 0,
+            uid: rustix::process::getuid().as_raw(),
+            gid: rustix::process::getgid().as_raw(),
+        };
+        fs.add_entries_recursive(ROOT_INODE, rez_file, &rez_file.root_entries);
+        fs
+    }
+
+    fn add_entries_recursive(&mut self, parent: u64, rez_file: &rez::File, entries: &[rez::Entry]) {
+        for entry in entries {
+            let kind = match entry {
+                rez::Entry::Directory(_) => NodeKind::Directory { children: Vec::new() },
+                rez::Entry::Resource(res) => {
+                    self.total_size += u64::from(res.header.size);
+                    NodeKind::Resource {
+                        offset: rez_file.data_offset(res),
+                        size: res.header.size.into(),
+                    }
+                },
+            };
+            let time = match entry {
+                rez::Entry::Directory(dir) => dir.header.time,
+                rez::Entry::Resource(res) => res.header.time,
+            };
+            self.nodes.push(Node {
+                parent,
+                name: entry.name().as_bytes().to_vec(),
+                time,
+                kind,
+            });
+            let inode: u64 = self.nodes.len().try_into().unwrap();
+            if let NodeKind::Directory { children } = &mut self.nodes[index_of(parent)].kind {
+                children.push(inode);
+            }
+            if let rez::Entry::Directory(dir) = entry {
+                self.add_entries_recursive(inode, rez_file, &dir.entries);
+            }
+        }
+    }
+
+    fn node(&self, inode: u64) -> Result<&Node, Errno> {
+        if inode == 0 {
+            return Err(Errno::NOENT);
+        }
+        self.nodes.get(index_of(inode)).ok_or(Errno::NOENT)
+    }
+
+    /// Serves requests from the kernel until the file system is unmounted.
+    pub fn serve(&self, device: &OwnedFd) -> io::Result<()> {
+        let mut buf = vec![0u8; BUFFER_SIZE];
+        loop {
+            let length = match rustix::io::read(device, &mut buf[..]) {
+                Ok(length) => length,
+                // the request has been interrupted before it could be read
+                Err(Errno::NOENT) | Err(Errno::INTR) | Err(Errno::AGAIN) => continue,
+                // the file system has been unmounted
+                Err(Errno::NODEV) => return Ok(()),
+                Err(e) => return Err(e.into()),
+            };
+            let request = &buf[..length];
+            if request.len() < IN_HEADER_LENGTH {
+                warn!("ignoring truncated FUSE request of {} bytes", request.len());
+                continue;
+            }
+            let opcode = read_u32(request, 4);
+            let unique = read_u64(request, 8);
+            let inode = read_u64(request, 16);
+            let body = &request[IN_HEADER_LENGTH..];
+
+            let Some(reply) = self.handle(opcode, inode, body) else { continue };
+            let (error, payload) = match reply {
+                Ok(payload) => (0, payload),
+                Err(errno) => (-errno.raw_os_error(), Vec::new()),
+            };
+            let mut out = Vec::with_capacity(OUT_HEADER_LENGTH + payload.len());
+            out.extend_from_slice(&u32::try_from(OUT_HEADER_LENGTH + payload.len()).unwrap().to_le_bytes());
+            out.extend_from_slice(&i32::to_le_bytes(error));
+            out.extend_from_slice(&unique.to_le_bytes());
+            out.extend_from_slice(&payload);
+            match rustix::io::write(device, &out) {
+                // the request has been interrupted in the meantime
+                Ok(_) | Err(Errno::NOENT) => {},
+                Err(e) => return Err(e.into()),
+            }
+            if opcode == OPCODE_DESTROY {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Handles a single request, returning the payload of the reply or `None` if the request must
+    /// not be replied to.
+    fn handle(&self, opcode: u32, inode: u64, body: &[u8]) -> Option<Result<Vec<u8>, Errno>> {
+        let reply = match opcode {
+            OPCODE_FORGET | OPCODE_BATCH_FORGET | OPCODE_INTERRUPT => return None,
+            OPCODE_INIT => init(body),
+            OPCODE_LOOKUP => self.lookup(inode, body),
+            OPCODE_GETATTR => self.node(inode).map(|_| {
+                let mut out = Vec::with_capacity(104);
+                out.extend_from_slice(&VALID_SECONDS.to_le_bytes());
+                out.extend_from_slice(&[0; 8]); // attr_valid_nsec, dummy
+                self.write_attr(&mut out, inode);
+                out
+            }),
+            OPCODE_OPENDIR => self.node(inode).and_then(|node| match node.kind {
+                NodeKind::Directory { .. } => Ok(open_out(0)),
+                NodeKind::Resource { .. } => Err(Errno::NOTDIR),
+            }),
+            OPCODE_OPEN => self.node(inode).and_then(|node| match node.kind {
+                NodeKind::Directory { .. } => Err(Errno::ISDIR),
+                NodeKind::Resource { .. } if body.len() >= 4 && read_u32(body, 0) & O_ACCMODE != 0 => Err(Errno::ROFS),
+                NodeKind::Resource { .. } => Ok(open_out(FOPEN_KEEP_CACHE)),
+            }),
+            OPCODE_READ => self.read(inode, body),
+            OPCODE_READDIR => self.read_dir(inode, body),
+            OPCODE_STATFS => Ok(self.statfs()),
+            OPCODE_RELEASE | OPCODE_RELEASEDIR | OPCODE_FLUSH | OPCODE_ACCESS | OPCODE_DESTROY => Ok(Vec::new()),
+            _ => Err(Errno::NOSYS),
+        };
+        Some(reply)
+    }
+
+    fn lookup(&self, parent: u64, body: &[u8]) -> Result<Vec<u8>, Errno> {
+        let name = body.split(|b| *b == 0).next().unwrap_or(body);
+        let NodeKind::Directory { children } = &self.node(parent)?.kind else { return Err(Errno::NOTDIR) };
+        let inode = children.iter()
+            .copied()
+            .find(|child| self.nodes[index_of(*child)].name == name)
+            .ok_or(Errno::NOENT)?;
+
+        let mut out = Vec::with_capacity(128);
+        out.extend_from_slice(&inode.to_le_bytes());
+        out.extend_from_slice(&0u64.to_le_bytes()); // generation
+        out.extend_from_slice(&VALID_SECONDS.to_le_bytes()); // entry_valid
+        out.extend_from_slice(&VALID_SECONDS.to_le_bytes()); // attr_valid
+        out.extend_from_slice(&[0; 8]); // entry_valid_nsec, attr_valid_nsec
+        self.write_attr(&mut out, inode);
+        Ok(out)
+    }
+
+    fn read(&self, inode: u64, body: &[u8]) -> Result<Vec<u8>, Errno> {
+        let NodeKind::Resource { offset, size } = self.node(inode)?.kind else { return Err(Errno::ISDIR) };
+        if body.len() < 20 {
+            return Err(Errno::INVAL);
+        }
+        let read_offset = read_u64(body, 8);
+        let read_size = read_u32(body, 16);
+        let length = size.saturating_sub(read_offset).min(read_size.into());
+
+        let mut data = vec![0u8; length.try_into().unwrap()];
+        let mut filled = 0;
+        while filled < data.len() {
+            let position = offset + read_offset + u64::try_from(filled).unwrap();
+            match self.file.read_at(&mut data[filled..], position) {
+                // the REZ file ends before the data does
+                Ok(0) => break,
+                Ok(count) => filled += count,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(e) => {
+                    warn!("failed to read from REZ file: {}", e);
+                    return Err(Errno::IO);
+                },
+            }
+        }
+        data.truncate(filled);
+        Ok(data)
+    }
+
+    fn read_dir(&self, inode: u64, body: &[u8]) -> Result<Vec<u8>, Errno> {
+        let node = self.node(inode)?;
+        let NodeKind::Directory { children } = &node.kind else { return Err(Errno::NOTDIR) };
+        if body.len() < 20 {
+            return Err(Errno::INVAL);
+        }
+        let start = read_u64(body, 8);
+        let max_size: usize = read_u32(body, 16).try_into().unwrap();
+
+        let dot_entries = [(inode, &b"."[..]), (node.parent, &b".."[..])];
+        let entries = dot_entries.into_iter()
+            .chain(children.iter().map(|child| (*child, &self.nodes[index_of(*child)].name[..])));
+        let mut out = Vec::new();
+        for (index, (entry_inode, name)) in entries.enumerate().skip(start.try_into().unwrap_or(usize::MAX)) {
+            let padded_length = (24 + name.len()).next_multiple_of(8);
+            if out.len() + padded_length > max_size {
+                break;
+            }
+            let entry_type = match self.nodes[index_of(entry_inode)].kind {
+                NodeKind::Directory { .. } => DT_DIR,
+                NodeKind::Resource { .. } => DT_REG,
+            };
+            out.extend_from_slice(&entry_inode.to_le_bytes());
+            // the offset at which to continue reading after this entry
+            out.extend_from_slice(&u64::try_from(index + 1).unwrap().to_le_bytes());
+            out.extend_from_slice(&u32::try_from(name.len()).unwrap().to_le_bytes());
+            out.extend_from_slice(&entry_type.to_le_bytes());
+            out.extend_from_slice(name);
+            out.resize(out.len().next_multiple_of(8), 0);
+        }
+        Ok(out)
+    }
+
+    fn statfs(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(80);
+        out.extend_from_slice(&self.total_size.div_ceil(BLOCK_SIZE).to_le_bytes()); // blocks
+        out.extend_from_slice(&0u64.to_le_bytes()); // bfree
+        out.extend_from_slice(&0u64.to_le_bytes()); // bavail
+        out.extend_from_slice(&u64::try_from(self.nodes.len()).unwrap().to_le_bytes()); // files
+        out.extend_from_slice(&0u64.to_le_bytes()); // ffree
+        out.extend_from_slice(&u32::try_from(BLOCK_SIZE).unwrap().to_le_bytes()); // bsize
+        out.extend_from_slice(&255u32.to_le_bytes()); // namelen
+        out.extend_from_slice(&u32::try_from(BLOCK_SIZE).unwrap().to_le_bytes()); // frsize
+        out.resize(80, 0);
+        out
+    }
+
+    /// Appends the attributes of the given inode in the layout of `struct fuse_attr`.
+    fn write_attr(&self, out: &mut Vec<u8>, inode: u64) {
+        let node = &self.nodes[index_of(inode)];
+        let (size, mode, link_count) = match &node.kind {
+            NodeKind::Directory { children } => {
+                let subdirectories = children.iter()
+                    .filter(|child| matches!(self.nodes[index_of(**child)].kind, NodeKind::Directory { .. }))
+                    .count();
+                (0, 0o040555, 2 + u32::try_from(subdirectories).unwrap())
+            },
+            NodeKind::Resource { size, .. } => (*size, 0o100444, 1),
+        };
+        let time = u64::from(node.time);
+        out.extend_from_slice(&inode.to_le_bytes());
+        out.extend_from_slice(&size.to_le_bytes());
+        out.extend_from_slice(&size.div_ceil(BLOCK_SIZE).to_le_bytes());
+        out.extend_from_slice(&time.to_le_bytes()); // atime
+        out.extend_from_slice(&time.to_le_bytes()); // mtime
+        out.extend_from_slice(&time.to_le_bytes()); // ctime
+        out.extend_from_slice(&[0; 12]); // atimensec, mtimensec, ctimensec
+        out.extend_from_slice(&u32::to_le_bytes(mode));
+        out.extend_from_slice(&link_count.to_le_bytes());
+        out.extend_from_slice(&self.uid.to_le_bytes());
+        out.extend_from_slice(&self.gid.to_le_bytes());
+        out.extend_from_slice(&0u32.to_le_bytes()); // rdev
+        out.extend_from_slice(&4096u32.to_le_bytes()); // blksize
+        out.extend_from_slice(&0u32.to_le_bytes()); // flags
+    }
+}
+
+
+fn index_of(inode: u64) -> usize {
+    usize::try_from(inode - 1).unwrap()
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset+4].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset+8].try_into().unwrap())
+}
+
+fn init(body: &[u8]) -> Result<Vec<u8>, Errno> {
+    if body.len() < 12 {
+        return Err(Errno::INVAL);
+    }
+    let major = read_u32(body, 0);
+    let max_readahead = read_u32(body, 8);
+    if major < KERNEL_VERSION {
+        return Err(Errno::PROTO);
+    }
+
+    let mut out = Vec::with_capacity(64);
+    out.extend_from_slice(&KERNEL_VERSION.to_le_bytes());
+    out.extend_from_slice(&KERNEL_MINOR_VERSION.to_le_bytes());
+    out.extend_from_slice(&max_readahead.to_le_bytes());
+    out.extend_from_slice(&0u32.to_le_bytes()); // flags
+    out.extend_from_slice(&16u16.to_le_bytes()); // max_background
+    out.extend_from_slice(&12u16.to_le_bytes()); // congestion_threshold
+    out.extend_from_slice(&MAX_WRITE.to_le_bytes());
+    out.extend_from_slice(&1u32.to_le_bytes()); // time_gran
+    out.resize(64, 0);
+    Ok(out)
+}
+
+fn open_out(open_flags: u32) -> Vec<u8> {
+    let mut out = Vec::with_capacity(16);
+    out.extend_from_slice(&0u64.to_le_bytes()); // fh
+    out.extend_from_slice(&open_flags.to_le_bytes());
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out
+}
+
+
+/// Mounts a read-only FUSE file system at the given mount point, returning the file descriptor
+/// through which the kernel sends its requests.
+pub fn mount(mountpoint: &Path, fs_name: &str) -> io::Result<OwnedFd> {
+    // commas separate mount options
+    let fs_name = fs_name.replace(',', "_");
+
+    let device: OwnedFd = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/fuse")?
+        .into();
+    let options = format!(
+        "fd={},rootmode=40000,user_id={},group_id={}",
+        device.as_raw_fd(), rustix::process::getuid().as_raw(), rustix::process::getgid().as_raw(),
+    );
+    let options = CString::new(options).unwrap();
+    let flags = MountFlags::RDONLY | MountFlags::NOSUID | MountFlags::NODEV;
+    match rustix::mount::mount(fs_name.as_str(), mountpoint, "fuse.lithrez", flags, options.as_c_str()) {
+        Ok(()) => Ok(device),
+        Err(Errno::PERM) | Err(Errno::ACCESS) => mount_with_fusermount(mountpoint, &fs_name),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Mounts the file system using the setuid helper shipped with FUSE, which passes the file
+/// descriptor back through a socket.
+fn mount_with_fusermount(mountpoint: &Path, fs_name: &str) -> io::Result<OwnedFd> {
+    let (ours, theirs) = rustix::net::socketpair(AddressFamily::UNIX, SocketType::STREAM, SocketFlags::CLOEXEC, None)?;
+    // the helper must inherit its end
+    rustix::io::fcntl_setfd(&theirs, FdFlags::empty())?;
+
+    let options = format!("ro,nosuid,nodev,fsname={},subtype=lithrez", fs_name);
+    let mut not_found = None;
+    for program in ["fusermount3", "fusermount"] {
+        let status = Command::new(program)
+            .arg("-o").arg(&options)
+            .arg("--").arg(mountpoint)
+            .env("_FUSE_COMMFD", theirs.as_raw_fd().to_string())
+            .status();
+        match status {
+            Ok(status) if status.success() => return receive_fd(&ours),
+            Ok(status) => return Err(io::Error::other(format!("{} failed ({})", program, status))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => not_found = Some(e),
+            Err(e) => return Err(e),
+        }
+    }
+    Err(not_found.unwrap())
+}
+
+fn receive_fd<Fd: AsFd>(socket: Fd) -> io::Result<OwnedFd> {
+    let mut space = [MaybeUninit::uninit(); rustix::cmsg_space!(ScmRights(1))];
+    let mut control = RecvAncillaryBuffer::new(&mut space);
+    let mut byte = [0u8];
+    rustix::net::recvmsg(socket, &mut [IoSliceMut::new(&mut byte)], &mut control, RecvFlags::empty())?;
+    for message in control.drain() {
+        if let RecvAncillaryMessage::ScmRights(mut fds) = message {
+            if let Some(fd) = fds.next() {
+                return Ok(fd);
+            }
+        }
+    }
+    Err(io::Error::other("fusermount did not pass a FUSE file descriptor"))
+}
+
+/// Returns the name under which to mount the given REZ file.
+pub fn fs_name(rez_path: &Path) -> String {
+    rez_path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "lithrez".to_owned())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIME: u32 = 1600000000;
+
+    /// Builds a file system with the layout `sounds/boom.wav` (inodes 2 and 3) and `README.txt`
+    /// (inode 4).
+    fn sample_fs(name: &str) -> RezFs {
+        let path = std::env::temp_dir().join(format!("lithrez-mount-{}-{}.rez", std::process::id(), name));
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&path).unwrap();
+        // the open file remains usable once it has been unlinked
+        std::fs::remove_file(&path).unwrap();
+
+        let mut writer = rez::Writer::new(file, "test type", "test title", TIME).unwrap();
+        writer.add_directory("sounds", TIME + 100).unwrap();
+        writer.add_resource("sounds", resource("boom", "wav", TIME + 200), &mut &b"boom"[..]).unwrap();
+        writer.add_resource("", resource("README", "txt", TIME + 300), &mut &b"hello world\n"[..]).unwrap();
+        let (file, rez_file) = writer.finish().unwrap();
+        RezFs::new(file, &rez_file)
+    }
+
+    fn resource(name: &str, extension: &str, time: u32) -> rez::Resource {
+        rez::Resource {
+            header: rez::EntryHeader {
+                entry_type: rez::EntryType::Resource,
+                position: 0,
+                size: 0,
+                time,
+            },
+            id: 0,
+            extension: extension.to_owned(),
+            extension_bytes: [0; 4],
+            name: name.to_owned(),
+            description: String::new(),
+            keys: Vec::new(),
+        }
+    }
+
+    /// Returns `struct fuse_attr` as the kernel expects it.
+    fn attr(fs: &RezFs, inode: u64, size: u64, blocks: u64, time: u32, mode: u32, link_count: u32) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&inode.to_le_bytes());
+        out.extend_from_slice(&size.to_le_bytes());
+        out.extend_from_slice(&blocks.to_le_bytes());
+        for _ in 0..3 {
+            out.extend_from_slice(&u64::from(time).to_le_bytes());
+        }
+        out.extend_from_slice(&[0; 12]);
+        out.extend_from_slice(&mode.to_le_bytes());
+        out.extend_from_slice(&link_count.to_le_bytes());
+        out.extend_from_slice(&fs.uid.to_le_bytes());
+        out.extend_from_slice(&fs.gid.to_le_bytes());
+        out.extend_from_slice(&0u32.to_le_bytes());
+        out.extend_from_slice(&4096u32.to_le_bytes());
+        out.extend_from_slice(&0u32.to_le_bytes());
+        assert_eq!(out.len(), 88);
+        out
+    }
+
+    /// Returns `struct fuse_read_in`, which is also used for reading directories.
+    fn read_in(offset: u64, size: u32) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&0u64.to_le_bytes()); // fh
+        body.extend_from_slice(&offset.to_le_bytes());
+        body.extend_from_slice(&size.to_le_bytes());
+        body.resize(40, 0);
+        body
+    }
+
+    /// Returns `struct fuse_dirent` followed by the name, padded to a multiple of 8 bytes.
+    fn dirent(inode: u64, next_offset: u64, entry_type: u32, name: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&inode.to_le_bytes());
+        out.extend_from_slice(&next_offset.to_le_bytes());
+        out.extend_from_slice(&u32::try_from(name.len()).unwrap().to_le_bytes());
+        out.extend_from_slice(&entry_type.to_le_bytes());
+        out.extend_from_slice(name);
+        out.resize(out.len().next_multiple_of(8), 0);
+        out
+    }
+
+    #[test]
+    fn lookup() {
+        let fs = sample_fs("lookup");
+        let mut expected = Vec::new();
+        expected.extend_from_slice(&4u64.to_le_bytes()); // nodeid
+        expected.extend_from_slice(&0u64.to_le_bytes()); // generation
+        expected.extend_from_slice(&3600u64.to_le_bytes()); // entry_valid
+        expected.extend_from_slice(&3600u64.to_le_bytes()); // attr_valid
+        expected.extend_from_slice(&[0; 8]);
+        expected.extend_from_slice(&attr(&fs, 4, 12, 1, TIME + 300, 0o100444, 1));
+        assert_eq!(fs.handle(OPCODE_LOOKUP, ROOT_INODE, b"README.txt\0"), Some(Ok(expected)));
+
+        assert_eq!(fs.handle(OPCODE_LOOKUP, ROOT_INODE, b"readme.txt\0"), Some(Err(Errno::NOENT)));
+        assert_eq!(fs.handle(OPCODE_LOOKUP, 4, b"x\0"), Some(Err(Errno::NOTDIR)));
+        assert_eq!(fs.handle(OPCODE_LOOKUP, 99, b"x\0"), Some(Err(Errno::NOENT)));
+    }
+
+    #[test]
+    fn getattr() {
+        let fs = sample_fs("getattr");
+        let mut expected = Vec::new();
+        expected.extend_from_slice(&3600u64.to_le_bytes()); // attr_valid
+        expected.extend_from_slice(&[0; 8]);
+        expected.extend_from_slice(&attr(&fs, ROOT_INODE, 0, 0, TIME, 0o040555, 3));
+        assert_eq!(fs.handle(OPCODE_GETATTR, ROOT_INODE, &[]), Some(Ok(expected)));
+
+        let reply = fs.handle(OPCODE_GETATTR, 2, &[]).unwrap().unwrap();
+        assert_eq!(&reply[16..], attr(&fs, 2, 0, 0, TIME + 100, 0o040555, 2));
+        assert_eq!(fs.handle(OPCODE_GETATTR, 0, &[]), Some(Err(Errno::NOENT)));
+    }
+
+    #[test]
+    fn read() {
+        let fs = sample_fs("read");
+        assert_eq!(fs.handle(OPCODE_READ, 4, &read_in(0, 4096)), Some(Ok(b"hello world\n".to_vec())));
+        assert_eq!(fs.handle(OPCODE_READ, 4, &read_in(6, 3)), Some(Ok(b"wor".to_vec())));
+        assert_eq!(fs.handle(OPCODE_READ, 3, &read_in(1, 4096)), Some(Ok(b"oom".to_vec())));
+        assert_eq!(fs.handle(OPCODE_READ, 3, &read_in(100, 4096)), Some(Ok(Vec::new())));
+        assert_eq!(fs.handle(OPCODE_READ, 2, &read_in(0, 4096)), Some(Err(Errno::ISDIR)));
+        assert_eq!(fs.handle(OPCODE_READ, 4, &[0; 8]), Some(Err(Errno::INVAL)));
+    }
+
+    #[test]
+    fn readdir() {
+        let fs = sample_fs("readdir");
+        let mut expected = Vec::new();
+        expected.extend_from_slice(&dirent(ROOT_INODE, 1, DT_DIR, b"."));
+        expected.extend_from_slice(&dirent(ROOT_INODE, 2, DT_DIR, b".."));
+        expected.extend_from_slice(&dirent(2, 3, DT_DIR, b"sounds"));
+        expected.extend_from_slice(&dirent(4, 4, DT_REG, b"README.txt"));
+        assert_eq!(fs.handle(OPCODE_READDIR, ROOT_INODE, &read_in(0, 4096)), Some(Ok(expected)));
+
+        // continues at the given offset and stops before the entry that does not fit
+        let mut expected = Vec::new();
+        expected.extend_from_slice(&dirent(ROOT_INODE, 2, DT_DIR, b".."));
+        expected.extend_from_slice(&dirent(3, 3, DT_REG, b"boom.wav"));
+        assert_eq!(fs.handle(OPCODE_READDIR, 2, &read_in(1, 64)), Some(Ok(expected)));
+        assert_eq!(fs.handle(OPCODE_READDIR, 2, &read_in(1, 63)), Some(Ok(dirent(ROOT_INODE, 2, DT_DIR, b".."))));
+        assert_eq!(fs.handle(OPCODE_READDIR, 2, &read_in(3, 4096)), Some(Ok(Vec::new())));
+        assert_eq!(fs.handle(OPCODE_READDIR, 3, &read_in(0, 4096)), Some(Err(Errno::NOTDIR)));
+    }
+}
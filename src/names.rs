@@ -0,0 +1,61 @@
+//! Making names taken from REZ files safe to use as file names.
+//!
+//! REZ files may come from untrusted sources, and nothing prevents the names of their entries from
+//! containing path separators, `..` or names that have a special meaning on Windows.
+
+
+use std::borrow::Cow;
+
+
+/// The names of devices on Windows, which cannot be used as file names, with or without an
+/// extension.
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL",
+    "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+
+/// Rewrites a single component of a path so that it refers to a regular file or directory within
+/// the directory in which it is used.
+///
+/// Path separators, NULs and other control characters are replaced by underscores, as are the
+/// characters not allowed in file names on Windows when running there. `.`, `..` and empty names
+/// become underscores, and an underscore is appended to the stem of Windows device names such as
+/// `CON` or `nul.txt`. Returns the name unchanged if it is already safe.
+pub fn sanitize_name(name: &str) -> Cow<'_, str> {
+    let mut sanitized = replace_forbidden_chars(name).into_owned();
+
+    if sanitized.is_empty() || sanitized == "." || sanitized == ".." {
+        sanitized = "_".repeat(sanitized.len().max(1));
+    }
+
+    let stem_length = sanitized.find('.').unwrap_or(sanitized.len());
+    if RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(&sanitized[..stem_length])) {
+        sanitized.insert(stem_length, '_');
+    }
+
+    if sanitized == name {
+        Cow::Borrowed(name)
+    } else {
+        Cow::Owned(sanitized)
+    }
+}
+
+/// Replaces path separators, NULs and other control characters as well as, when running on
+/// Windows, the characters not allowed in file names there by underscores.
+pub fn replace_forbidden_chars(name: &str) -> Cow<'_, str> {
+    if !name.chars().any(is_forbidden) {
+        return Cow::Borrowed(name);
+    }
+    Cow::Owned(name.chars()
+        .map(|c| if is_forbidden(c) { '_' } else { c })
+        .collect())
+}
+
+fn is_forbidden(c: char) -> bool {
+    if c == '/' || c == '\\' || c.is_control() {
+        return true;
+    }
+    cfg!(windows) && matches!(c, '<' | '>' | ':' | '"' | '|' | '?' | '*')
+}
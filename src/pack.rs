@@ -0,0 +1,300 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, Metadata};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use clap::ValueEnum;
+
+use log::info;
+
+use crate::hashing::fnv1a_32;
+use crate::rez;
+
+
+/// The result of scanning a directory tree to be packed into a REZ file.
+#[derive(Debug, Default)]
+pub struct PackTree {
+    /// The entries to write into the REZ file.
+    pub entries: Vec<rez::Entry>,
+
+    /// Maps the path of each resource within the REZ file to the path of its source file.
+    pub source_paths: HashMap<String, PathBuf>,
+}
+
+/// How IDs are assigned to resources when packing.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd, ValueEnum)]
+pub enum IdStrategy {
+    /// Assign 1, 2, 3, ... in the order of the entries.
+    #[default] Sequential,
+
+    /// Derive the ID from a hash of the lowercased path, which stays stable between builds.
+    /// Collisions are resolved by taking the next free ID, in the order of the entries.
+    Hash,
+
+    /// Take the IDs from a manifest written during extraction. Files missing from the manifest
+    /// are assigned sequential IDs above the highest ID in the manifest.
+    FromManifest,
+}
+
+
+/// Scans the given directory and builds the corresponding REZ entry tree.
+///
+/// Directories become REZ directories and files become resources, with the part of the file name
+/// after the last dot taken as the extension. All resources have the ID 0; use [`assign_ids`] to
+/// assign actual IDs.
+pub fn scan_directory(input_dir: &Path) -> Result<PackTree, String> {
+    let mut tree = PackTree::default();
+    tree.entries = scan_directory_recursive(input_dir, "", &mut tree.source_paths)?;
+    Ok(tree)
+}
+
+/// Scans the given file or directory in order to add it to an existing REZ file.
+///
+/// A directory becomes a REZ directory of the same name containing the entries found by
+/// [`scan_directory`]; a file becomes a single resource.
+pub fn scan_path(path: &Path) -> Result<PackTree, String> {
+    let file_name = path.file_name()
+        .ok_or_else(|| format!("{} has no file name", path.display()))?
+        .to_str()
+        .ok_or_else(|| format!("file name of {} is not valid Unicode", path.display()))?
+        .to_owned();
+    let metadata = std::fs::metadata(path)
+        .map_err(|e| format!("failed to obtain metadata of {}: {}", path.display(), e))?;
+    let time = modification_time(&metadata);
+
+    let mut tree = PackTree::default();
+    if metadata.is_dir() {
+        let entries = scan_directory_recursive(path, &file_name, &mut tree.source_paths)?;
+        tree.entries.push(rez::Entry::Directory(rez::Directory {
+            header: rez::EntryHeader {
+                entry_type: rez::EntryType::Directory,
+                position: 0,
+                size: 0,
+                time,
+            },
+            name: file_name,
+            entries,
+            entries_loaded: true,
+        }));
+    } else {
+        let entry = rez::Entry::Resource(new_resource(path, &file_name, time)?);
+        tree.source_paths.insert(entry.name().into_owned(), path.to_owned());
+        tree.entries.push(entry);
+    }
+    Ok(tree)
+}
+
+/// Adds the entries of the given tree to a REZ file below the directory at `target_dir`, assigning
+/// consecutive IDs starting at `next_id`. `next_id` is `None` once all IDs have been used up.
+pub fn add_tree<W: Read + Write + Seek>(writer: &mut rez::Writer<W>, tree: &PackTree, target_dir: &str, next_id: &mut Option<u32>) -> Result<(), String> {
+    add_tree_recursive(writer, &tree.entries, "", target_dir, &tree.source_paths, next_id)
+}
+
+/// Returns the highest ID of all resources in the given entry tree, or 0 if it contains no
+/// resources.
+pub fn highest_id(entries: &[rez::Entry]) -> u32 {
+    entries.iter()
+        .map(|entry| match entry {
+            rez::Entry::Directory(dir) => highest_id(&dir.entries),
+            rez::Entry::Resource(res) => res.id,
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Assigns IDs to all resources in the given entry tree according to the given strategy.
+///
+/// `manifest` maps paths within the REZ file to IDs and is only consulted with
+/// [`IdStrategy::FromManifest`]. Fails if the IDs after the highest one in the manifest do not
+/// suffice for the resources missing from it.
+pub fn assign_ids(entries: &mut [rez::Entry], strategy: IdStrategy, manifest: &HashMap<String, u32>) -> Result<(), String> {
+    let mut resources = Vec::new();
+    collect_resources_recursive(entries, "", &mut resources);
+
+    match strategy {
+        IdStrategy::Sequential => {
+            for (id, (_path, res)) in (1..).zip(resources) {
+                res.id = id;
+            }
+        },
+        IdStrategy::Hash => {
+            let mut used_ids = HashSet::new();
+            for (path, res) in resources {
+                let mut id = fnv1a_32(path.to_lowercase().as_bytes());
+                while !used_ids.insert(id) {
+                    id = id.wrapping_add(1);
+                }
+                res.id = id;
+            }
+        },
+        IdStrategy::FromManifest => {
+            let mut next_id = manifest.values().max().map_or(Some(1), |m| m.checked_add(1));
+            for (path, res) in resources {
+                if let Some(id) = manifest.get(&path) {
+                    res.id = *id;
+                } else {
+                    res.id = next_id.ok_or_else(|| format!("no resource ID is left for {}", path))?;
+                    next_id = res.id.checked_add(1);
+                }
+            }
+        },
+    }
+    Ok(())
+}
+
+fn add_tree_recursive<W: Read + Write + Seek>(
+    writer: &mut rez::Writer<W>,
+    entries: &[rez::Entry],
+    base_path: &str,
+    target_dir: &str,
+    source_paths: &HashMap<String, PathBuf>,
+    next_id: &mut Option<u32>,
+) -> Result<(), String> {
+    let archive_dir = rez::join_path(target_dir, base_path);
+    for entry in entries {
+        let entry_path = rez::join_path(base_path, &entry.name());
+        match entry {
+            rez::Entry::Directory(dir) => {
+                let archive_path = rez::join_path(target_dir, &entry_path);
+                writer.add_directory(&archive_path, dir.header.time)
+                    .map_err(|e| format!("failed to add directory {}: {}", archive_path, e))?;
+                add_tree_recursive(writer, &dir.entries, &entry_path, target_dir, source_paths, next_id)?;
+            },
+            rez::Entry::Resource(res) => {
+                let source_path = &source_paths[&entry_path];
+                info!("adding {}", source_path.display());
+                let mut data = File::open(source_path)
+                    .map_err(|e| format!("failed to open {}: {}", source_path.display(), e))?;
+                let mut res = res.clone();
+                res.id = next_id.ok_or_else(|| format!("no resource ID is left for {}", source_path.display()))?;
+                *next_id = res.id.checked_add(1);
+                writer.add_resource(&archive_dir, res, &mut data)
+                    .map_err(|e| format!("failed to add {}: {}", source_path.display(), e))?;
+            },
+        }
+    }
+    Ok(())
+}
+
+fn collect_resources_recursive<'a>(entries: &'a mut [rez::Entry], base_path: &str, resources: &mut Vec<(String, &'a mut rez::Resource)>) {
+    for entry in entries {
+        let entry_path = rez::join_path(base_path, &entry.name());
+        match entry {
+            rez::Entry::Directory(dir) => collect_resources_recursive(&mut dir.entries, &entry_path, resources),
+            rez::Entry::Resource(res) => resources.push((entry_path, res)),
+        }
+    }
+}
+
+/// Writes a manifest mapping paths within a REZ file to resource IDs.
+///
+/// Each line contains the ID, a tab character and the path.
+pub fn write_manifest(path: &Path, ids: &[(String, u32)]) -> Result<(), io::Error> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    for (entry_path, id) in ids {
+        writeln!(writer, "{}\t{}", id, entry_path)?;
+    }
+    writer.flush()
+}
+
+/// Reads a manifest written by [`write_manifest`].
+pub fn read_manifest(path: &Path) -> Result<HashMap<String, u32>, io::Error> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut ret = HashMap::new();
+    for (line_index, line_res) in reader.lines().enumerate() {
+        let line = line_res?;
+        if line.is_empty() {
+            continue;
+        }
+        let id_and_path = line.split_once('\t')
+            .and_then(|(id_str, entry_path)| Some((id_str.parse::<u32>().ok()?, entry_path)));
+        let Some((id, entry_path)) = id_and_path else {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("line {} is invalid", line_index + 1)));
+        };
+        ret.insert(entry_path.to_owned(), id);
+    }
+    Ok(ret)
+}
+
+fn scan_directory_recursive(dir_path: &Path, base_path: &str, source_paths: &mut HashMap<String, PathBuf>) -> Result<Vec<rez::Entry>, String> {
+    let read_dir = std::fs::read_dir(dir_path)
+        .map_err(|e| format!("failed to read directory {}: {}", dir_path.display(), e))?;
+    let mut dir_entries = Vec::new();
+    for dir_entry_res in read_dir {
+        let dir_entry = dir_entry_res
+            .map_err(|e| format!("failed to read directory {}: {}", dir_path.display(), e))?;
+        dir_entries.push(dir_entry);
+    }
+    // stable order independent of the file system
+    dir_entries.sort_by_key(|e| e.file_name());
+
+    let mut entries = Vec::with_capacity(dir_entries.len());
+    for dir_entry in dir_entries {
+        let path = dir_entry.path();
+        let file_name = dir_entry.file_name().into_string()
+            .map_err(|n| format!("file name {:?} is not valid Unicode", n))?;
+        let metadata = dir_entry.metadata()
+            .map_err(|e| format!("failed to obtain metadata of {}: {}", path.display(), e))?;
+        let time = modification_time(&metadata);
+        let entry_path = rez::join_path(base_path, &file_name);
+
+        if metadata.is_dir() {
+            let sub_entries = scan_directory_recursive(&path, &entry_path, source_paths)?;
+            entries.push(rez::Entry::Directory(rez::Directory {
+                header: rez::EntryHeader {
+                    entry_type: rez::EntryType::Directory,
+                    position: 0,
+                    size: 0,
+                    time,
+                },
+                name: file_name,
+                entries: sub_entries,
+                entries_loaded: true,
+            }));
+        } else {
+            let entry = rez::Entry::Resource(new_resource(&path, &file_name, time)?);
+
+            // the path under which File::write asks for the data
+            let archive_path = rez::join_path(base_path, &entry.name());
+            source_paths.insert(archive_path, path);
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
+
+/// Creates a resource with the ID 0 for the file with the given name, taking the part of the name
+/// after the last dot as the extension. A leading dot, as in `.gitignore`, does not start an
+/// extension.
+pub fn new_resource(path: &Path, file_name: &str, time: u32) -> Result<rez::Resource, String> {
+    let (name, extension) = match file_name.rsplit_once('.') {
+        Some((name, extension)) if !name.is_empty() => (name, extension),
+        _ => (file_name, ""),
+    };
+    if extension.len() > 4 {
+        return Err(format!("extension of {} is longer than 4 bytes", path.display()));
+    }
+
+    Ok(rez::Resource {
+        header: rez::EntryHeader {
+            entry_type: rez::EntryType::Resource,
+            position: 0,
+            size: 0,
+            time,
+        },
+        id: 0,
+        extension: extension.to_owned(),
+        extension_bytes: [0; 4], // filled in when writing
+        name: name.to_owned(),
+        description: String::new(),
+        keys: Vec::new(),
+    })
+}
+
+fn modification_time(metadata: &Metadata) -> u32 {
+    metadata.modified().ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .and_then(|d| u32::try_from(d.as_secs()).ok())
+        .unwrap_or(0)
+}
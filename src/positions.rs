@@ -0,0 +1,88 @@
+//! Tables mapping the paths of the resources in a REZ file to the locations of their data, for
+//! programs that access the REZ file directly (e.g. by mapping it into memory) without parsing its
+//! directory.
+//!
+//! The binary format consists of little-endian values and is laid out as follows:
+//!
+//! ```text
+//! u32 record_count
+//! record_count times:
+//!     u32 offset   (from the start of the REZ file)
+//!     u32 length   (in bytes)
+//!     u8[] path    (ISO-8859-1, components separated by '/', terminated by a NUL byte)
+//! ```
+//!
+//! There is no padding between records. The records are in the order in which the resources
+//! appear in the directory tree (depth-first).
+//!
+//! The JSON format is an array of objects with the keys `path`, `offset` and `length`.
+
+
+use std::io::{self, Write};
+
+use serde_json::json;
+
+use crate::rez;
+
+
+/// The location of the data of a resource.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Position {
+    pub path: String,
+    pub offset: u64,
+    pub length: u32,
+}
+
+
+/// Collects the positions of all resources in the REZ file.
+pub fn collect_positions(rez_file: &rez::File) -> Vec<Position> {
+    rez_file.walk()
+        .filter_map(|(path, entry)| {
+            let res = entry.as_resource()?;
+            Some(Position {
+                path,
+                offset: rez_file.data_offset(res),
+                length: res.header.size,
+            })
+        })
+        .collect()
+}
+
+/// Writes the positions in the binary format described in the module documentation.
+pub fn write_binary<W: Write>(writer: &mut W, positions: &[Position]) -> io::Result<()> {
+    let too_large = |what: &str| io::Error::new(io::ErrorKind::InvalidData, format!("{} does not fit into 32 bits", what));
+
+    let count = u32::try_from(positions.len())
+        .map_err(|_| too_large("number of resources"))?;
+    writer.write_all(&count.to_le_bytes())?;
+    for position in positions {
+        let offset = u32::try_from(position.offset)
+            .map_err(|_| too_large("offset"))?;
+        writer.write_all(&offset.to_le_bytes())?;
+        writer.write_all(&position.length.to_le_bytes())?;
+
+        let mut path_bytes = Vec::with_capacity(position.path.len() + 1);
+        for c in position.path.chars() {
+            let b = u8::try_from(u32::from(c))
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("path {:?} cannot be encoded as ISO-8859-1", position.path)))?;
+            path_bytes.push(b);
+        }
+        path_bytes.push(0x00);
+        writer.write_all(&path_bytes)?;
+    }
+    writer.flush()
+}
+
+/// Writes the positions as a JSON array.
+pub fn write_json<W: Write>(writer: &mut W, positions: &[Position]) -> io::Result<()> {
+    let records: Vec<serde_json::Value> = positions.iter()
+        .map(|p| json!({
+            "path": p.path,
+            "offset": p.offset,
+            "length": p.length,
+        }))
+        .collect();
+    serde_json::to_writer_pretty(&mut *writer, &records)?;
+    writeln!(writer)?;
+    writer.flush()
+}
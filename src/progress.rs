@@ -0,0 +1,85 @@
+//! Progress bars displayed on the terminal during extraction.
+//!
+//! Progress bars are drawn on standard error and only if it is a terminal. Log messages are routed
+//! through the progress bars so that they appear above them instead of garbling them.
+
+
+use std::fmt;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use indicatif_log_bridge::LogWrapper;
+
+
+static MULTI_PROGRESS: OnceLock<MultiProgress> = OnceLock::new();
+
+
+fn multi_progress() -> &'static MultiProgress {
+    MULTI_PROGRESS.get_or_init(MultiProgress::new)
+}
+
+/// Installs the given logger as the global logger, arranging for its messages not to interfere
+/// with progress bars.
+pub fn init_logger(logger: env_logger::Logger) {
+    let max_level = logger.filter();
+    LogWrapper::new(multi_progress().clone(), logger)
+        .try_init()
+        .expect("failed to initialize logger");
+    log::set_max_level(max_level);
+}
+
+/// Runs the given function with all progress bars hidden, e.g. to ask a question on the terminal
+/// without the progress bars drawing over it.
+pub fn suspend<T, F: FnOnce() -> T>(f: F) -> T {
+    multi_progress().suspend(f)
+}
+
+
+
+/// Displays the progress of an extraction: the number of files and bytes handled and the
+/// throughput. May be shared between threads.
+pub struct ExtractProgress {
+    bar: ProgressBar,
+    files: AtomicU64,
+    total_files: u64,
+}
+impl ExtractProgress {
+    /// Starts displaying the progress of an extraction of the given number of files with the given
+    /// total size.
+    pub fn new(total_files: u64, total_bytes: u64) -> Self {
+        let style = ProgressStyle::with_template(
+            "[{elapsed_precise}] {wide_bar} {msg} files, {binary_bytes}/{binary_total_bytes} ({binary_bytes_per_sec}, {eta} left)",
+        )
+            .expect("invalid progress bar template");
+        let bar = multi_progress().add(ProgressBar::new(total_bytes).with_style(style));
+        bar.set_message(format!("0/{}", total_files));
+        Self {
+            bar,
+            files: AtomicU64::new(0),
+            total_files,
+        }
+    }
+
+    /// Records that a file of the given size has been handled, whether it has been extracted or
+    /// not.
+    pub fn file_done(&self, bytes: u64) {
+        let files = self.files.fetch_add(1, Ordering::Relaxed) + 1;
+        self.bar.set_message(format!("{}/{}", files, self.total_files));
+        self.bar.inc(bytes);
+    }
+
+    /// Stops displaying the progress.
+    pub fn finish(&self) {
+        self.bar.finish_and_clear();
+    }
+}
+
+impl fmt::Debug for ExtractProgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExtractProgress")
+            .field("files", &self.files)
+            .field("total_files", &self.total_files)
+            .finish_non_exhaustive()
+    }
+}
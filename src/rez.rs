@@ -3,13 +3,33 @@ use std::fmt;
 use std::io::{self, Cursor, Read, Seek, SeekFrom};
 
 use from_to_repr::from_to_other;
+use log::{debug, trace, warn};
 use smallvec::SmallVec;
 
-use crate::io_ext::ReadExt;
+use crate::io_ext::{ReadExt, SliceCursorExt};
 
 
+mod archive;
+mod extract;
+#[cfg(feature = "mmap")]
+mod mapped;
+pub mod readers;
+mod validate;
+mod write;
+
+pub use self::archive::Archive;
+#[cfg(feature = "mmap")]
+pub use self::mapped::MappedFile;
+pub use self::write::Writer;
+
+
+/// The file type that is written into newly created REZ files by default.
+pub const DEFAULT_FILE_TYPE: &str = "RezMgr Version 1 Copyright (C) 1995 MONOLITH INC.";
+
 const HEAD_TAIL_XOR: u8 = 0x11;
 const ENCODE_VALUE_XOR: u32 = 0x016B4423;
+const VERSION_2_OFFSET: usize = 7;
+const VERSION_2_SEARCH_WINDOW: usize = 20;
 
 
 #[derive(Debug)]
@@ -23,6 +43,34 @@ pub enum Error {
     InvalidEncodeInteger { detection_value: bool, string: String },
     EncodeValueMismatch { encode_value: u32, detect_encode_value: u32 },
     UnknownEntryType { type_code: u32 },
+    TruncatedDirectory { position: u32, expected: u32, available: u64 },
+    UnencodableString { string: String },
+    ExtensionTooLong { extension: String },
+    FileTooLarge { size: u64 },
+    DuplicateEntry { path: String },
+    NoSuchEntry { path: String },
+    MoveIntoItself { path: String },
+    CannotModify { reason: &'static str },
+    SizeCapExceeded { cap: u64, required: u64 },
+}
+impl Error {
+    /// Returns whether this error was caused by a failed I/O operation.
+    pub fn is_io(&self) -> bool {
+        matches!(self, Self::Io(_))
+    }
+
+    /// Returns whether this error was caused by the REZ file being malformed.
+    pub fn is_format_error(&self) -> bool {
+        !self.is_io() && !matches!(self, Self::SizeCapExceeded { .. })
+    }
+
+    /// Returns the underlying I/O error, if any.
+    pub fn io_error(&self) -> Option<&io::Error> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
 }
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -44,6 +92,24 @@ impl fmt::Display for Error {
                 => write!(f, "encode value mismatch (encode value 0x{:08X}, xor'ed encode value 0x{:08X}, detect encode value 0x{:08X}", encode_value, encode_value ^ ENCODE_VALUE_XOR, detect_encode_value),
             Self::UnknownEntryType { type_code }
                 => write!(f, "cannot handle entries with type code {}", type_code),
+            Self::TruncatedDirectory { position, expected, available }
+                => write!(f, "directory at position {} is truncated (expected {} bytes, only {} available; missing {})", position, expected, available, u64::from(*expected) - available),
+            Self::UnencodableString { string }
+                => write!(f, "string {:?} cannot be encoded as ISO-8859-1 without NUL characters", string),
+            Self::ExtensionTooLong { extension }
+                => write!(f, "extension {:?} is longer than 4 bytes", extension),
+            Self::FileTooLarge { size }
+                => write!(f, "REZ file would be {} bytes long, which exceeds the maximum of 4 GiB", size),
+            Self::DuplicateEntry { path }
+                => write!(f, "an entry already exists at {:?}", path),
+            Self::NoSuchEntry { path }
+                => write!(f, "no entry exists at {:?}", path),
+            Self::MoveIntoItself { path }
+                => write!(f, "cannot move {:?} into itself", path),
+            Self::CannotModify { reason }
+                => write!(f, "cannot modify REZ file: {}", reason),
+            Self::SizeCapExceeded { cap, required }
+                => write!(f, "reading would require at least {} bytes, which exceeds the cap of {} bytes", required, cap),
         }
     }
 }
@@ -59,6 +125,15 @@ impl std::error::Error for Error {
             Self::InvalidEncodeInteger { .. } => None,
             Self::EncodeValueMismatch { .. } => None,
             Self::UnknownEntryType { .. } => None,
+            Self::TruncatedDirectory { .. } => None,
+            Self::UnencodableString { .. } => None,
+            Self::ExtensionTooLong { .. } => None,
+            Self::FileTooLarge { .. } => None,
+            Self::DuplicateEntry { .. } => None,
+            Self::NoSuchEntry { .. } => None,
+            Self::MoveIntoItself { .. } => None,
+            Self::CannotModify { .. } => None,
+            Self::SizeCapExceeded { .. } => None,
         }
     }
 }
@@ -72,6 +147,8 @@ pub struct FileHeader {
     pub file_type: SmallVec<[u8; 60]>,
     pub user_title: SmallVec<[u8; 60]>,
     pub version: u32,
+    /// The offset of the version number, counted from the end of the control bytes.
+    pub version_offset: u32,
     pub root_dir_position: u32,
     pub root_dir_size: u32,
     pub root_dir_time: u32,
@@ -84,6 +161,11 @@ pub struct FileHeader {
     pub is_sorted: bool,
 }
 impl FileHeader {
+    /// Returns the length of this header as stored in the REZ file.
+    pub fn length(&self) -> u64 {
+        2 + 60 + 2 + 60 + 3 + u64::from(self.version_offset) + 4 + 9*4 + 1
+    }
+
     pub fn try_read<R: Read>(r: &mut R) -> Result<Self, Error> {
         let mut one_buf = [0u8];
         let mut two_buf = [0u8; 2];
@@ -114,6 +196,8 @@ impl FileHeader {
 
         // the EOF character is a hint at the version
         let mut version = 0;
+        let mut version_offset = 0;
+        let mut leftover_bytes = Vec::new();
         if three_buf[2] == 0x1A {
             // assume version 1
             version = r.read_u32_le()?;
@@ -121,12 +205,29 @@ impl FileHeader {
                 // fallback to format 2:
                 // EOF ??? ??? ??? ??? | ??? ??? ??? vvv vvv vvv vvv
                 //         we are here ^
+                // some files have more padding; look for the version in a small window, in steps
+                // of 4 bytes starting at the usual position
+
+                let mut window = [0u8; VERSION_2_SEARCH_WINDOW];
+                window[0..4].copy_from_slice(&version.to_le_bytes());
+                r.read_exact(&mut window[4..])?;
+
+                let found_offset = (VERSION_2_OFFSET..=VERSION_2_SEARCH_WINDOW-4)
+                    .step_by(4)
+                    .find(|&o| u32::from_le_bytes(window[o..o+4].try_into().unwrap()) == 2);
+                let Some(offset) = found_offset else {
+                    let obtained = u32::from_le_bytes(window[VERSION_2_OFFSET..VERSION_2_OFFSET+4].try_into().unwrap());
+                    return Err(Error::InvalidVersion { expected: 2, obtained });
+                };
 
-                r.read_exact(&mut three_buf)?; // throw away
-                version = r.read_u32_le()?;
-                if version != 2 {
-                    return Err(Error::InvalidVersion { expected: 2, obtained: version });
+                version = 2;
+                version_offset = offset.try_into().unwrap();
+                if offset != VERSION_2_OFFSET {
+                    warn!("version 2 header has {} unexpected padding bytes before the version number", offset - VERSION_2_OFFSET);
                 }
+
+                // the bytes after the version belong to the following fields
+                leftover_bytes.extend_from_slice(&window[offset+4..]);
             }
         } else if three_buf[2] == 0x2A {
             let mut two_buf = [0u8; 2];
@@ -144,7 +245,7 @@ impl FileHeader {
             }
 
             let encode_bytes = without_trailing_zero_bytes(&encode_buf);
-            let encode_str = std::str::from_utf8(&encode_bytes)
+            let encode_str = std::str::from_utf8(encode_bytes)
                 .map_err(|_| Error::InvalidEncodeUtf8 { detection_value: false, bytes: encode_bytes.to_vec() })?;
             let encode_value: u32 = encode_str.parse()
                 .map_err(|_| Error::InvalidEncodeInteger { detection_value: false, string: encode_str.to_owned() })?;
@@ -155,7 +256,7 @@ impl FileHeader {
             r.read_exact(&mut detect_encode_buf)?;
 
             let detect_encode_bytes = without_trailing_zero_bytes(&detect_encode_buf);
-            let detect_encode_str = std::str::from_utf8(&detect_encode_bytes)
+            let detect_encode_str = std::str::from_utf8(detect_encode_bytes)
                 .map_err(|_| Error::InvalidEncodeUtf8 { detection_value: true, bytes: detect_encode_bytes.to_vec() })?;
             let detect_encode_value: u32 = detect_encode_str.parse()
                 .map_err(|_| Error::InvalidEncodeInteger { detection_value: true, string: detect_encode_str.to_owned() })?;
@@ -171,12 +272,14 @@ impl FileHeader {
                 return Err(Error::InvalidDetectTail { tail, detect_tail });
             }
 
+            version_offset = 1 + 32 + 2 + 32 + 1;
             version = r.read_u32_le()?;
             if version != 1 {
                 return Err(Error::InvalidVersion { expected: 1, obtained: version });
             }
         }
 
+        let mut r = Cursor::new(leftover_bytes).chain(r);
         let root_dir_pos = r.read_u32_le()?;
         let root_dir_size = r.read_u32_le()?;
         let root_dir_time = r.read_u32_le()?;
@@ -194,6 +297,7 @@ impl FileHeader {
             file_type,
             user_title,
             version,
+            version_offset,
             root_dir_position: root_dir_pos,
             root_dir_size,
             root_dir_time,
@@ -265,18 +369,136 @@ impl EntryHeader {
     }
 }
 
+/// Iterates over the headers of the entries in a directory block.
+///
+/// The names, descriptions and keys following each header are skipped without being decoded.
+/// Iteration stops after the first error.
+pub fn iter_entry_headers(block: &[u8]) -> impl Iterator<Item = Result<EntryHeader, Error>> + '_ {
+    let mut cursor = Cursor::new(block);
+    let mut finished = false;
+    std::iter::from_fn(move || {
+        if finished {
+            return None;
+        }
+        match read_entry_header_skipping_payload(&mut cursor) {
+            Ok(Some(header)) => Some(Ok(header)),
+            Ok(None) => {
+                finished = true;
+                None
+            },
+            Err(e) => {
+                finished = true;
+                Some(Err(e))
+            },
+        }
+    })
+}
+
+/// An entry whose data or directory block does not lie completely within the REZ file, as found by
+/// [`File::validate_offsets_only`].
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct OutOfBounds {
+    /// The position of the directory block containing the entry, or `None` if the root directory
+    /// block referenced by the header is itself out of bounds.
+    pub directory_position: Option<u32>,
+    /// The index of the entry within its directory block.
+    pub index: usize,
+    /// The header of the entry, or a header describing the root directory block.
+    pub header: EntryHeader,
+}
+
+fn read_entry_header_skipping_payload(cursor: &mut Cursor<&[u8]>) -> Result<Option<EntryHeader>, Error> {
+    if only_padding_remains(cursor) {
+        return Ok(None);
+    }
+    let header = match EntryHeader::try_read_next(cursor)? {
+        Some(h) => h,
+        None => return Ok(None),
+    };
+    match header.entry_type {
+        EntryType::Directory => {
+            cursor.read_nul_terminated_slice()?;
+        },
+        EntryType::Resource => {
+            // id and extension
+            cursor.seek(SeekFrom::Current(8))?;
+            let num_keys = cursor.read_u32_le()?;
+            cursor.read_nul_terminated_slice()?; // name
+            cursor.read_nul_terminated_slice()?; // description
+            cursor.seek(SeekFrom::Current(i64::from(num_keys) * 4))?;
+            if cursor.position() > u64::try_from(cursor.get_ref().len()).unwrap() {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+        },
+        EntryType::Other(type_code) => return Err(Error::UnknownEntryType { type_code }),
+    }
+    Ok(Some(header))
+}
+
 #[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub enum Entry {
     Resource(Resource),
     Directory(Directory),
 }
 impl Entry {
-    pub fn name(&self) -> Cow<str> {
+    pub fn name(&self) -> Cow<'_, str> {
         match self {
-            Self::Resource(res) => Cow::Owned(format!("{}.{}", res.name.as_str(), res.extension.as_str())),
+            Self::Resource(res) => Cow::Owned(res.file_name()),
             Self::Directory(dir) => Cow::Borrowed(dir.name.as_str()),
         }
     }
+
+    pub fn as_resource(&self) -> Option<&Resource> {
+        match self {
+            Self::Resource(res) => Some(res),
+            Self::Directory(_) => None,
+        }
+    }
+
+    pub fn as_directory(&self) -> Option<&Directory> {
+        match self {
+            Self::Resource(_) => None,
+            Self::Directory(dir) => Some(dir),
+        }
+    }
+
+    pub fn is_resource(&self) -> bool {
+        matches!(self, Self::Resource(_))
+    }
+
+    pub fn is_directory(&self) -> bool {
+        matches!(self, Self::Directory(_))
+    }
+}
+
+/// Appends a name to a path within a REZ file, separating them with a forward slash unless either
+/// is empty.
+pub fn join_path(base_path: &str, name: &str) -> String {
+    if base_path.is_empty() {
+        name.to_owned()
+    } else if name.is_empty() {
+        base_path.to_owned()
+    } else {
+        format!("{}/{}", base_path, name)
+    }
+}
+
+/// Compares two entry names in the order in which entries of sorted REZ files are sorted.
+///
+/// The comparison is case-insensitive, since the LithTech engine treats paths case-insensitively.
+pub fn compare_entry_names(left: &str, right: &str) -> std::cmp::Ordering {
+    left.bytes().map(|b| b.to_ascii_lowercase())
+        .cmp(right.bytes().map(|b| b.to_ascii_lowercase()))
+}
+
+/// Sorts the given entries and those of all their subdirectories by name.
+pub fn sort_entries_recursive(entries: &mut [Entry]) {
+    entries.sort_by(|left, right| compare_entry_names(&left.name(), &right.name()));
+    for entry in entries {
+        if let Entry::Directory(dir) = entry {
+            sort_entries_recursive(&mut dir.entries);
+        }
+    }
 }
 
 #[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
@@ -284,16 +506,56 @@ pub struct Resource {
     pub header: EntryHeader,
     pub id: u32,
     pub extension: String,
+    /// The extension bytes as stored in the directory entry, from which `extension` was decoded.
+    /// [`File::write`] encodes `extension` and updates these to match.
+    pub extension_bytes: [u8; 4],
     pub name: String,
     pub description: String,
     pub keys: Vec<u32>,
 }
+impl Resource {
+    /// Returns the name of this resource along with its extension, as used in paths.
+    pub fn file_name(&self) -> String {
+        format!("{}.{}", self.name, self.extension)
+    }
+}
 
 #[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct Directory {
     pub header: EntryHeader,
     pub name: String,
     pub entries: Vec<Entry>,
+    /// Whether `entries` has been read from the REZ file. Only the subdirectories of a REZ file
+    /// read using [`File::try_read_lazy`] start out without their entries; see
+    /// [`Directory::load_children`].
+    pub entries_loaded: bool,
+}
+impl Directory {
+    /// Reads the entries of this directory from the REZ file read by the given reader, unless they
+    /// have already been loaded. The entries of its subdirectories are not loaded.
+    ///
+    /// `base_offset` and `file_size` are those of the [`File`] containing this directory.
+    pub fn load_children<R: Read + Seek>(&mut self, r: &mut R, base_offset: u64, file_size: u64) -> Result<(), Error> {
+        if !self.entries_loaded {
+            self.entries = read_directory_entries(r, base_offset, file_size, self.header.position, self.header.size)?;
+            self.entries_loaded = true;
+        }
+        Ok(())
+    }
+
+    /// Reads the entries of this directory and of all its subdirectories from the REZ file read by
+    /// the given reader, skipping those that have already been loaded.
+    ///
+    /// `base_offset` and `file_size` are those of the [`File`] containing this directory.
+    pub fn load_tree<R: Read + Seek>(&mut self, r: &mut R, base_offset: u64, file_size: u64) -> Result<(), Error> {
+        self.load_children(r, base_offset, file_size)?;
+        for entry in &mut self.entries {
+            if let Entry::Directory(dir) = entry {
+                dir.load_tree(r, base_offset, file_size)?;
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
@@ -302,26 +564,495 @@ pub struct File {
     pub user_title: String,
     pub version: u32,
     pub time: u32,
+    pub is_sorted: bool,
+    /// The length of the REZ file in bytes, counted from its base offset to the end of the
+    /// underlying file.
+    pub file_size: u64,
+    /// The position of the REZ file within the underlying file. The positions of all entries are
+    /// relative to this offset.
+    pub base_offset: u64,
     pub root_entries: Vec<Entry>,
 }
 impl File {
+    /// Reads the header and the whole directory tree of a REZ file.
+    ///
+    /// No assumptions are made about the location of the directory blocks; they are read from
+    /// wherever the header and the parent directories point, whether that is at the end of the
+    /// file (as is usual) or directly after the header.
+    ///
+    /// The length of the file is determined by seeking to its end, which is why the reader must
+    /// implement `Seek`.
+    ///
+    /// The REZ file is assumed to start at the current position of the reader, which becomes its
+    /// base offset.
     pub fn try_read<R: Read + Seek>(r: &mut R) -> Result<Self, Error> {
+        Self::try_read_with_header(r).map(|(file, _header)| file)
+    }
+
+    /// Reads all REZ files that have been concatenated into one file, starting at the current
+    /// position of the reader.
+    ///
+    /// After each REZ file, reading continues with the next REZ file at the end of the region
+    /// covered by the previous one, i.e. after its last resource, its last directory block and the
+    /// position at which the next write would have happened, whichever is last.
+    pub fn try_read_all<R: Read + Seek>(r: &mut R) -> Result<Vec<Self>, Error> {
+        let mut files = Vec::new();
+        let mut position = r.stream_position()?;
+        let total_length = r.seek(SeekFrom::End(0))?;
+        while position < total_length {
+            r.seek(SeekFrom::Start(position))?;
+            let (file, header) = Self::try_read_with_header(r)?;
+
+            let mut end = u64::from(header.next_write_pos)
+                .max(u64::from(header.root_dir_position) + u64::from(header.root_dir_size));
+            for entry in &file.root_entries {
+                end = end.max(entry_region_end_recursive(entry));
+            }
+            // a REZ file covers at least its header, even if its header claims otherwise
+            end = end.max(header.length());
+
+            position += end;
+            files.push(file);
+        }
+        Ok(files)
+    }
+
+    /// Reads the header and the directory tree of a REZ file, passing each entry to `on_entry` as
+    /// soon as it has been read instead of collecting them, which keeps memory usage flat even for
+    /// huge REZ files.
+    ///
+    /// `on_entry` is called with the names of the directories containing the entry and the entry
+    /// itself. Directories are passed without their entries, which follow them. The returned file
+    /// has no root entries.
+    ///
+    /// See [`File::try_read`] for the assumptions about the reader.
+    pub fn try_read_streaming<R, F>(r: &mut R, mut on_entry: F) -> Result<Self, Error>
+        where
+            R: Read + Seek,
+            F: FnMut(&[String], &Entry),
+    {
+        let (file, header) = Self::try_read_header(r)?;
+        let mut dir_path = Vec::new();
+        stream_directory_entries_recursive(r, file.base_offset, file.file_size, header.root_dir_position, header.root_dir_size, &mut dir_path, &mut on_entry)?;
+        Ok(file)
+    }
+
+    /// Reads the header and the root directory of a REZ file without the entries of any
+    /// subdirectories, which are then loaded on demand using [`File::load_directory`] or
+    /// [`Directory::load_children`].
+    ///
+    /// This avoids reading the whole directory tree when only a part of a large REZ file is
+    /// needed. Until their entries have been loaded, subdirectories appear empty, e.g. to
+    /// [`File::walk`] and [`File::entry_by_path`].
+    ///
+    /// See [`File::try_read`] for the assumptions about the reader.
+    pub fn try_read_lazy<R: Read + Seek>(r: &mut R) -> Result<Self, Error> {
+        let (mut file, header) = Self::try_read_header(r)?;
+        file.root_entries = read_directory_entries(r, file.base_offset, file.file_size, header.root_dir_position, header.root_dir_size)?;
+        Ok(file)
+    }
+
+    /// Finds the directory at the given path, loading the entries of the directories leading up to
+    /// it and of the directory itself if necessary.
+    ///
+    /// The reader must be the one from which this REZ file was read. See [`File::entry_by_path`]
+    /// for the path syntax.
+    pub fn load_directory<R: Read + Seek>(&mut self, r: &mut R, path: &str) -> Result<&mut Directory, Error> {
+        let (base_offset, file_size) = (self.base_offset, self.file_size);
+        let no_such_entry = || Error::NoSuchEntry { path: path.to_owned() };
+        let mut entries = &mut self.root_entries;
+        let mut components = path.split(['/', '\\']).filter(|c| !c.is_empty()).peekable();
+        if components.peek().is_none() {
+            // the root directory is not a Directory
+            return Err(no_such_entry());
+        }
+        loop {
+            let component = components.next().unwrap();
+            let dir = entries.iter_mut()
+                .find_map(|e| match e {
+                    Entry::Directory(dir) if dir.name == component => Some(dir),
+                    _ => None,
+                })
+                .ok_or_else(no_such_entry)?;
+            dir.load_children(r, base_offset, file_size)?;
+            if components.peek().is_none() {
+                return Ok(dir);
+            }
+            entries = &mut dir.entries;
+        }
+    }
+
+    fn try_read_with_header<R: Read + Seek>(r: &mut R) -> Result<(Self, FileHeader), Error> {
+        let (mut file, header) = Self::try_read_header(r)?;
+        file.root_entries = read_directory_entries_recursive(r, file.base_offset, file.file_size, header.root_dir_position, header.root_dir_size)?;
+        Ok((file, header))
+    }
+
+    /// Reads the header of a REZ file, returning it along with a file without any entries.
+    fn try_read_header<R: Read + Seek>(r: &mut R) -> Result<(Self, FileHeader), Error> {
+        let base_offset = r.stream_position()?;
+        let file_size = r.seek(SeekFrom::End(0))? - base_offset;
+        r.seek(SeekFrom::Start(base_offset))?;
+
         let header = FileHeader::try_read(r)?;
-        let root_entries = read_directory_entries_recursive(r, header.root_dir_position, header.root_dir_size)?;
+        debug!(
+            "read version {} header at offset {}; root directory at {} ({} bytes)",
+            header.version, base_offset, header.root_dir_position, header.root_dir_size,
+        );
         let file_type = iso88591_bytes_to_string(&header.file_type);
         let user_title = iso88591_bytes_to_string(&header.user_title);
 
-        Ok(File {
+        let file = File {
             file_type,
             user_title,
             version: header.version,
             time: header.time,
-            root_entries,
+            is_sorted: header.is_sorted,
+            file_size,
+            base_offset,
+            root_entries: Vec::new(),
+        };
+        Ok((file, header))
+    }
+
+    /// Returns the file type (signature) stored in the header, or `None` if the header contains
+    /// only spaces in its place.
+    pub fn file_type(&self) -> Option<&str> {
+        if self.file_type.is_empty() {
+            None
+        } else {
+            Some(&self.file_type)
+        }
+    }
+
+    /// Returns the length of the REZ file in bytes, as determined when it was read or written.
+    pub fn len_on_disk(&self) -> u64 {
+        self.file_size
+    }
+
+    /// Finds the entry at the given path within the REZ file.
+    ///
+    /// Path components may be separated by forward slashes or backslashes. Resources are referenced
+    /// by their name and extension, e.g. `sounds/boom.wav`.
+    pub fn entry_by_path(&self, path: &str) -> Option<&Entry> {
+        self.find_entry(path, false)
+    }
+
+    /// Finds the entry at the given path within the REZ file, ignoring case unless an entry matches
+    /// exactly, the way the LithTech engine looks up paths.
+    ///
+    /// See [`File::entry_by_path`] for the path syntax.
+    pub fn entry_by_path_ignoring_case(&self, path: &str) -> Option<&Entry> {
+        self.find_entry(path, true)
+    }
+
+    fn find_entry(&self, path: &str, ignore_case: bool) -> Option<&Entry> {
+        let mut entries = &self.root_entries;
+        let mut found: Option<&Entry> = None;
+        for component in path.split(['/', '\\']).filter(|c| !c.is_empty()) {
+            if let Some(Entry::Directory(dir)) = found {
+                entries = &dir.entries;
+            } else if found.is_some() {
+                // a resource has no children
+                return None;
+            }
+            let mut matching = entries.iter().find(|e| e.name() == component);
+            if matching.is_none() && ignore_case {
+                let component = component.to_lowercase();
+                matching = entries.iter().find(|e| e.name().to_lowercase() == component);
+            }
+            found = Some(matching?);
+        }
+        found
+    }
+
+    /// Returns whether the REZ file contains a resource at the given path.
+    ///
+    /// See [`File::entry_by_path`] for the path syntax.
+    pub fn contains(&self, path: &str) -> bool {
+        self.resource_by_path(path).is_some()
+    }
+
+    /// Finds the resource at the given path within the REZ file.
+    ///
+    /// See [`File::entry_by_path`] for the path syntax.
+    pub fn resource_by_path(&self, path: &str) -> Option<&Resource> {
+        self.entry_by_path(path)?.as_resource()
+    }
+
+    /// Decodes the extensions of all resources anew from their stored bytes using the given byte
+    /// order.
+    ///
+    /// [`File::try_read`] assumes [`ExtensionOrder::Reversed`]; this allows reading variant REZ
+    /// files that store their extensions differently.
+    pub fn redecode_extensions(&mut self, order: ExtensionOrder) {
+        redecode_extensions_recursive(&mut self.root_entries, order);
+    }
+
+    /// Returns the paths of all directories along with the total size of the resources within them
+    /// and all their subdirectories. The root directory is included with an empty path; the other
+    /// directories follow in the order of the directory tree, each after its subdirectories.
+    pub fn directory_sizes(&self) -> Vec<(String, u64)> {
+        let mut sizes = Vec::new();
+        let root_size = directory_sizes_recursive(&self.root_entries, "", &mut sizes);
+        sizes.push((String::new(), root_size));
+        sizes
+    }
+
+    /// Returns the position of the data of the given resource within the underlying file, taking
+    /// the base offset into account.
+    pub fn data_offset(&self, res: &Resource) -> u64 {
+        self.base_offset + u64::from(res.header.position)
+    }
+
+    /// Returns the stored data of the given resource as a slice of `data`, which must contain the
+    /// whole underlying file, e.g. because it has been read into memory or mapped.
+    ///
+    /// Returns `None` if the data of the resource extends past the end of `data`.
+    pub fn resource_data<'d>(&self, data: &'d [u8], res: &Resource) -> Option<&'d [u8]> {
+        let start = usize::try_from(self.data_offset(res)).ok()?;
+        let end = start.checked_add(usize::try_from(res.header.size).ok()?)?;
+        data.get(start..end)
+    }
+
+    /// Finds the resource whose data contains the byte at the given offset within the underlying
+    /// file, returning its path and the resource itself.
+    ///
+    /// If the data of multiple resources overlaps at that offset, the first one in directory order
+    /// is returned.
+    pub fn resource_at_offset(&self, offset: u64) -> Option<(String, &Resource)> {
+        let relative_offset = offset.checked_sub(self.base_offset)?;
+        self.walk()
+            .filter_map(|(path, entry)| Some((path, entry.as_resource()?)))
+            .find(|(_path, res)| {
+                let start = u64::from(res.header.position);
+                let end = start + u64::from(res.header.size);
+                (start..end).contains(&relative_offset)
+            })
+    }
+
+    /// Returns an iterator over all entries of the directory tree along with their paths, in
+    /// depth-first order: each directory is followed by its entries, before the entries after it.
+    ///
+    /// The paths use forward slashes as separators, e.g. `sounds/boom.wav`.
+    pub fn walk(&self) -> Walk<'_> {
+        walk_entries(&self.root_entries, "")
+    }
+}
+
+/// Returns an iterator over the given entries and those of all their subdirectories along with
+/// their paths, in the order of [`File::walk`]. The paths are prefixed with `base_path`, which is
+/// usually the path of the directory containing the entries.
+pub fn walk_entries<'a>(entries: &'a [Entry], base_path: &str) -> Walk<'a> {
+    Walk {
+        stack: vec![(entries.iter(), base_path.to_owned())],
+    }
+}
+
+/// An iterator over the entries of a REZ file along with their paths, returned by [`File::walk`]
+/// and [`walk_entries`].
+#[derive(Clone, Debug)]
+pub struct Walk<'a> {
+    /// The entries remaining in each directory being walked, along with the path of that directory.
+    stack: Vec<(std::slice::Iter<'a, Entry>, String)>,
+}
+impl<'a> Iterator for Walk<'a> {
+    type Item = (String, &'a Entry);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (entries, dir_path) = self.stack.last_mut()?;
+            let Some(entry) = entries.next() else {
+                self.stack.pop();
+                continue;
+            };
+            let path = join_path(dir_path, &entry.name());
+            if let Entry::Directory(dir) = entry {
+                self.stack.push((dir.entries.iter(), path.clone()));
+            }
+            return Some((path, entry));
+        }
+    }
+}
+
+/// Appends the cumulative sizes of the subdirectories to `sizes` and returns the cumulative size of
+/// the given entries.
+fn directory_sizes_recursive(entries: &[Entry], dir_path: &str, sizes: &mut Vec<(String, u64)>) -> u64 {
+    let mut total: u64 = 0;
+    for entry in entries {
+        match entry {
+            Entry::Directory(dir) => {
+                let sub_path = join_path(dir_path, &dir.name);
+                let sub_size = directory_sizes_recursive(&dir.entries, &sub_path, sizes);
+                sizes.push((sub_path, sub_size));
+                total += sub_size;
+            },
+            Entry::Resource(res) => total += u64::from(res.header.size),
+        }
+    }
+    total
+}
+
+fn redecode_extensions_recursive(entries: &mut [Entry], order: ExtensionOrder) {
+    for entry in entries {
+        match entry {
+            Entry::Directory(dir) => redecode_extensions_recursive(&mut dir.entries, order),
+            Entry::Resource(res) => res.extension = order.decode(&res.extension_bytes),
+        }
+    }
+}
+
+
+/// The order in which the bytes of an extension are stored in a directory entry.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum ExtensionOrder {
+    /// Reversed and padded with NUL bytes at the end, e.g. `xtd\0` for `dtx`. This is how
+    /// standard REZ files store extensions.
+    #[default] Reversed,
+    /// In reading order and padded with NUL bytes at the end, e.g. `dtx\0` for `dtx`.
+    Normal,
+}
+impl ExtensionOrder {
+    /// Decodes the stored extension bytes into an extension.
+    pub fn decode(self, extension_bytes: &[u8; 4]) -> String {
+        match self {
+            Self::Reversed => {
+                let mut reversed = *extension_bytes;
+                reversed.reverse();
+                iso88591_bytes_to_string(without_leading_zero_bytes(&reversed))
+            },
+            Self::Normal => iso88591_bytes_to_string(without_trailing_zero_bytes(extension_bytes)),
+        }
+    }
+}
+
+
+/// Transforms the stored data of a resource into its actual contents.
+///
+/// Base REZ files store resources uncompressed, but some game-specific variants compress them.
+pub trait Decompressor: fmt::Debug {
+    fn decompress(&self, stored: &[u8]) -> io::Result<Vec<u8>>;
+}
+
+/// Decompresses resources stored as zlib streams.
+#[cfg(feature = "zlib")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ZlibDecompressor;
+#[cfg(feature = "zlib")]
+impl Decompressor for ZlibDecompressor {
+    fn decompress(&self, stored: &[u8]) -> io::Result<Vec<u8>> {
+        let mut ret = Vec::new();
+        flate2::read::ZlibDecoder::new(stored).read_to_end(&mut ret)?;
+        Ok(ret)
+    }
+}
+
+
+/// What [`RezReader::extract_all`] should do with a resource after the callback has seen it.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum ExtractDecision {
+    /// The callback has taken care of the resource; nothing else is written.
+    Handled,
+    /// The resource is not extracted.
+    Skip,
+    /// The resource is written into the output directory as usual, regardless of how much of its
+    /// data the callback has read.
+    Default,
+}
+
+/// Reads the data of resources from a REZ file.
+#[derive(Debug)]
+pub struct RezReader<R> {
+    reader: R,
+    decompressor: Option<Box<dyn Decompressor>>,
+    base_offset: u64,
+}
+impl<R> RezReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self { reader, decompressor: None, base_offset: 0 }
+    }
+
+    /// Returns the position of the REZ file within the underlying reader.
+    pub fn base_offset(&self) -> u64 {
+        self.base_offset
+    }
+
+    /// Sets the position of the REZ file within the underlying reader; see [`File::base_offset`].
+    pub fn set_base_offset(&mut self, base_offset: u64) {
+        self.base_offset = base_offset;
+    }
+
+    /// Sets the decompressor applied by [`RezReader::read_resource`].
+    pub fn set_decompressor(&mut self, decompressor: Option<Box<dyn Decompressor>>) {
+        self.decompressor = decompressor;
+    }
+
+    pub fn get_ref(&self) -> &R { &self.reader }
+    pub fn get_mut(&mut self) -> &mut R { &mut self.reader }
+    pub fn into_inner(self) -> R { self.reader }
+}
+impl<R: Read + Seek> RezReader<R> {
+    /// Reads the data of the given resource into the given buffer, which must be exactly as long
+    /// as the resource.
+    pub fn read_resource_exact(&mut self, res: &Resource, out: &mut [u8]) -> io::Result<()> {
+        if u64::try_from(out.len()).unwrap() != u64::from(res.header.size) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("buffer has {} bytes but resource has {} bytes", out.len(), res.header.size),
+            ));
+        }
+        self.reader.seek(SeekFrom::Start(self.base_offset + u64::from(res.header.position)))?;
+        self.reader.read_exact(out)
+    }
+
+    /// Reads the contents of the given resource, decompressing them if a decompressor has been set.
+    pub fn read_resource(&mut self, res: &Resource) -> io::Result<Vec<u8>> {
+        let mut stored = vec![0u8; res.header.size.try_into().unwrap()];
+        self.read_resource_exact(res, &mut stored)?;
+        match &self.decompressor {
+            Some(decompressor) => decompressor.decompress(&stored),
+            None => Ok(stored),
+        }
+    }
+
+    /// Returns an iterator over the data of the given resource in chunks of at most `chunk_size`
+    /// bytes.
+    ///
+    /// The reader is positioned at the start of the resource when the first chunk is requested and
+    /// must not be moved elsewhere in between. Iteration stops after the first error.
+    pub fn resource_chunks(&mut self, res: &Resource, chunk_size: usize) -> impl Iterator<Item = io::Result<Vec<u8>>> + '_ {
+        assert!(chunk_size > 0, "chunk size must be greater than zero");
+
+        let position = self.base_offset + u64::from(res.header.position);
+        let mut remain_length = u64::from(res.header.size);
+        let mut seeked = false;
+        let mut failed = false;
+        std::iter::from_fn(move || {
+            if failed || remain_length == 0 {
+                return None;
+            }
+            if !seeked {
+                if let Err(e) = self.reader.seek(SeekFrom::Start(position)) {
+                    failed = true;
+                    return Some(Err(e));
+                }
+                seeked = true;
+            }
+
+            let read_count: usize = remain_length.min(chunk_size.try_into().unwrap()).try_into().unwrap();
+            let mut buf = vec![0u8; read_count];
+            if let Err(e) = self.reader.read_exact(&mut buf) {
+                failed = true;
+                return Some(Err(e));
+            }
+            remain_length -= u64::try_from(read_count).unwrap();
+            Some(Ok(buf))
         })
     }
 }
 
-fn iso88591_bytes_to_string(bytes: &[u8]) -> String {
+/// Decodes ISO-8859-1 bytes, as used for all strings in REZ files.
+pub fn iso88591_bytes_to_string(bytes: &[u8]) -> String {
     let string_byte_count: usize = bytes.iter()
         .map(|b| if *b >= 0x80 { 2 } else { 1 })
         .sum();
@@ -332,76 +1063,152 @@ fn iso88591_bytes_to_string(bytes: &[u8]) -> String {
     ret
 }
 
-fn read_directory_entries_recursive<R: Read + Seek>(reader: &mut R, position: u32, length: u32) -> Result<Vec<Entry>, Error> {
-    let length_usize: usize = length.try_into().unwrap();
-    let mut buf = vec![0u8; length_usize];
+/// Returns whether the rest of a directory block consists only of zero bytes.
+///
+/// Some REZ files pad their directory blocks to an alignment boundary. Such padding would otherwise
+/// be misread as a resource entry (type 0) with a zero position and size.
+fn only_padding_remains(cursor: &Cursor<&[u8]>) -> bool {
+    let position: usize = cursor.position().try_into().unwrap();
+    cursor.get_ref()
+        .get(position..)
+        .map(|rest| rest.iter().all(|b| *b == 0x00))
+        .unwrap_or(true)
+}
+
+/// Returns the position, relative to the start of the REZ file, after the end of the given entry
+/// and all its descendants.
+fn entry_region_end_recursive(entry: &Entry) -> u64 {
+    match entry {
+        Entry::Resource(res) => u64::from(res.header.position) + u64::from(res.header.size),
+        Entry::Directory(dir) => dir.entries.iter()
+            .map(entry_region_end_recursive)
+            .fold(u64::from(dir.header.position) + u64::from(dir.header.size), u64::max),
+    }
+}
+
+fn read_directory_entries_recursive<R: Read + Seek>(reader: &mut R, base_offset: u64, file_length: u64, position: u32, length: u32) -> Result<Vec<Entry>, Error> {
+    let mut entries = read_directory_entries(reader, base_offset, file_length, position, length)?;
+    for entry in &mut entries {
+        if let Entry::Directory(dir) = entry {
+            dir.entries = read_directory_entries_recursive(reader, base_offset, file_length, dir.header.position, dir.header.size)?;
+            dir.entries_loaded = true;
+        }
+    }
+    Ok(entries)
+}
+
+/// Reads the entries of the directory block at the given position. Subdirectories are returned
+/// without their entries.
+fn read_directory_entries<R: Read + Seek>(reader: &mut R, base_offset: u64, file_length: u64, position: u32, length: u32) -> Result<Vec<Entry>, Error> {
+    let buf = read_directory_block(reader, base_offset, file_length, position, length)?;
+    let mut buf_reader = Cursor::new(buf.as_slice());
     let mut entries = Vec::new();
 
-    reader.seek(SeekFrom::Start(position.into()))?;
-    reader.read_exact(&mut buf)?;
+    loop {
+        if only_padding_remains(&buf_reader) {
+            break;
+        }
+        let Some(header) = EntryHeader::try_read_next(&mut buf_reader)? else { break };
+        entries.push(read_entry(&mut buf_reader, header)?);
+    }
+
+    Ok(entries)
+}
+
+fn stream_directory_entries_recursive<R, F>(reader: &mut R, base_offset: u64, file_length: u64, position: u32, length: u32, dir_path: &mut Vec<String>, on_entry: &mut F) -> Result<(), Error>
+    where
+        R: Read + Seek,
+        F: FnMut(&[String], &Entry),
+{
+    let buf = read_directory_block(reader, base_offset, file_length, position, length)?;
     let mut buf_reader = Cursor::new(buf.as_slice());
 
-    while let Some(header) = EntryHeader::try_read_next(&mut buf_reader)? {
-        match header.entry_type {
-            EntryType::Directory => {
-                let name_bytes = buf_reader.read_nul_terminated_byte_string()?;
-                let name = iso88591_bytes_to_string(&name_bytes);
+    loop {
+        if only_padding_remains(&buf_reader) {
+            break;
+        }
+        let Some(header) = EntryHeader::try_read_next(&mut buf_reader)? else { break };
+        let entry = read_entry(&mut buf_reader, header)?;
+        on_entry(dir_path, &entry);
+        if let Entry::Directory(dir) = entry {
+            dir_path.push(dir.name);
+            stream_directory_entries_recursive(reader, base_offset, file_length, dir.header.position, dir.header.size, dir_path, on_entry)?;
+            dir_path.pop();
+        }
+    }
 
-                let position = reader.stream_position()?;
-                let sub_entries = read_directory_entries_recursive(reader, header.position, header.size)?;
-                reader.seek(SeekFrom::Start(position))?;
+    Ok(())
+}
 
-                let directory = Directory {
-                    header,
-                    name,
-                    entries: sub_entries,
-                };
-                entries.push(Entry::Directory(directory));
-            },
-            EntryType::Resource => {
-                let id = buf_reader.read_u32_le()?;
+/// Reads the directory block at the given position, checking that it lies within the file.
+fn read_directory_block<R: Read + Seek>(reader: &mut R, base_offset: u64, file_length: u64, position: u32, length: u32) -> Result<Vec<u8>, Error> {
+    // check if the file is long enough to contain the whole directory
+    let available = file_length.saturating_sub(position.into());
+    if available < length.into() {
+        return Err(Error::TruncatedDirectory { position, expected: length, available });
+    }
+    trace!("reading directory block at {} ({} bytes)", position, length);
 
-                let mut extension_bytes = [0u8; 4];
-                buf_reader.read_exact(&mut extension_bytes)?;
-                extension_bytes.reverse();
-                let extension_slice = without_leading_zero_bytes(&extension_bytes);
-                let extension = iso88591_bytes_to_string(&extension_slice);
+    let mut buf = vec![0u8; length.try_into().unwrap()];
+    reader.seek(SeekFrom::Start(base_offset + u64::from(position)))?;
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
 
-                let num_keys = buf_reader.read_u32_le()?;
+/// Reads the rest of the entry with the given header from a directory block. Directories are
+/// returned without their entries.
+fn read_entry(buf_reader: &mut Cursor<&[u8]>, header: EntryHeader) -> Result<Entry, Error> {
+    match header.entry_type {
+        EntryType::Directory => {
+            let name_bytes = buf_reader.read_nul_terminated_slice()?;
+            let name = iso88591_bytes_to_string(name_bytes);
 
-                let name_bytes = buf_reader.read_nul_terminated_byte_string()?;
-                let name = iso88591_bytes_to_string(&name_bytes);
+            Ok(Entry::Directory(Directory {
+                header,
+                name,
+                entries: Vec::new(),
+                entries_loaded: false,
+            }))
+        },
+        EntryType::Resource => {
+            let id = buf_reader.read_u32_le()?;
 
-                let description_bytes = buf_reader.read_nul_terminated_byte_string()?;
-                let description = iso88591_bytes_to_string(&description_bytes);
+            let mut extension_bytes = [0u8; 4];
+            buf_reader.read_exact(&mut extension_bytes)?;
+            let extension = ExtensionOrder::Reversed.decode(&extension_bytes);
 
-                let mut keys = Vec::with_capacity(num_keys.try_into().unwrap());
-                for _ in 0..num_keys {
-                    let key = buf_reader.read_u32_le()?;
-                    keys.push(key);
-                }
+            let num_keys = buf_reader.read_u32_le()?;
 
-                let resource = Resource {
-                    header,
-                    id,
-                    extension,
-                    name,
-                    description,
-                    keys,
-                };
-                entries.push(Entry::Resource(resource));
-            },
-            EntryType::Other(type_code) => return Err(Error::UnknownEntryType { type_code }),
-        }
-    }
+            let name_bytes = buf_reader.read_nul_terminated_slice()?;
+            let name = iso88591_bytes_to_string(name_bytes);
 
-    Ok(entries)
+            let description_bytes = buf_reader.read_nul_terminated_slice()?;
+            let description = iso88591_bytes_to_string(description_bytes);
+
+            let mut keys = Vec::with_capacity(num_keys.try_into().unwrap());
+            for _ in 0..num_keys {
+                let key = buf_reader.read_u32_le()?;
+                keys.push(key);
+            }
+
+            Ok(Entry::Resource(Resource {
+                header,
+                id,
+                extension,
+                extension_bytes,
+                name,
+                description,
+                keys,
+            }))
+        },
+        EntryType::Other(type_code) => Err(Error::UnknownEntryType { type_code }),
+    }
 }
 
 
 fn without_trailing_zero_bytes(value: &[u8]) -> &[u8] {
     let mut ret = value;
-    while ret.len() > 0 && ret[ret.len() - 1] == 0x00 {
+    while !ret.is_empty() && ret[ret.len() - 1] == 0x00 {
         ret = &ret[..ret.len() - 1];
     }
     ret
@@ -409,7 +1216,7 @@ fn without_trailing_zero_bytes(value: &[u8]) -> &[u8] {
 
 fn without_leading_zero_bytes(value: &[u8]) -> &[u8] {
     let mut ret = value;
-    while ret.len() > 0 && ret[0] == 0x00 {
+    while !ret.is_empty() && ret[0] == 0x00 {
         ret = &ret[1..];
     }
     ret
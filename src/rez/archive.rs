@@ -0,0 +1,64 @@
+use std::io::{Read, Seek};
+
+use crate::rez::{Error, File, Resource};
+
+
+/// A REZ file along with the reader from which its data is read, allowing resources to be looked
+/// up and read by path.
+///
+/// Paths are looked up using [`File::entry_by_path_ignoring_case`], as the LithTech engine does not
+/// distinguish case either.
+///
+/// ```ignore
+/// let mut archive = Archive::open(std::fs::File::open("sounds.rez")?)?;
+/// if archive.exists("Sounds\\Boom.WAV") {
+///     let data = archive.read("sounds/boom.wav")?;
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Archive<R> {
+    reader: R,
+    rez_file: File,
+}
+impl<R: Read + Seek> Archive<R> {
+    /// Reads the header and the directory tree of the REZ file starting at the current position of
+    /// the given reader.
+    pub fn open(mut reader: R) -> Result<Self, Error> {
+        let rez_file = File::try_read(&mut reader)?;
+        Ok(Self {
+            reader,
+            rez_file,
+        })
+    }
+
+    /// Reads the stored data of the resource at the given path.
+    pub fn read(&mut self, path: &str) -> Result<Vec<u8>, Error> {
+        let res = self.rez_file.entry_by_path_ignoring_case(path)
+            .and_then(|entry| entry.as_resource())
+            .ok_or_else(|| Error::NoSuchEntry { path: path.to_owned() })?;
+        // the stored size is not trusted for preallocation, since it may exceed the file
+        let mut data = Vec::new();
+        self.rez_file.open_resource(&mut self.reader, res)
+            .read_to_end(&mut data)?;
+        Ok(data)
+    }
+}
+impl<R> Archive<R> {
+    /// Returns the directory tree of the REZ file.
+    pub fn rez_file(&self) -> &File { &self.rez_file }
+
+    /// Returns the reader from which the data is read and the REZ file.
+    pub fn into_inner(self) -> (R, File) { (self.reader, self.rez_file) }
+
+    /// Finds the resource at the given path.
+    pub fn get(&self, path: &str) -> Option<&Resource> {
+        self.rez_file.entry_by_path_ignoring_case(path)?
+            .as_resource()
+    }
+
+    /// Returns whether a resource exists at the given path.
+    pub fn exists(&self, path: &str) -> bool {
+        self.get(path).is_some()
+    }
+}
+
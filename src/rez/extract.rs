@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use std::io::{self, Cursor, Read, Seek};
+use std::path::Path;
+
+use crate::rez::{Entry, Error, ExtractDecision, File, Resource, RezReader};
+
+
+impl<R: Read + Seek> RezReader<R> {
+    /// Extracts all resources of the given REZ file that are selected by `filter` into
+    /// `output_directory`, mirroring the directory structure of the REZ file.
+    ///
+    /// Before each selected resource is written, `on_resource` is called with the path of the
+    /// resource (components separated by forward slashes), the resource itself and a reader
+    /// providing its data (decompressed if a decompressor has been set). The returned
+    /// [`ExtractDecision`] determines whether the resource is then written to
+    /// `<output_directory>/<path>`.
+    ///
+    /// The base offset of this reader is set to that of the REZ file while extracting and restored
+    /// afterwards.
+    pub fn extract_all<P, F>(&mut self, rez_file: &File, output_directory: &Path, filter: P, on_resource: F) -> io::Result<()>
+        where
+            P: Fn(&str, &Resource) -> bool,
+            F: FnMut(&str, &Resource, &mut dyn Read) -> io::Result<ExtractDecision>,
+    {
+        let previous_base_offset = std::mem::replace(&mut self.base_offset, rez_file.base_offset);
+        let result = self.extract_selected(rez_file, output_directory, filter, on_resource);
+        self.base_offset = previous_base_offset;
+        result
+    }
+
+    fn extract_selected<P, F>(&mut self, rez_file: &File, output_directory: &Path, filter: P, mut on_resource: F) -> io::Result<()>
+        where
+            P: Fn(&str, &Resource) -> bool,
+            F: FnMut(&str, &Resource, &mut dyn Read) -> io::Result<ExtractDecision>,
+    {
+        for (entry_path, entry) in rez_file.walk() {
+            let Entry::Resource(res) = entry else { continue };
+            if !filter(&entry_path, res) {
+                continue;
+            }
+
+            let decision = {
+                let mut data = self.resource_data_reader(res)?;
+                on_resource(&entry_path, res, &mut data)?
+            };
+            if decision != ExtractDecision::Default {
+                continue;
+            }
+
+            let mut output_path = output_directory.to_owned();
+            output_path.extend(entry_path.split('/'));
+            if let Some(parent) = output_path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let mut output = std::fs::File::create(&output_path)?;
+            let mut data = self.resource_data_reader(res)?;
+            io::copy(&mut data, &mut output)?;
+        }
+        Ok(())
+    }
+
+    /// Reads all resources of the given REZ file that are selected by `filter` into memory,
+    /// returning a map from the path of each resource (components separated by forward slashes) to
+    /// its data, decompressed if a decompressor has been set.
+    ///
+    /// All the data is held in memory at once, so this needs at least as much memory as the
+    /// selected resources are large. Use [`RezReader::extract_to_map_capped`] if the REZ file is
+    /// not trusted.
+    ///
+    /// The base offset of this reader is set to that of the REZ file while reading and restored
+    /// afterwards.
+    pub fn extract_to_map<P>(&mut self, rez_file: &File, filter: P) -> Result<HashMap<String, Vec<u8>>, Error>
+        where
+            P: Fn(&str, &Resource) -> bool,
+    {
+        self.extract_to_map_capped(rez_file, filter, u64::MAX)
+    }
+
+    /// Like [`RezReader::extract_to_map`], but fails with [`Error::SizeCapExceeded`] instead of
+    /// reading more than `max_total_bytes` bytes of data in total.
+    pub fn extract_to_map_capped<P>(&mut self, rez_file: &File, filter: P, max_total_bytes: u64) -> Result<HashMap<String, Vec<u8>>, Error>
+        where
+            P: Fn(&str, &Resource) -> bool,
+    {
+        let previous_base_offset = std::mem::replace(&mut self.base_offset, rez_file.base_offset);
+        let result = self.read_selected(rez_file, filter, max_total_bytes);
+        self.base_offset = previous_base_offset;
+        result
+    }
+
+    fn read_selected<P>(&mut self, rez_file: &File, filter: P, max_total_bytes: u64) -> Result<HashMap<String, Vec<u8>>, Error>
+        where
+            P: Fn(&str, &Resource) -> bool,
+    {
+        let mut map = HashMap::new();
+        let mut total_bytes: u64 = 0;
+        for (entry_path, entry) in rez_file.walk() {
+            let Entry::Resource(res) = entry else { continue };
+            if !filter(&entry_path, res) {
+                continue;
+            }
+
+            // check before reading to avoid allocating too much...
+            let required = total_bytes + u64::from(res.header.size);
+            if required > max_total_bytes {
+                return Err(Error::SizeCapExceeded { cap: max_total_bytes, required });
+            }
+
+            // ...and after reading, since decompression may enlarge the data
+            let data = self.read_resource(res)?;
+            let required = total_bytes + u64::try_from(data.len()).unwrap();
+            if required > max_total_bytes {
+                return Err(Error::SizeCapExceeded { cap: max_total_bytes, required });
+            }
+
+            total_bytes = required;
+            map.insert(entry_path, data);
+        }
+        Ok(map)
+    }
+
+    /// Returns a reader over the data of the given resource, decompressed if a decompressor has
+    /// been set. Data that has to be decompressed is read and decompressed in full beforehand;
+    /// otherwise, it is read as the returned reader is read from.
+    pub fn resource_data_reader(&mut self, res: &Resource) -> io::Result<Box<dyn Read + '_>> {
+        if self.decompressor.is_some() {
+            let data = self.read_resource(res)?;
+            Ok(Box::new(Cursor::new(data)))
+        } else {
+            Ok(Box::new(self.open_resource(res)))
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use std::io::{Cursor, Seek, SeekFrom};
+use std::ops::Deref;
+
+use memmap2::Mmap;
+
+use crate::rez::{Error, File, Resource};
+
+
+/// A REZ file whose underlying file has been mapped into memory.
+///
+/// The directory tree is read from the mapped bytes, and the data of the resources can be accessed
+/// without copying it or issuing any further system calls.
+#[derive(Debug)]
+pub struct MappedFile {
+    map: Mmap,
+    rez_file: File,
+}
+impl MappedFile {
+    /// Maps the given file into memory and reads the REZ file starting at the given offset within
+    /// it.
+    ///
+    /// The file must not be modified or truncated while it is mapped; on most platforms, doing so
+    /// changes the data returned by this structure or makes accessing it crash the program.
+    pub fn open(file: &std::fs::File, base_offset: u64) -> Result<Self, Error> {
+        // SAFETY: the caller is responsible for not modifying the file while it is mapped
+        let map = unsafe { Mmap::map(file) }?;
+        let mut cursor = Cursor::new(&map[..]);
+        cursor.seek(SeekFrom::Start(base_offset))?;
+        let rez_file = File::try_read(&mut cursor)?;
+        Ok(Self {
+            map,
+            rez_file,
+        })
+    }
+
+    /// Returns the REZ file read from the mapped bytes.
+    pub fn rez_file(&self) -> &File { &self.rez_file }
+
+    /// Returns the REZ file read from the mapped bytes, e.g. to decode its extensions anew.
+    pub fn rez_file_mut(&mut self) -> &mut File { &mut self.rez_file }
+
+    /// Returns all the bytes of the underlying file.
+    pub fn bytes(&self) -> &[u8] { self.map.deref() }
+
+    /// Returns the stored data of the given resource without copying it, or `None` if it extends
+    /// past the end of the underlying file.
+    pub fn resource_data(&self, res: &Resource) -> Option<&[u8]> {
+        self.rez_file.resource_data(&self.map, res)
+    }
+
+    /// Returns a reader over all the bytes of the underlying file, e.g. for use with
+    /// [`RezReader`](crate::rez::RezReader).
+    pub fn reader(&self) -> Cursor<&[u8]> {
+        Cursor::new(self.bytes())
+    }
+}
@@ -0,0 +1,131 @@
+use std::io::{self, Read, Seek, SeekFrom};
+
+use crate::rez::{File, Resource, RezReader};
+
+
+/// Walks the resources of a REZ file, providing a reader over the data of each one.
+///
+/// This cannot be an [`Iterator`]: every [`ResourceReader`] mutably borrows the underlying reader,
+/// so only one of them may exist at a time. Call [`ResourcesWithReaders::next_resource`] in a loop
+/// instead; the reader it returns must be dropped before the next call.
+///
+/// ```ignore
+/// let mut resources = rez_reader.resources_with_readers(&rez_file);
+/// while let Some((path, mut data)) = resources.next_resource() {
+///     // read from data if the path is interesting, or ignore it to skip the resource
+/// }
+/// ```
+#[derive(Debug)]
+pub struct ResourcesWithReaders<'f, 'r, R> {
+    reader: &'r mut R,
+    base_offset: u64,
+    resources: std::vec::IntoIter<(String, &'f Resource)>,
+}
+impl<'f, 'r, R: Read + Seek> ResourcesWithReaders<'f, 'r, R> {
+    /// Returns the path of the next resource (components separated by forward slashes) and a reader
+    /// over its data, or `None` once all resources have been visited.
+    ///
+    /// Nothing is read from the REZ file until the returned reader is first read from.
+    pub fn next_resource(&mut self) -> Option<(String, ResourceReader<'_, R>)> {
+        let (path, res) = self.resources.next()?;
+        let reader = ResourceReader {
+            reader: &mut *self.reader,
+            position: self.base_offset + u64::from(res.header.position),
+            remaining: res.header.size.into(),
+            seeked: false,
+        };
+        Some((path, reader))
+    }
+
+    /// Returns the number of resources that have not been visited yet.
+    pub fn remaining(&self) -> usize {
+        self.resources.len()
+    }
+}
+
+
+/// Reads the stored data of a single resource, seeking to it on the first read.
+///
+/// Reading stops at the end of the resource; if the underlying reader ends before that, reading
+/// fails with [`io::ErrorKind::UnexpectedEof`]. Obtained from [`File::open_resource`],
+/// [`RezReader::open_resource`] or [`ResourcesWithReaders::next_resource`].
+#[derive(Debug)]
+pub struct ResourceReader<'r, R> {
+    reader: &'r mut R,
+    position: u64,
+    remaining: u64,
+    seeked: bool,
+}
+impl<'r, R> ResourceReader<'r, R> {
+    /// Returns the number of bytes of the resource that have not been read yet.
+    pub fn remaining(&self) -> u64 {
+        self.remaining
+    }
+}
+impl<'r, R: Read + Seek> Read for ResourceReader<'r, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 || buf.is_empty() {
+            return Ok(0);
+        }
+        if !self.seeked {
+            self.reader.seek(SeekFrom::Start(self.position))?;
+            self.seeked = true;
+        }
+
+        let max_count: usize = self.remaining.min(buf.len().try_into().unwrap()).try_into().unwrap();
+        let read_count = self.reader.read(&mut buf[..max_count])?;
+        if read_count == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.remaining -= u64::try_from(read_count).unwrap();
+        Ok(read_count)
+    }
+}
+
+
+impl File {
+    /// Returns a reader over the stored data of the given resource of this REZ file, which is read
+    /// from the given underlying reader. The data is provided as stored, without decompression.
+    ///
+    /// Nothing is read until the returned reader is first read from, at which point it seeks to the
+    /// start of the resource.
+    pub fn open_resource<'r, R: Read + Seek>(&self, reader: &'r mut R, res: &Resource) -> ResourceReader<'r, R> {
+        ResourceReader {
+            reader,
+            position: self.base_offset + u64::from(res.header.position),
+            remaining: res.header.size.into(),
+            seeked: false,
+        }
+    }
+}
+
+impl<R: Read + Seek> RezReader<R> {
+    /// Returns a reader over the stored data of the given resource, taking the base offset of this
+    /// reader into account. The decompressor of this reader is not applied.
+    ///
+    /// Nothing is read until the returned reader is first read from, at which point it seeks to the
+    /// start of the resource.
+    pub fn open_resource(&mut self, res: &Resource) -> ResourceReader<'_, R> {
+        ResourceReader {
+            reader: &mut self.reader,
+            position: self.base_offset + u64::from(res.header.position),
+            remaining: res.header.size.into(),
+            seeked: false,
+        }
+    }
+
+    /// Walks the resources of the given REZ file in directory order, providing a lazily seeking
+    /// reader over the stored data of each one. See [`ResourcesWithReaders`] for how to use it.
+    ///
+    /// The data is provided as stored; the decompressor of this reader is not applied.
+    pub fn resources_with_readers<'f>(&mut self, rez_file: &'f File) -> ResourcesWithReaders<'f, '_, R> {
+        let resources: Vec<(String, &Resource)> = rez_file.walk()
+            .filter_map(|(path, entry)| Some((path, entry.as_resource()?)))
+            .collect();
+        ResourcesWithReaders {
+            reader: &mut self.reader,
+            base_offset: rez_file.base_offset,
+            resources: resources.into_iter(),
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use std::collections::HashSet;
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::rez::{iter_entry_headers, EntryHeader, EntryType, Error, File, FileHeader, OutOfBounds};
+
+
+impl File {
+    /// Checks that all directory blocks and the data of all resources lie within the REZ file,
+    /// returning the entries that do not.
+    ///
+    /// Only the entry headers are read; names, descriptions and keys are skipped without being
+    /// decoded, which makes this much faster than [`File::try_read`]. Directories that are out of
+    /// bounds or have already been visited are not descended into. Errors in the structure of the
+    /// directory blocks are still returned as errors.
+    ///
+    /// See [`File::try_read`] for the assumptions about the reader.
+    pub fn validate_offsets_only<R: Read + Seek>(r: &mut R) -> Result<Vec<OutOfBounds>, Error> {
+        let base_offset = r.stream_position()?;
+        let file_size = r.seek(SeekFrom::End(0))? - base_offset;
+        r.seek(SeekFrom::Start(base_offset))?;
+        let header = FileHeader::try_read(r)?;
+
+        let root_header = EntryHeader {
+            entry_type: EntryType::Directory,
+            position: header.root_dir_position,
+            size: header.root_dir_size,
+            time: header.root_dir_time,
+        };
+        let mut findings = Vec::new();
+        if !is_within(&root_header, file_size) {
+            findings.push(OutOfBounds { directory_position: None, index: 0, header: root_header });
+            return Ok(findings);
+        }
+
+        let mut visited = HashSet::new();
+        validate_directory_recursive(r, base_offset, file_size, &root_header, &mut visited, &mut findings)?;
+        Ok(findings)
+    }
+}
+
+
+fn is_within(header: &EntryHeader, file_size: u64) -> bool {
+    u64::from(header.position) + u64::from(header.size) <= file_size
+}
+
+fn validate_directory_recursive<R: Read + Seek>(r: &mut R, base_offset: u64, file_size: u64, dir_header: &EntryHeader, visited: &mut HashSet<u32>, findings: &mut Vec<OutOfBounds>) -> Result<(), Error> {
+    if !visited.insert(dir_header.position) {
+        return Ok(());
+    }
+
+    let mut block = vec![0u8; dir_header.size.try_into().unwrap()];
+    r.seek(SeekFrom::Start(base_offset + u64::from(dir_header.position)))?;
+    r.read_exact(&mut block)?;
+
+    for (index, header) in iter_entry_headers(&block).enumerate() {
+        let header = header?;
+        if !is_within(&header, file_size) {
+            findings.push(OutOfBounds { directory_position: Some(dir_header.position), index, header });
+        } else if header.entry_type == EntryType::Directory {
+            validate_directory_recursive(r, base_offset, file_size, &header, visited, findings)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,539 @@
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::rez::{compare_entry_names, join_path, sort_entries_recursive, Directory, Entry, EntryHeader, EntryType, Error, File, Resource};
+
+
+const HEADER_LENGTH: u64 = 2 + 60 + 2 + 60 + 3 + 4 + 9*4 + 1;
+const COPY_BUFFER_SIZE: usize = 64 * 1024;
+
+
+/// Statistics about the names and keys in a REZ file, which are stored in its header.
+#[derive(Clone, Copy, Debug, Default)]
+struct Largest {
+    key_ary: u32,
+    dir_name_size: u32,
+    rez_name_size: u32,
+    comment_size: u32,
+}
+
+
+impl File {
+    /// Writes this REZ file as a version 1 REZ file.
+    ///
+    /// The contents of each resource are obtained by calling `open_data` with the path of the
+    /// resource (components separated by forward slashes) and the resource itself. The positions and
+    /// sizes of all entries, the stored extension bytes of all resources as well as the file size
+    /// and base offset are updated to match the written file.
+    pub fn write<W, F, D>(&mut self, writer: &mut W, mut open_data: F) -> Result<(), Error>
+        where
+            W: Write + Seek,
+            F: FnMut(&str, &Resource) -> io::Result<D>,
+            D: Read,
+    {
+        // writing would drop the entries of directories that have not been loaded
+        if self.walk().any(|(_path, entry)| entry.as_directory().is_some_and(|dir| !dir.entries_loaded)) {
+            return Err(Error::CannotModify { reason: "not all directories have been loaded" });
+        }
+
+        // skip the header for now; it is written last once all the positions are known
+        writer.seek(SeekFrom::Start(HEADER_LENGTH))?;
+        self.base_offset = 0;
+
+        let mut position = HEADER_LENGTH;
+        write_data_recursive(writer, &mut self.root_entries, "", &mut position, &mut open_data)?;
+
+        let mut largest = Largest::default();
+        let (root_dir_position, root_dir_size) = write_directory_recursive(writer, &mut self.root_entries, &mut position, &mut largest)?;
+
+        self.finish_writing(writer, root_dir_position, root_dir_size, position, &largest)
+    }
+
+    /// Writes the header once the data and the directory blocks have been written, leaving the
+    /// writer at the end of the file.
+    fn finish_writing<W: Write + Seek>(&mut self, writer: &mut W, root_dir_position: u32, root_dir_size: u32, position: u64, largest: &Largest) -> Result<(), Error> {
+        let next_write_pos = to_u32_position(position)?;
+        writer.seek(SeekFrom::Start(self.base_offset))?;
+        self.write_header(writer, root_dir_position, root_dir_size, next_write_pos, largest)?;
+        writer.seek(SeekFrom::Start(self.base_offset + position))?;
+        writer.flush()?;
+        self.file_size = position;
+        Ok(())
+    }
+
+    fn write_header<W: Write>(&self, writer: &mut W, root_dir_position: u32, root_dir_size: u32, next_write_pos: u32, largest: &Largest) -> Result<(), Error> {
+        let mut header = Vec::with_capacity(HEADER_LENGTH.try_into().unwrap());
+        header.extend_from_slice(b"\r\n");
+        header.extend_from_slice(&space_padded::<60>(&self.file_type)?);
+        header.extend_from_slice(b"\r\n");
+        header.extend_from_slice(&space_padded::<60>(&self.user_title)?);
+        header.extend_from_slice(b"\r\n\x1A");
+        header.extend_from_slice(&1u32.to_le_bytes()); // version
+        header.extend_from_slice(&root_dir_position.to_le_bytes());
+        header.extend_from_slice(&root_dir_size.to_le_bytes());
+        header.extend_from_slice(&self.time.to_le_bytes()); // root_dir_time
+        header.extend_from_slice(&next_write_pos.to_le_bytes());
+        header.extend_from_slice(&self.time.to_le_bytes());
+        header.extend_from_slice(&largest.key_ary.to_le_bytes());
+        header.extend_from_slice(&largest.dir_name_size.to_le_bytes());
+        header.extend_from_slice(&largest.rez_name_size.to_le_bytes());
+        header.extend_from_slice(&largest.comment_size.to_le_bytes());
+        header.push(if self.is_sorted { 0x01 } else { 0x00 });
+        debug_assert_eq!(u64::try_from(header.len()).unwrap(), HEADER_LENGTH);
+
+        writer.write_all(&header)?;
+        Ok(())
+    }
+}
+
+
+/// Writes a version 1 REZ file incrementally.
+///
+/// Unlike [`File::write`], which requires the whole entry tree upfront, this writes the data of
+/// each resource as soon as it is added. The directory blocks and the header are written by
+/// [`Writer::finish`].
+///
+/// A writer can also be opened on an existing REZ file using [`Writer::open`] to add entries to it.
+#[derive(Debug)]
+pub struct Writer<W> {
+    writer: W,
+    file: File,
+    position: u64,
+}
+impl<W: Write + Seek> Writer<W> {
+    /// Starts writing a REZ file with the given header strings and time at the current position of
+    /// the writer, which must be at its start.
+    pub fn new(mut writer: W, file_type: &str, user_title: &str, time: u32) -> Result<Self, Error> {
+        // skip the header for now; it is written by finish once all the positions are known
+        writer.seek(SeekFrom::Start(HEADER_LENGTH))?;
+        let file = File {
+            file_type: file_type.to_owned(),
+            user_title: user_title.to_owned(),
+            version: 1,
+            time,
+            is_sorted: false,
+            file_size: 0,
+            base_offset: 0,
+            root_entries: Vec::new(),
+        };
+        Ok(Self { writer, file, position: HEADER_LENGTH })
+    }
+
+    /// Returns the REZ file as written so far. The positions and sizes of directories are only
+    /// filled in by [`Writer::finish`].
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+
+    /// Sets whether the entries of each directory are sorted by name when finishing, in which case
+    /// the REZ file is also marked as sorted.
+    pub fn set_sorted(&mut self, is_sorted: bool) {
+        self.file.is_sorted = is_sorted;
+    }
+
+    /// Adds a directory at the given path, creating any missing parent directories with the same
+    /// time. Adding a directory that already exists is not an error; as everywhere in REZ files,
+    /// names are compared without regard to case.
+    ///
+    /// Path components may be separated by forward slashes or backslashes.
+    pub fn add_directory(&mut self, path: &str, time: u32) -> Result<(), Error> {
+        directory_entries_mut(&mut self.file.root_entries, path, time)?;
+        Ok(())
+    }
+
+    /// Writes the data of a resource and adds it to the directory at the given path, which is
+    /// created as with [`Writer::add_directory`] if it does not exist yet. Fails if the directory
+    /// already contains an entry with the same name, compared without regard to case.
+    ///
+    /// The position and size in the header of the resource are filled in; the remaining fields
+    /// are taken as they are.
+    pub fn add_resource<D: Read>(&mut self, dir_path: &str, mut res: Resource, data: &mut D) -> Result<(), Error> {
+        let time = self.file.time;
+        let entries = directory_entries_mut(&mut self.file.root_entries, dir_path, time)?;
+        let entry_name = res.file_name();
+        if entries.iter().any(|e| compare_entry_names(&e.name(), &entry_name).is_eq()) {
+            return Err(Error::DuplicateEntry { path: join_path(dir_path, &entry_name) });
+        }
+
+        self.writer.seek(SeekFrom::Start(self.file.base_offset + self.position))?;
+        let size = io::copy(data, &mut self.writer)?;
+        res.header.entry_type = EntryType::Resource;
+        res.header.position = to_u32_position(self.position)?;
+        res.header.size = to_u32_position(size)?;
+        self.position += size;
+        to_u32_position(self.position)?;
+
+        entries.push(Entry::Resource(res));
+        Ok(())
+    }
+
+    /// Removes the resource or directory at the given path, along with all entries within it, and
+    /// returns it.
+    ///
+    /// The data of removed resources stays in the file as unused space; see [`Writer::compact`].
+    pub fn remove(&mut self, path: &str) -> Result<Entry, Error> {
+        let (dir_path, name) = split_parent(path);
+        let entries = existing_directory_entries_mut(&mut self.file.root_entries, dir_path)
+            .ok_or_else(|| Error::NoSuchEntry { path: path.to_owned() })?;
+        let index = entries.iter().position(|e| e.name() == name)
+            .ok_or_else(|| Error::NoSuchEntry { path: path.to_owned() })?;
+        Ok(entries.remove(index))
+    }
+
+    /// Renames the resource or directory at the path `from` or moves it to another directory.
+    ///
+    /// If `to` is the path of an existing directory, the entry is moved into it, keeping its name.
+    /// Otherwise, `to` is the new path of the entry; its parent directory must exist. The name of a
+    /// resource is split into name and extension at the last dot.
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<(), Error> {
+        let from = normalize_path(from);
+        let (_, from_name) = split_parent(&from);
+        let mut to = normalize_path(to);
+        if existing_directory_entries_mut(&mut self.file.root_entries, &to).is_some() {
+            to = join_path(&to, from_name);
+        }
+        if to == from || to.starts_with(&format!("{}/", from)) {
+            return Err(Error::MoveIntoItself { path: from });
+        }
+
+        let (to_dir, to_name) = split_parent(&to);
+        let to_entries = existing_directory_entries_mut(&mut self.file.root_entries, to_dir)
+            .ok_or_else(|| Error::NoSuchEntry { path: to_dir.to_owned() })?;
+        // changing only the case of a name within the same directory is fine
+        let from_dir = split_parent(&from).0;
+        if to_entries.iter().any(|e| compare_entry_names(&e.name(), to_name).is_eq() && !(to_dir == from_dir && e.name() == from_name)) {
+            return Err(Error::DuplicateEntry { path: to.clone() });
+        }
+        let from_entry = self.file.entry_by_path(&from)
+            .ok_or_else(|| Error::NoSuchEntry { path: from.clone() })?;
+        let (name, extension) = to_name.rsplit_once('.')
+            .unwrap_or((to_name, ""));
+        if from_entry.is_resource() && extension.len() > 4 {
+            return Err(Error::ExtensionTooLong { extension: extension.to_owned() });
+        }
+
+        let mut entry = self.remove(&from)?;
+        match &mut entry {
+            Entry::Directory(dir) => dir.name = to_name.to_owned(),
+            Entry::Resource(res) => {
+                res.name = name.to_owned();
+                res.extension = extension.to_owned();
+            },
+        }
+        directory_entries_mut(&mut self.file.root_entries, to_dir, 0)?.push(entry);
+        Ok(())
+    }
+
+    /// Writes the directory blocks and the header, returning the writer and the written REZ file.
+    pub fn finish(mut self) -> Result<(W, File), Error> {
+        if self.file.is_sorted {
+            sort_entries_recursive(&mut self.file.root_entries);
+        }
+
+        let mut largest = Largest::default();
+        self.writer.seek(SeekFrom::Start(self.file.base_offset + self.position))?;
+        let (root_dir_position, root_dir_size) = write_directory_recursive(&mut self.writer, &mut self.file.root_entries, &mut self.position, &mut largest)?;
+        self.file.finish_writing(&mut self.writer, root_dir_position, root_dir_size, self.position, &largest)?;
+        Ok((self.writer, self.file))
+    }
+}
+impl<W: Read + Write + Seek> Writer<W> {
+    /// Opens the REZ file starting at the current position of the given reader-writer in order to
+    /// add entries to it.
+    ///
+    /// The data of new resources is written at the position recorded in the header as the next
+    /// write position, unless that would overwrite the data of existing resources, in which case it
+    /// is appended to the end of the file instead. The directory blocks are written anew after the
+    /// data of the new resources; the previous blocks are left in place as unused space.
+    ///
+    /// Only plain version 1 REZ files can be opened this way.
+    pub fn open(mut writer: W) -> Result<Self, Error> {
+        let (file, header) = File::try_read_with_header(&mut writer)?;
+        if header.version != 1 || header.length() != HEADER_LENGTH {
+            return Err(Error::CannotModify { reason: "only version 1 REZ files can be modified" });
+        }
+        writer.seek(SeekFrom::Start(file.base_offset))?;
+        let mut first_byte = [0u8];
+        writer.read_exact(&mut first_byte)?;
+        if first_byte[0] != b'\r' {
+            return Err(Error::CannotModify { reason: "obfuscated REZ files cannot be modified" });
+        }
+
+        let data_end = data_end_recursive(&file.root_entries).max(HEADER_LENGTH);
+        let next_write_pos = u64::from(header.next_write_pos);
+        let position = if (data_end..=file.file_size).contains(&next_write_pos) {
+            next_write_pos
+        } else {
+            file.file_size
+        };
+        Ok(Self { writer, file, position })
+    }
+
+    /// Moves the data of all resources towards the start of the file, removing any unused space
+    /// between them, such as that left by removed resources or previous directory blocks.
+    ///
+    /// Resources whose data overlaps keep sharing it. The file is not truncated; the end of the
+    /// compacted REZ file is known once [`Writer::finish`] has been called.
+    pub fn compact(&mut self) -> Result<(), Error> {
+        let mut resources = Vec::new();
+        collect_resources_mut(&mut self.file.root_entries, &mut resources);
+        resources.sort_by_key(|res| (res.header.position, res.header.size));
+
+        let base_offset = self.file.base_offset;
+        let mut buf = vec![0u8; COPY_BUFFER_SIZE];
+        let mut position = HEADER_LENGTH;
+        // the old start and end as well as the new start of the run of data being moved
+        let mut run: Option<(u64, u64, u64)> = None;
+        for res in resources {
+            // resources without data have no extent to move, whatever their position
+            if res.header.size == 0 {
+                res.header.position = to_u32_position(position)?;
+                continue;
+            }
+            let old_start = u64::from(res.header.position);
+            let old_end = old_start + u64::from(res.header.size);
+            if old_start < HEADER_LENGTH {
+                return Err(Error::CannotModify { reason: "resource data overlaps the header" });
+            }
+
+            let (run_old_start, run_old_end, run_new_start) = match run {
+                Some((run_old_start, run_old_end, run_new_start)) if old_start < run_old_end
+                    => (run_old_start, run_old_end, run_new_start),
+                _ => (old_start, old_start, position),
+            };
+            if old_end > run_old_end {
+                // data is only ever moved towards the start, so copying in ascending chunks never
+                // overwrites data before it has been read
+                let mut copy_from = run_old_end;
+                while copy_from < old_end {
+                    let chunk_length = usize::try_from((old_end - copy_from).min(u64::try_from(buf.len()).unwrap())).unwrap();
+                    self.writer.seek(SeekFrom::Start(base_offset + copy_from))?;
+                    self.writer.read_exact(&mut buf[..chunk_length])?;
+                    self.writer.seek(SeekFrom::Start(base_offset + position))?;
+                    self.writer.write_all(&buf[..chunk_length])?;
+                    copy_from += u64::try_from(chunk_length).unwrap();
+                    position += u64::try_from(chunk_length).unwrap();
+                }
+            }
+            res.header.position = to_u32_position(run_new_start + (old_start - run_old_start))?;
+            run = Some((run_old_start, run_old_end.max(old_end), run_new_start));
+        }
+
+        self.position = position;
+        Ok(())
+    }
+}
+
+
+/// Returns the entries of the directory at the given path, or `None` if there is no such directory.
+fn existing_directory_entries_mut<'a>(root_entries: &'a mut Vec<Entry>, path: &str) -> Option<&'a mut Vec<Entry>> {
+    let mut entries = root_entries;
+    for component in path.split(['/', '\\']).filter(|c| !c.is_empty()) {
+        entries = match entries.iter_mut().find(|e| e.name() == component)? {
+            Entry::Directory(dir) => &mut dir.entries,
+            Entry::Resource(_) => return None,
+        };
+    }
+    Some(entries)
+}
+
+/// Joins the non-empty components of the path using forward slashes.
+fn normalize_path(path: &str) -> String {
+    path.split(['/', '\\'])
+        .filter(|c| !c.is_empty())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Splits the path into the path of the parent directory and the name of the entry.
+fn split_parent(path: &str) -> (&str, &str) {
+    let path = path.trim_end_matches(['/', '\\']);
+    path.rsplit_once(['/', '\\'])
+        .unwrap_or(("", path))
+}
+
+fn collect_resources_mut<'a>(entries: &'a mut [Entry], resources: &mut Vec<&'a mut Resource>) {
+    for entry in entries {
+        match entry {
+            Entry::Directory(dir) => collect_resources_mut(&mut dir.entries, resources),
+            Entry::Resource(res) => resources.push(res),
+        }
+    }
+}
+
+/// Returns the position just after the data of the resource that ends last.
+fn data_end_recursive(entries: &[Entry]) -> u64 {
+    entries.iter()
+        .map(|entry| match entry {
+            Entry::Directory(dir) => data_end_recursive(&dir.entries),
+            Entry::Resource(res) => u64::from(res.header.position) + u64::from(res.header.size),
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+
+/// Returns the entries of the directory at the given path, creating it and its parents with the
+/// given time if necessary.
+fn directory_entries_mut<'a>(root_entries: &'a mut Vec<Entry>, path: &str, time: u32) -> Result<&'a mut Vec<Entry>, Error> {
+    let mut entries = root_entries;
+    let mut walked = String::new();
+    for component in path.split(['/', '\\']).filter(|c| !c.is_empty()) {
+        walked = join_path(&walked, component);
+        let index = match entries.iter().position(|e| compare_entry_names(&e.name(), component).is_eq()) {
+            Some(i) => i,
+            None => {
+                entries.push(Entry::Directory(Directory {
+                    header: EntryHeader {
+                        entry_type: EntryType::Directory,
+                        position: 0,
+                        size: 0,
+                        time,
+                    },
+                    name: component.to_owned(),
+                    entries: Vec::new(),
+                    entries_loaded: true,
+                }));
+                entries.len() - 1
+            },
+        };
+        entries = match &mut entries[index] {
+            Entry::Directory(dir) => &mut dir.entries,
+            Entry::Resource(_) => return Err(Error::DuplicateEntry { path: walked }),
+        };
+    }
+    Ok(entries)
+}
+
+fn write_data_recursive<W, F, D>(writer: &mut W, entries: &mut [Entry], base_path: &str, position: &mut u64, open_data: &mut F) -> Result<(), Error>
+    where
+        W: Write,
+        F: FnMut(&str, &Resource) -> io::Result<D>,
+        D: Read,
+{
+    for entry in entries {
+        let entry_path = join_path(base_path, &entry.name());
+
+        match entry {
+            Entry::Directory(dir) => {
+                write_data_recursive(writer, &mut dir.entries, &entry_path, position, open_data)?;
+            },
+            Entry::Resource(res) => {
+                let mut data = open_data(&entry_path, res)?;
+                let size = io::copy(&mut data, writer)?;
+
+                res.header.position = to_u32_position(*position)?;
+                res.header.size = to_u32_position(size)?;
+                *position += size;
+                to_u32_position(*position)?;
+            },
+        }
+    }
+    Ok(())
+}
+
+/// Writes the directory block for the given entries, preceded by the blocks of all their
+/// subdirectories. Returns the position and size of the block.
+fn write_directory_recursive<W: Write>(writer: &mut W, entries: &mut [Entry], position: &mut u64, largest: &mut Largest) -> Result<(u32, u32), Error> {
+    // subdirectories first, so that their positions are known
+    for entry in entries.iter_mut() {
+        if let Entry::Directory(dir) = entry {
+            let (dir_position, dir_size) = write_directory_recursive(writer, &mut dir.entries, position, largest)?;
+            dir.header.position = dir_position;
+            dir.header.size = dir_size;
+        }
+    }
+
+    let mut block = Vec::new();
+    for entry in entries.iter_mut() {
+        match entry {
+            Entry::Directory(dir) => encode_directory(dir, &mut block, largest)?,
+            Entry::Resource(res) => encode_resource(res, &mut block, largest)?,
+        }
+    }
+
+    writer.write_all(&block)?;
+    let block_position = to_u32_position(*position)?;
+    let block_size = to_u32_position(u64::try_from(block.len()).unwrap())?;
+    *position += u64::try_from(block.len()).unwrap();
+    to_u32_position(*position)?;
+    Ok((block_position, block_size))
+}
+
+fn encode_directory(dir: &Directory, block: &mut Vec<u8>, largest: &mut Largest) -> Result<(), Error> {
+    let name = nul_terminated_iso88591(&dir.name)?;
+    largest.dir_name_size = largest.dir_name_size.max(name.len().try_into().unwrap());
+
+    block.extend_from_slice(&u32::from(EntryType::Directory).to_le_bytes());
+    block.extend_from_slice(&dir.header.position.to_le_bytes());
+    block.extend_from_slice(&dir.header.size.to_le_bytes());
+    block.extend_from_slice(&dir.header.time.to_le_bytes());
+    block.extend_from_slice(&name);
+    Ok(())
+}
+
+fn encode_resource(res: &mut Resource, block: &mut Vec<u8>, largest: &mut Largest) -> Result<(), Error> {
+    let name = nul_terminated_iso88591(&res.name)?;
+    let description = nul_terminated_iso88591(&res.description)?;
+    let extension = nul_terminated_iso88591(&res.extension)?;
+    let extension = &extension[..extension.len()-1];
+    if extension.len() > 4 {
+        return Err(Error::ExtensionTooLong { extension: res.extension.clone() });
+    }
+    // the extension is stored reversed and padded with NUL bytes at the end
+    let mut extension_bytes = [0u8; 4];
+    for (target, source) in extension_bytes.iter_mut().zip(extension.iter().rev()) {
+        *target = *source;
+    }
+    res.extension_bytes = extension_bytes;
+
+    let num_keys: u32 = res.keys.len().try_into().unwrap();
+    largest.key_ary = largest.key_ary.max(num_keys);
+    largest.rez_name_size = largest.rez_name_size.max(name.len().try_into().unwrap());
+    largest.comment_size = largest.comment_size.max(description.len().try_into().unwrap());
+
+    block.extend_from_slice(&u32::from(EntryType::Resource).to_le_bytes());
+    block.extend_from_slice(&res.header.position.to_le_bytes());
+    block.extend_from_slice(&res.header.size.to_le_bytes());
+    block.extend_from_slice(&res.header.time.to_le_bytes());
+    block.extend_from_slice(&res.id.to_le_bytes());
+    block.extend_from_slice(&extension_bytes);
+    block.extend_from_slice(&num_keys.to_le_bytes());
+    block.extend_from_slice(&name);
+    block.extend_from_slice(&description);
+    for key in &res.keys {
+        block.extend_from_slice(&key.to_le_bytes());
+    }
+    Ok(())
+}
+
+fn to_u32_position(position: u64) -> Result<u32, Error> {
+    position.try_into()
+        .map_err(|_| Error::FileTooLarge { size: position })
+}
+
+/// Encodes the string as ISO-8859-1 and appends a NUL byte.
+fn nul_terminated_iso88591(string: &str) -> Result<Vec<u8>, Error> {
+    let mut ret = Vec::with_capacity(string.len() + 1);
+    for c in string.chars() {
+        let b: u8 = u32::from(c).try_into()
+            .map_err(|_| Error::UnencodableString { string: string.to_owned() })?;
+        if b == 0x00 {
+            return Err(Error::UnencodableString { string: string.to_owned() });
+        }
+        ret.push(b);
+    }
+    ret.push(0x00);
+    Ok(ret)
+}
+
+/// Encodes the string as ISO-8859-1 and pads it with spaces to the given length.
+fn space_padded<const N: usize>(string: &str) -> Result<[u8; N], Error> {
+    let encoded = nul_terminated_iso88591(string)?;
+    let encoded = &encoded[..encoded.len()-1];
+    if encoded.len() > N {
+        return Err(Error::UnencodableString { string: string.to_owned() });
+    }
+    let mut ret = [b' '; N];
+    ret[..encoded.len()].copy_from_slice(encoded);
+    Ok(ret)
+}
@@ -0,0 +1,220 @@
+//! Serving the contents of a REZ file over HTTP.
+//!
+//! Directories are served as HTML indexes linking to their entries; a directory requested without
+//! a trailing slash is redirected to the path with one. Resources are served as
+//! `application/octet-stream` and support single byte ranges (`Range: bytes=...`) so that large
+//! resources can be fetched piecewise. Only `GET` and `HEAD` requests are supported.
+
+
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+
+use lithrez::rez;
+use log::warn;
+use tiny_http::{Header, Method, Request, Response, Server, StatusCode};
+
+
+/// Answers the requests arriving at the given server, reading the data of the resources from the
+/// given file.
+pub fn serve(server: &Server, file: &File, rez_file: &rez::File) {
+    for request in server.incoming_requests() {
+        let url = request.url().to_owned();
+        if let Err(e) = handle_request(request, file, rez_file) {
+            warn!("failed to respond to request for {}: {}", url, e);
+        }
+    }
+}
+
+fn handle_request(request: Request, file: &File, rez_file: &rez::File) -> io::Result<()> {
+    if !matches!(request.method(), Method::Get | Method::Head) {
+        let response = text_response(405, "method not allowed")?
+            .with_header(header("Allow", "GET, HEAD")?);
+        return request.respond(response);
+    }
+
+    // the query string is irrelevant
+    let url_path = request.url().split(['?', '#']).next().unwrap_or("").to_owned();
+    let Some(path) = percent_decode(&url_path) else {
+        return request.respond(text_response(400, "invalid percent-encoding")?);
+    };
+    let entry_path = path.trim_matches('/');
+
+    let entries = if entry_path.is_empty() {
+        &rez_file.root_entries
+    } else {
+        match rez_file.entry_by_path(entry_path) {
+            None => return request.respond(text_response(404, "not found")?),
+            Some(rez::Entry::Resource(res)) => return serve_resource(request, file, rez_file, res),
+            Some(rez::Entry::Directory(dir)) => &dir.entries,
+        }
+    };
+    if !url_path.ends_with('/') {
+        // the decoded path may contain characters that are not allowed in a header
+        let mut location = String::from("/");
+        for component in entry_path.split(['/', '\\']).filter(|c| !c.is_empty()) {
+            location.push_str(&percent_encode(component));
+            location.push('/');
+        }
+        let response = Response::empty(301)
+            .with_header(header("Location", &location)?);
+        return request.respond(response);
+    }
+    let index = directory_index(&format!("/{}", entry_path), entries);
+    let response = Response::from_string(index)
+        .with_header(header("Content-Type", "text/html; charset=utf-8")?);
+    request.respond(response)
+}
+
+fn serve_resource(request: Request, file: &File, rez_file: &rez::File, res: &rez::Resource) -> io::Result<()> {
+    // do not promise more data than the REZ file contains
+    let available = rez_file.file_size.saturating_sub(res.header.position.into());
+    let size = u64::from(res.header.size).min(available);
+
+    let range = request.headers().iter()
+        .find(|h| h.field.equiv("Range"))
+        .and_then(|h| parse_range(h.value.as_str(), size));
+    let (status, start, length) = match range {
+        None => (200, 0, size),
+        Some(Ok((start, end))) => (206, start, end - start + 1),
+        Some(Err(())) => {
+            let response = text_response(416, "range not satisfiable")?
+                .with_header(header("Content-Range", &format!("bytes */{}", size))?);
+            return request.respond(response);
+        },
+    };
+
+    let mut reader = file.try_clone()?;
+    reader.seek(SeekFrom::Start(rez_file.data_offset(res) + start))?;
+    let mut headers = vec![
+        header("Content-Type", "application/octet-stream")?,
+        header("Accept-Ranges", "bytes")?,
+    ];
+    if status == 206 {
+        headers.push(header("Content-Range", &format!("bytes {}-{}/{}", start, start + length - 1, size))?);
+    }
+    let response = Response::new(
+        StatusCode(status),
+        headers,
+        reader.take(length),
+        Some(length.try_into().unwrap()),
+        None,
+    );
+    request.respond(response)
+}
+
+/// Parses the value of a `Range` header for a resource of the given size, returning the first and
+/// last byte of the range or an error if the range cannot be satisfied.
+///
+/// Returns `None` if the header is to be ignored, i.e. if it is malformed, uses a unit other than
+/// bytes or requests multiple ranges; the whole resource is then served.
+fn parse_range(value: &str, size: u64) -> Option<Result<(u64, u64), ()>> {
+    let spec = value.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+    let (first, last) = spec.split_once('-')?;
+    let (first, last) = (first.trim(), last.trim());
+    if first.is_empty() {
+        // the last N bytes
+        let suffix_length: u64 = last.parse().ok()?;
+        if suffix_length == 0 || size == 0 {
+            return Some(Err(()));
+        }
+        return Some(Ok((size.saturating_sub(suffix_length), size - 1)));
+    }
+    let start: u64 = first.parse().ok()?;
+    let end: u64 = if last.is_empty() { u64::MAX } else { last.parse().ok()? };
+    if end < start {
+        return None;
+    }
+    if start >= size {
+        return Some(Err(()));
+    }
+    Some(Ok((start, end.min(size - 1))))
+}
+
+/// Generates the HTML index of a directory at the given path.
+fn directory_index(dir_path: &str, entries: &[rez::Entry]) -> String {
+    let title = if dir_path == "/" { "/".to_owned() } else { format!("{}/", dir_path) };
+    let mut html = String::new();
+    writeln!(html, "<!DOCTYPE html>").unwrap();
+    writeln!(html, "<html><head><meta charset=\"utf-8\"><title>Index of {}</title></head>", html_escape(&title)).unwrap();
+    writeln!(html, "<body><h1>Index of {}</h1><ul>", html_escape(&title)).unwrap();
+    if dir_path != "/" {
+        writeln!(html, "<li><a href=\"../\">../</a></li>").unwrap();
+    }
+    for entry in entries {
+        let name = entry.name();
+        match entry {
+            rez::Entry::Directory(_) => writeln!(
+                html, "<li><a href=\"{}/\">{}/</a></li>",
+                percent_encode(&name), html_escape(&name),
+            ).unwrap(),
+            rez::Entry::Resource(res) => writeln!(
+                html, "<li><a href=\"{}\">{}</a> ({} bytes)</li>",
+                percent_encode(&name), html_escape(&name), res.header.size,
+            ).unwrap(),
+        }
+    }
+    writeln!(html, "</ul></body></html>").unwrap();
+    html
+}
+
+fn text_response(status: u16, text: &str) -> io::Result<Response<io::Cursor<Vec<u8>>>> {
+    Ok(Response::from_string(text)
+        .with_status_code(status)
+        .with_header(header("Content-Type", "text/plain; charset=utf-8")?))
+}
+
+/// Creates a header, failing if the value contains characters that are not allowed in headers.
+fn header(field: &str, value: &str) -> io::Result<Header> {
+    Header::from_bytes(field, value)
+        .map_err(|()| io::Error::new(io::ErrorKind::InvalidData, format!("invalid value for header {}: {:?}", field, value)))
+}
+
+/// Decodes percent-encoded bytes in a URL path, returning `None` if the encoding is invalid or the
+/// result is not valid UTF-8.
+fn percent_decode(encoded: &str) -> Option<String> {
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i+1..i+3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// Percent-encodes a path component for use in a URL.
+fn percent_encode(component: &str) -> String {
+    let mut encoded = String::with_capacity(component.len());
+    for b in component.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+            encoded.push(char::from(b));
+        } else {
+            write!(encoded, "%{:02X}", b).unwrap();
+        }
+    }
+    encoded
+}
+
+fn html_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,68 @@
+//! Conversion of REZ files into tar streams.
+//!
+//! The stream uses the GNU tar format, which allows arbitrarily long paths. Files are written with
+//! mode 0644 and directories with mode 0755, both owned by user and group 0.
+
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use log::warn;
+use tar::{Builder, EntryType, Header};
+
+use crate::filter::ResourceFilter;
+use crate::rez;
+
+
+/// Writes the resources of the REZ file selected by the filter as a tar stream, preserving their
+/// paths and modification times. The data is copied straight from `reader` into the stream.
+///
+/// Directories are only added as separate entries if the filter is unrestricted, which preserves
+/// empty directories. Resources whose data extends past the end of the REZ file are stored with
+/// only the available data if `truncate_short` is true and left out otherwise.
+pub fn write_tar<R: Read + Seek, W: Write>(reader: &mut R, rez_file: &rez::File, filter: &ResourceFilter, writer: W, truncate_short: bool) -> io::Result<W> {
+    let mut builder = Builder::new(writer);
+    for (entry_path, entry) in rez_file.walk() {
+        match entry {
+            rez::Entry::Directory(dir) => {
+                if filter.is_unrestricted() {
+                    let mut header = new_header(EntryType::Directory, 0o755, 0, dir.header.time);
+                    builder.append_data(&mut header, format!("{}/", entry_path), io::empty())?;
+                }
+            },
+            rez::Entry::Resource(res) => {
+                if !filter.matches(&entry_path, res) {
+                    continue;
+                }
+                let size = u64::from(res.header.size);
+                let available = rez_file.file_size.saturating_sub(res.header.position.into());
+                if available < size {
+                    if !truncate_short {
+                        warn!("skipping {}: the REZ file ends {} bytes before the end of its data", entry_path, size - available);
+                        continue;
+                    }
+                    warn!(
+                        "truncating {} to {} bytes: the REZ file ends {} bytes before the end of its data",
+                        entry_path, available, size - available,
+                    );
+                }
+                let size = available.min(size);
+                reader.seek(SeekFrom::Start(rez_file.data_offset(res)))?;
+                let data = (&mut *reader).take(size);
+                let mut header = new_header(EntryType::Regular, 0o644, size, res.header.time);
+                builder.append_data(&mut header, &entry_path, data)?;
+            },
+        }
+    }
+    builder.into_inner()
+}
+
+fn new_header(entry_type: EntryType, mode: u32, size: u64, time: u32) -> Header {
+    let mut header = Header::new_gnu();
+    header.set_entry_type(entry_type);
+    header.set_mode(mode);
+    header.set_size(size);
+    header.set_mtime(time.into());
+    header.set_uid(0);
+    header.set_gid(0);
+    header
+}
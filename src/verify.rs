@@ -0,0 +1,326 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use serde_json::json;
+
+use crate::rez;
+
+
+/// How serious a problem is.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Severity {
+    /// The REZ file is unusual but can be used as it is.
+    Warning,
+    /// The REZ file is broken.
+    Error,
+}
+impl Severity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Warning => "warning",
+            Self::Error => "error",
+        }
+    }
+}
+
+/// The kind of a problem, as output in machine-readable reports.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum ProblemKind {
+    /// A directory is marked as sorted but its entries are not.
+    SortViolation,
+    /// Multiple resources have the same ID.
+    DuplicateId,
+    /// A directory contains an entry of an unknown type, which prevents reading it.
+    UnknownType,
+    /// The data of a resource or a directory block extends past the end of the REZ file.
+    OutOfBounds,
+    /// The data of a resource overlaps the data of another resource.
+    Overlap,
+    /// The size of a directory block does not match the entries within it.
+    DirectorySize,
+    /// The REZ file continues past the end of all its data and directory blocks.
+    TrailingBytes,
+}
+impl ProblemKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::SortViolation => "SortViolation",
+            Self::DuplicateId => "DuplicateId",
+            Self::UnknownType => "UnknownType",
+            Self::OutOfBounds => "OutOfBounds",
+            Self::Overlap => "Overlap",
+            Self::DirectorySize => "DirectorySize",
+            Self::TrailingBytes => "TrailingBytes",
+        }
+    }
+}
+
+/// A problem found while verifying a REZ file.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Problem {
+    pub severity: Severity,
+    pub kind: ProblemKind,
+    /// The path of the entry within the REZ file to which the problem pertains.
+    pub path: String,
+    pub description: String,
+}
+
+
+/// Verifies the structure of an already-parsed REZ file with the given header, returning the
+/// problems found.
+pub fn verify_file(rez_file: &rez::File, header: &rez::FileHeader) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    if rez_file.is_sorted {
+        check_sort_order_recursive(&rez_file.root_entries, "", &mut problems);
+    }
+    check_duplicate_ids(rez_file, &mut problems);
+    check_directory_sizes_recursive(&rez_file.root_entries, "", header.root_dir_size, &mut problems);
+
+    let mut extents: Vec<Extent> = rez_file.walk()
+        .filter_map(|(path, entry)| {
+            let res = entry.as_resource()?;
+            Some(Extent {
+                path,
+                position: res.header.position.into(),
+                size: res.header.size.into(),
+            })
+        })
+        .collect();
+    check_bounds(&extents, rez_file.file_size, &mut problems);
+    check_overlaps(&mut extents, &mut problems);
+    check_trailing_bytes(rez_file, header, &extents, &mut problems);
+    problems
+}
+
+/// Turns an error encountered while reading a REZ file into a problem, if it is one that
+/// verification reports instead of failing.
+pub fn problem_from_read_error(error: &rez::Error) -> Option<Problem> {
+    match error {
+        rez::Error::UnknownEntryType { type_code } => Some(Problem {
+            severity: Severity::Error,
+            kind: ProblemKind::UnknownType,
+            path: "/".to_owned(),
+            description: format!("a directory contains an entry of unknown type {}", type_code),
+        }),
+        rez::Error::TruncatedDirectory { position, expected, .. } => Some(Problem {
+            severity: Severity::Error,
+            kind: ProblemKind::OutOfBounds,
+            path: "/".to_owned(),
+            description: format!("directory block at {}+{} extends past the end of the REZ file", position, expected),
+        }),
+        rez::Error::Io(e) if e.kind() == io::ErrorKind::UnexpectedEof => Some(Problem {
+            severity: Severity::Error,
+            kind: ProblemKind::DirectorySize,
+            path: "/".to_owned(),
+            description: "a directory block ends in the middle of an entry".to_owned(),
+        }),
+        _ => None,
+    }
+}
+
+/// Turns the findings of [`rez::File::validate_offsets_only`] into problems.
+///
+/// Since names are not read by that check, entries are identified by the position of their
+/// directory block and their index within it.
+pub fn problems_from_out_of_bounds(findings: &[rez::OutOfBounds]) -> Vec<Problem> {
+    findings.iter()
+        .map(|finding| {
+            let what = if finding.header.entry_type == rez::EntryType::Directory { "directory block" } else { "data" };
+            let path = match finding.directory_position {
+                Some(directory_position) => format!("entry {} of the directory block at {}", finding.index, directory_position),
+                None => "/".to_owned(),
+            };
+            Problem {
+                severity: Severity::Error,
+                kind: ProblemKind::OutOfBounds,
+                path,
+                description: format!(
+                    "{} at {}+{} extends past the end of the REZ file",
+                    what, finding.header.position, finding.header.size,
+                ),
+            }
+        })
+        .collect()
+}
+
+/// Writes the problems as a JSON array.
+pub fn write_json<W: Write>(writer: &mut W, problems: &[Problem]) -> io::Result<()> {
+    let records: Vec<serde_json::Value> = problems.iter()
+        .map(|p| json!({
+            "severity": p.severity.as_str(),
+            "kind": p.kind.as_str(),
+            "path": p.path,
+            "detail": p.description,
+        }))
+        .collect();
+    serde_json::to_writer_pretty(&mut *writer, &records)?;
+    writeln!(writer)?;
+    writer.flush()
+}
+
+fn check_sort_order_recursive(entries: &[rez::Entry], dir_path: &str, problems: &mut Vec<Problem>) {
+    // report only the first violation per directory
+    for window in entries.windows(2) {
+        let previous_name = window[0].name();
+        let current_name = window[1].name();
+        if rez::compare_entry_names(&previous_name, &current_name) == Ordering::Greater {
+            problems.push(Problem {
+                severity: Severity::Error,
+                kind: ProblemKind::SortViolation,
+                path: if dir_path.is_empty() { "/".to_owned() } else { dir_path.to_owned() },
+                description: format!(
+                    "directory is marked as sorted but {:?} comes before {:?}",
+                    previous_name, current_name,
+                ),
+            });
+            break;
+        }
+    }
+
+    for entry in entries {
+        if let rez::Entry::Directory(dir) = entry {
+            let sub_path = rez::join_path(dir_path, &dir.name);
+            check_sort_order_recursive(&dir.entries, &sub_path, problems);
+        }
+    }
+}
+
+fn check_duplicate_ids(rez_file: &rez::File, problems: &mut Vec<Problem>) {
+    let mut id_to_path: HashMap<u32, String> = HashMap::new();
+    for (entry_path, entry) in rez_file.walk() {
+        let Some(res) = entry.as_resource() else { continue };
+        // ID 0 is left unassigned by many tools (including ours)
+        if res.id == 0 {
+            continue;
+        }
+        if let Some(first_path) = id_to_path.get(&res.id) {
+            problems.push(Problem {
+                severity: Severity::Warning,
+                kind: ProblemKind::DuplicateId,
+                path: entry_path,
+                description: format!("ID {} is also used by {}", res.id, first_path),
+            });
+        } else {
+            id_to_path.insert(res.id, entry_path);
+        }
+    }
+}
+
+/// Checks that the size of each directory block is at least as large as its entries, reporting
+/// any bytes beyond them as a warning.
+fn check_directory_sizes_recursive(entries: &[rez::Entry], dir_path: &str, block_size: u32, problems: &mut Vec<Problem>) {
+    let entries_length: u64 = entries.iter().map(encoded_entry_length).sum();
+    let block_size = u64::from(block_size);
+    if block_size > entries_length {
+        problems.push(Problem {
+            severity: Severity::Warning,
+            kind: ProblemKind::DirectorySize,
+            path: if dir_path.is_empty() { "/".to_owned() } else { dir_path.to_owned() },
+            description: format!(
+                "directory block is {} bytes long but its entries only take up {} bytes",
+                block_size, entries_length,
+            ),
+        });
+    }
+
+    for entry in entries {
+        if let rez::Entry::Directory(dir) = entry {
+            let sub_path = rez::join_path(dir_path, &dir.name);
+            check_directory_sizes_recursive(&dir.entries, &sub_path, dir.header.size, problems);
+        }
+    }
+}
+
+/// Returns the number of bytes the entry takes up in its directory block.
+fn encoded_entry_length(entry: &rez::Entry) -> u64 {
+    // the strings were decoded from ISO-8859-1, so each character was one byte
+    let string_length = |s: &str| u64::try_from(s.chars().count()).unwrap() + 1;
+    match entry {
+        rez::Entry::Directory(dir) => 16 + string_length(&dir.name),
+        rez::Entry::Resource(res) => {
+            16 + 4 + 4 + 4
+                + string_length(&res.name)
+                + string_length(&res.description)
+                + 4 * u64::try_from(res.keys.len()).unwrap()
+        },
+    }
+}
+
+/// The location of the data of a resource.
+struct Extent {
+    path: String,
+    position: u64,
+    size: u64,
+}
+
+
+fn check_bounds(extents: &[Extent], file_size: u64, problems: &mut Vec<Problem>) {
+    for extent in extents {
+        if extent.position + extent.size > file_size {
+            problems.push(Problem {
+                severity: Severity::Error,
+                kind: ProblemKind::OutOfBounds,
+                path: extent.path.clone(),
+                description: format!("data at {}+{} extends past the end of the REZ file", extent.position, extent.size),
+            });
+        }
+    }
+}
+
+/// Reports resources whose data overlaps that of another resource. Resources sharing exactly the
+/// same data are only reported as a warning.
+fn check_overlaps(extents: &mut [Extent], problems: &mut Vec<Problem>) {
+    extents.sort_by_key(|e| (e.position, e.size));
+
+    // the extent reaching furthest among those processed so far
+    let mut furthest: Option<&Extent> = None;
+    for extent in extents.iter().filter(|e| e.size > 0) {
+        if let Some(previous) = furthest {
+            if extent.position == previous.position && extent.size == previous.size {
+                problems.push(Problem {
+                    severity: Severity::Warning,
+                    kind: ProblemKind::Overlap,
+                    path: extent.path.clone(),
+                    description: format!("data at {}+{} is shared with {}", extent.position, extent.size, previous.path),
+                });
+            } else if extent.position < previous.position + previous.size {
+                problems.push(Problem {
+                    severity: Severity::Error,
+                    kind: ProblemKind::Overlap,
+                    path: extent.path.clone(),
+                    description: format!(
+                        "data at {}+{} overlaps the data of {} at {}+{}",
+                        extent.position, extent.size, previous.path, previous.position, previous.size,
+                    ),
+                });
+            }
+            if extent.position + extent.size <= previous.position + previous.size {
+                continue;
+            }
+        }
+        furthest = Some(extent);
+    }
+}
+
+/// Reports bytes after the end of the header, the data and the directory blocks as a warning, e.g.
+/// padding added by the tool that wrote the REZ file.
+fn check_trailing_bytes(rez_file: &rez::File, header: &rez::FileHeader, extents: &[Extent], problems: &mut Vec<Problem>) {
+    let directory_ends = rez_file.walk()
+        .filter_map(|(_path, entry)| entry.as_directory())
+        .map(|dir| u64::from(dir.header.position) + u64::from(dir.header.size));
+    let end = extents.iter()
+        .map(|e| e.position + e.size)
+        .chain(directory_ends)
+        .chain([header.length(), u64::from(header.root_dir_position) + u64::from(header.root_dir_size)])
+        .max()
+        .unwrap();
+    if rez_file.file_size > end {
+        problems.push(Problem {
+            severity: Severity::Warning,
+            kind: ProblemKind::TrailingBytes,
+            path: "/".to_owned(),
+            description: format!("{} bytes follow the end of the REZ data at {}", rez_file.file_size - end, end),
+        });
+    }
+}
@@ -0,0 +1,195 @@
+//! Conversion between REZ files and ZIP archives.
+//!
+//! ZIP archives store modification times as local date and time without a time zone; they are
+//! treated as UTC in both directions. Times before 1980, which ZIP archives cannot represent,
+//! become 1980-01-01 00:00:00.
+
+
+use std::collections::HashMap;
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+
+use log::{info, warn};
+use zip::{CompressionMethod, DateTime, ZipArchive, ZipWriter};
+use zip::result::ZipResult;
+use zip::write::SimpleFileOptions;
+
+use crate::calendar::{civil_from_days, days_from_civil};
+use crate::filter::ResourceFilter;
+use crate::pack::new_resource;
+use crate::rez;
+
+
+/// Writes the resources of the REZ file selected by the filter into a ZIP archive, preserving their
+/// paths and modification times. The data is copied straight from `reader` into the archive.
+///
+/// Directories are only added as separate entries if the filter is unrestricted, which preserves
+/// empty directories. Resources whose data extends past the end of the REZ file are stored with
+/// only the available data if `truncate_short` is true and left out otherwise.
+pub fn write_zip<R, W>(reader: &mut R, rez_file: &rez::File, filter: &ResourceFilter, writer: W, compress: bool, truncate_short: bool) -> ZipResult<W>
+    where
+        R: Read + Seek,
+        W: Write + Seek,
+{
+    let mut zip = ZipWriter::new(writer);
+    let method = if compress { CompressionMethod::Deflated } else { CompressionMethod::Stored };
+    for (entry_path, entry) in rez_file.walk() {
+        match entry {
+            rez::Entry::Directory(dir) => {
+                if filter.is_unrestricted() {
+                    let options = SimpleFileOptions::default()
+                        .last_modified_time(zip_time(dir.header.time));
+                    zip.add_directory(entry_path.as_str(), options)?;
+                }
+            },
+            rez::Entry::Resource(res) => {
+                if !filter.matches(&entry_path, res) {
+                    continue;
+                }
+                let size = u64::from(res.header.size);
+                let available = rez_file.file_size.saturating_sub(res.header.position.into());
+                if available < size {
+                    if !truncate_short {
+                        warn!("skipping {}: the REZ file ends {} bytes before the end of its data", entry_path, size - available);
+                        continue;
+                    }
+                    warn!(
+                        "truncating {} to {} bytes: the REZ file ends {} bytes before the end of its data",
+                        entry_path, available, size - available,
+                    );
+                }
+                info!("adding {}", entry_path);
+                let options = SimpleFileOptions::default()
+                    .compression_method(method)
+                    .last_modified_time(zip_time(res.header.time));
+                zip.start_file(entry_path.as_str(), options)?;
+                reader.seek(SeekFrom::Start(rez_file.data_offset(res)))?;
+                io::copy(&mut (&mut *reader).take(available.min(size)), &mut zip)?;
+            },
+        }
+    }
+    zip.finish()
+}
+
+/// Builds the REZ entry tree corresponding to the contents of a ZIP archive.
+///
+/// Files become resources, with the part of the file name after the last dot taken as the
+/// extension, and directories become REZ directories. Directories that are only implied by the
+/// paths of the files take the modification time of the first file within them. All resources have
+/// the ID 0; use [`assign_ids`](crate::pack::assign_ids) to assign actual IDs. As in REZ files,
+/// names differing only in case refer to the same entry.
+///
+/// Also returns a map from the path of each resource within the REZ file to the index of the
+/// corresponding file within the ZIP archive.
+pub fn scan_zip<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<(Vec<rez::Entry>, HashMap<String, usize>), String> {
+    let mut entries = Vec::new();
+    let mut indexes = HashMap::new();
+    for index in 0..archive.len() {
+        let file = archive.by_index_raw(index)
+            .map_err(|e| format!("failed to read entry {} of ZIP file: {}", index, e))?;
+        let Some(path) = file.enclosed_name() else {
+            return Err(format!("ZIP file contains the unsafe path {:?}", file.name()));
+        };
+        let components: Vec<String> = path.components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        let time = file.last_modified()
+            .map(unix_time)
+            .unwrap_or(0);
+        let Some((last, parents)) = components.split_last() else { continue };
+
+        // the directories may already exist with names differing in case
+        let mut dir_path = String::new();
+        let dir_entries = directory_entries_mut(&mut entries, parents, time, file.name(), &mut dir_path)?;
+        let existing = dir_entries.iter_mut().find(|e| rez::compare_entry_names(&e.name(), last).is_eq());
+        if file.is_dir() {
+            match existing {
+                None => dir_entries.push(rez::Entry::Directory(rez::Directory {
+                    header: directory_header(time),
+                    name: last.clone(),
+                    entries: Vec::new(),
+                    entries_loaded: true,
+                })),
+                // created implicitly by a file within it
+                Some(rez::Entry::Directory(dir)) => dir.header.time = time,
+                Some(rez::Entry::Resource(_)) => return Err(format!("ZIP file contains {:?} both as a file and as a directory", file.name())),
+            }
+        } else {
+            if existing.is_some() {
+                return Err(format!("ZIP file contains {:?} more than once", file.name()));
+            }
+            let entry = rez::Entry::Resource(new_resource(&path, last, time)?);
+            indexes.insert(rez::join_path(&dir_path, &entry.name()), index);
+            dir_entries.push(entry);
+        }
+    }
+    Ok((entries, indexes))
+}
+
+/// Reads the decompressed data of the file at the given index within the ZIP archive.
+pub fn read_zip_entry<R: Read + Seek>(archive: &mut ZipArchive<R>, index: usize) -> io::Result<Cursor<Vec<u8>>> {
+    let mut file = archive.by_index(index)?;
+    let mut data = Vec::with_capacity(file.size().try_into().unwrap_or(0));
+    file.read_to_end(&mut data)?;
+    Ok(Cursor::new(data))
+}
+
+/// Returns the entries of the directory at the given path, creating the directory and its parents
+/// with the given time if they do not exist yet. Existing directories are found without regard to
+/// case; their actual path is appended to `dir_path`.
+fn directory_entries_mut<'a>(entries: &'a mut Vec<rez::Entry>, components: &[String], time: u32, zip_path: &str, dir_path: &mut String) -> Result<&'a mut Vec<rez::Entry>, String> {
+    let Some((first, rest)) = components.split_first() else { return Ok(entries) };
+    let index = match entries.iter().position(|e| rez::compare_entry_names(&e.name(), first).is_eq()) {
+        Some(index) => index,
+        None => {
+            entries.push(rez::Entry::Directory(rez::Directory {
+                header: directory_header(time),
+                name: first.clone(),
+                entries: Vec::new(),
+                entries_loaded: true,
+            }));
+            entries.len() - 1
+        },
+    };
+    match &mut entries[index] {
+        rez::Entry::Directory(dir) => {
+            *dir_path = rez::join_path(dir_path, &dir.name);
+            directory_entries_mut(&mut dir.entries, rest, time, zip_path, dir_path)
+        },
+        rez::Entry::Resource(_) => Err(format!("ZIP file contains {:?} within a file", zip_path)),
+    }
+}
+
+fn directory_header(time: u32) -> rez::EntryHeader {
+    rez::EntryHeader {
+        entry_type: rez::EntryType::Directory,
+        position: 0,
+        size: 0,
+        time,
+    }
+}
+
+/// Converts a ZIP timestamp, taken to be in UTC, into a Unix timestamp.
+fn unix_time(time: DateTime) -> u32 {
+    let days = days_from_civil(time.year().into(), time.month().into(), time.day().into());
+    let seconds = days * 86400
+        + i64::from(time.hour()) * 3600
+        + i64::from(time.minute()) * 60
+        + i64::from(time.second());
+    seconds.try_into().unwrap_or(0)
+}
+
+/// Converts a Unix timestamp into a ZIP timestamp in UTC.
+fn zip_time(unix_time: u32) -> DateTime {
+    let days = i64::from(unix_time / 86400);
+    let seconds_of_day = unix_time % 86400;
+    let (year, month, day) = civil_from_days(days);
+    let Ok(year) = u16::try_from(year) else { return DateTime::default() };
+    DateTime::from_date_and_time(
+        year,
+        month,
+        day,
+        (seconds_of_day / 3600).try_into().unwrap(),
+        (seconds_of_day / 60 % 60).try_into().unwrap(),
+        (seconds_of_day % 60).try_into().unwrap(),
+    ).unwrap_or_default()
+}
@@ -0,0 +1,352 @@
+//! Helpers for building REZ files and running the `lithrez` binary in tests.
+
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+
+pub const FILE_TYPE: &str = "RezMgr Version 1 Copyright (C) 1995 MONOLITH INC.";
+pub const USER_TITLE: &str = "test archive";
+
+const HEAD_TAIL_XOR: u8 = 0x11;
+const ENCODE_VALUE_XOR: u32 = 0x016B4423;
+
+
+/// An entry of a REZ file to build.
+#[derive(Clone, Debug)]
+pub enum Node {
+    Directory { name: &'static str, time: u32, children: Vec<Node> },
+    Resource { name: &'static str, extension: &'static str, id: u32, description: &'static str, time: u32, data: Vec<u8> },
+    /// An entry with an unsupported type code.
+    Unknown { type_code: u32 },
+}
+
+pub fn dir(name: &'static str, time: u32, children: Vec<Node>) -> Node {
+    Node::Directory { name, time, children }
+}
+
+pub fn res(name: &'static str, extension: &'static str, id: u32, description: &'static str, time: u32, data: &[u8]) -> Node {
+    Node::Resource { name, extension, id, description, time, data: data.to_vec() }
+}
+
+/// A small tree exercising nested directories, descriptions and an empty directory.
+pub fn sample_tree() -> Vec<Node> {
+    vec![
+        res("README", "txt", 1, "the readme", 1600000000, b"hello world\n"),
+        dir("sounds", 1600000300, vec![
+            res("boom", "wav", 2, "", 1600000100, b"RIFF....WAVE"),
+            res("Alpha", "wav", 3, "orig: c:\\alpha.wav", 1600000200, &[0xAA; 300]),
+            dir("empty", 1600000000, vec![]),
+        ]),
+        dir("tex", 1650000000, vec![
+            dir("ui", 1650000000, vec![
+                res("button", "dtx", 4, "", 1650000000, &[0x00; 64]),
+            ]),
+        ]),
+    ]
+}
+
+
+/// Where the directory blocks are placed relative to the resource data.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Layout {
+    DirectoryAtEnd,
+    DirectoryAtFront,
+}
+
+
+fn padded(value: &str, length: usize) -> Vec<u8> {
+    let mut ret = value.as_bytes().to_vec();
+    ret.resize(length, b' ');
+    ret
+}
+
+/// Builds a LithTech 2 DTX texture with the given pixel format (the third extra byte of the header)
+/// and data of the first mipmap level.
+pub fn dtx_texture(width: u16, height: u16, pixel_format: u8, pixel_data: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&0u32.to_le_bytes()); // resource type
+    bytes.extend_from_slice(&(-5i32).to_le_bytes());
+    bytes.extend_from_slice(&width.to_le_bytes());
+    bytes.extend_from_slice(&height.to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes()); // mipmap count
+    bytes.extend_from_slice(&0u16.to_le_bytes()); // section count
+    bytes.extend_from_slice(&0u32.to_le_bytes()); // flags
+    bytes.extend_from_slice(&0u32.to_le_bytes()); // user flags
+    let mut extra = [0u8; 12];
+    extra[2] = pixel_format;
+    bytes.extend_from_slice(&extra);
+    bytes.resize(164, 0); // command string
+    bytes.extend_from_slice(pixel_data);
+    bytes
+}
+
+/// The start of a version 1 header, up to and including the version number.
+pub fn v1_header_prefix() -> Vec<u8> {
+    let mut ret = Vec::new();
+    ret.extend_from_slice(b"\r\n");
+    ret.extend(padded(FILE_TYPE, 60));
+    ret.extend_from_slice(b"\r\n");
+    ret.extend(padded(USER_TITLE, 60));
+    ret.extend_from_slice(b"\r\n\x1A");
+    ret.extend_from_slice(&1u32.to_le_bytes());
+    ret
+}
+
+/// The start of a version 2 header with the given number of bytes between the end-of-file control
+/// byte and the version number.
+pub fn v2_header_prefix(padding: usize) -> Vec<u8> {
+    let mut ret = v1_header_prefix();
+    ret.truncate(ret.len() - 4);
+    ret.extend(std::iter::repeat_n(0xEE, padding));
+    ret.extend_from_slice(&2u32.to_le_bytes());
+    ret
+}
+
+/// The start of a header using the obfuscated `*` variant, with the given values.
+pub fn obfuscated_header_prefix(head: u8, encode_value: &str, detect_head: u8, detect_encode_value: &str, tail: u8, detect_tail: u8, version: u32) -> Vec<u8> {
+    let mut ret = Vec::new();
+    ret.extend_from_slice(b"&#");
+    ret.extend(padded(FILE_TYPE, 60));
+    ret.extend_from_slice(b"!\"");
+    ret.extend(padded(USER_TITLE, 60));
+    ret.extend_from_slice(b"%'*");
+    ret.push(head);
+    let mut encode_buf = encode_value.as_bytes().to_vec();
+    encode_buf.resize(32, 0x00);
+    ret.extend(encode_buf);
+    ret.push(tail);
+    ret.push(detect_head);
+    let mut detect_encode_buf = detect_encode_value.as_bytes().to_vec();
+    detect_encode_buf.resize(32, 0x00);
+    ret.extend(detect_encode_buf);
+    ret.push(detect_tail);
+    ret.extend_from_slice(&version.to_le_bytes());
+    ret
+}
+
+/// The start of a valid header using the obfuscated `*` variant.
+pub fn valid_obfuscated_header_prefix() -> Vec<u8> {
+    let encode_value: u32 = 12345678;
+    obfuscated_header_prefix(
+        0x42, &encode_value.to_string(), 0x42 ^ HEAD_TAIL_XOR,
+        &(encode_value ^ ENCODE_VALUE_XOR).to_string(), 0x24, 0x24 ^ HEAD_TAIL_XOR, 1,
+    )
+}
+
+
+/// Builds a REZ file from the given header start (everything up to and including the version
+/// number) and entry tree.
+pub fn build_rez(header_prefix: &[u8], nodes: &[Node], layout: Layout) -> Vec<u8> {
+    build_rez_with(header_prefix, nodes, layout, 0, false)
+}
+
+/// Builds a REZ file, appending `padding` zero bytes to every directory block.
+pub fn build_rez_with(header_prefix: &[u8], nodes: &[Node], layout: Layout, padding: usize, is_sorted: bool) -> Vec<u8> {
+    let header_length = u32::try_from(header_prefix.len()).unwrap() + 9*4 + 1;
+
+    // measure the directory blocks first; their length does not depend on the positions
+    let (_, measure_dirs, _, _) = encode_tree(nodes, 0, 0, padding);
+    let dirs_length = u32::try_from(measure_dirs.len()).unwrap();
+
+    let (data_start, dir_start) = match layout {
+        Layout::DirectoryAtEnd => {
+            let (measure_data, _, _, _) = encode_tree(nodes, 0, 0, padding);
+            (header_length, header_length + u32::try_from(measure_data.len()).unwrap())
+        },
+        Layout::DirectoryAtFront => (header_length + dirs_length, header_length),
+    };
+    let (data, dirs, root_position, root_size) = encode_tree(nodes, data_start, dir_start, padding);
+
+    let mut ret = header_prefix.to_vec();
+    let next_write_pos = header_length + u32::try_from(data.len() + dirs.len()).unwrap();
+    for value in [root_position, root_size, 1700000000, next_write_pos, 1700000000, 0, 16, 16, 32] {
+        ret.extend_from_slice(&value.to_le_bytes());
+    }
+    ret.push(if is_sorted { 1 } else { 0 });
+    match layout {
+        Layout::DirectoryAtEnd => {
+            ret.extend(data);
+            ret.extend(dirs);
+        },
+        Layout::DirectoryAtFront => {
+            ret.extend(dirs);
+            ret.extend(data);
+        },
+    }
+    ret
+}
+
+/// Encodes the tree into resource data and directory blocks starting at the given positions.
+/// Returns the data, the directory blocks, and the position and size of the root block.
+fn encode_tree(nodes: &[Node], data_start: u32, dir_start: u32, padding: usize) -> (Vec<u8>, Vec<u8>, u32, u32) {
+    let mut data = Vec::new();
+    let mut dirs = Vec::new();
+    let (root_position, root_size) = encode_directory(nodes, data_start, dir_start, padding, &mut data, &mut dirs);
+    (data, dirs, root_position, root_size)
+}
+
+fn encode_directory(nodes: &[Node], data_start: u32, dir_start: u32, padding: usize, data: &mut Vec<u8>, dirs: &mut Vec<u8>) -> (u32, u32) {
+    let mut block = Vec::new();
+    for node in nodes {
+        match node {
+            Node::Directory { name, time, children } => {
+                let (position, size) = encode_directory(children, data_start, dir_start, padding, data, dirs);
+                for value in [1, position, size, *time] {
+                    block.extend_from_slice(&value.to_le_bytes());
+                }
+                block.extend_from_slice(name.as_bytes());
+                block.push(0x00);
+            },
+            Node::Resource { name, extension, id, description, time, data: res_data } => {
+                let position = data_start + u32::try_from(data.len()).unwrap();
+                data.extend_from_slice(res_data);
+                for value in [0, position, u32::try_from(res_data.len()).unwrap(), *time, *id] {
+                    block.extend_from_slice(&value.to_le_bytes());
+                }
+                let mut extension_bytes: Vec<u8> = extension.bytes().rev().collect();
+                extension_bytes.resize(4, 0x00);
+                block.extend(extension_bytes);
+                block.extend_from_slice(&0u32.to_le_bytes()); // no keys
+                block.extend_from_slice(name.as_bytes());
+                block.push(0x00);
+                block.extend_from_slice(description.as_bytes());
+                block.push(0x00);
+            },
+            Node::Unknown { type_code } => {
+                for value in [*type_code, 0, 0, 0] {
+                    block.extend_from_slice(&value.to_le_bytes());
+                }
+            },
+        }
+    }
+    block.extend(std::iter::repeat_n(0x00, padding));
+
+    let position = dir_start + u32::try_from(dirs.len()).unwrap();
+    let size = u32::try_from(block.len()).unwrap();
+    dirs.extend(block);
+    (position, size)
+}
+
+
+/// Returns a fresh path for a test to use.
+pub fn temp_path(name: &str) -> PathBuf {
+    let mut path = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    path.push(format!("{}-{}", std::process::id(), name));
+    if path.is_dir() {
+        std::fs::remove_dir_all(&path).unwrap();
+    } else if path.exists() {
+        std::fs::remove_file(&path).unwrap();
+    }
+    path
+}
+
+/// Writes the REZ file data into a fresh temporary file and returns its path.
+pub fn write_rez(name: &str, bytes: &[u8]) -> PathBuf {
+    let path = temp_path(name);
+    std::fs::write(&path, bytes).unwrap();
+    path
+}
+
+/// Writes the REZ file data at the given offset into a fresh temporary file and returns its path.
+///
+/// The bytes before the REZ file are left as a hole, so even offsets beyond 4 GiB take up hardly
+/// any disk space on file systems that support sparse files.
+pub fn write_embedded_rez(name: &str, base_offset: u64, bytes: &[u8]) -> PathBuf {
+    use std::io::{Seek, SeekFrom, Write};
+
+    let path = temp_path(name);
+    let mut file = std::fs::File::create(&path).unwrap();
+    file.seek(SeekFrom::Start(base_offset)).unwrap();
+    file.write_all(bytes).unwrap();
+    path
+}
+
+/// Runs the `lithrez` binary with the given arguments.
+pub fn lithrez<I, S>(args: I) -> Output
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<std::ffi::OsStr>,
+{
+    Command::new(env!("CARGO_BIN_EXE_lithrez"))
+        .args(args)
+        .env("RUST_LOG", "warn")
+        .output()
+        .expect("failed to run lithrez")
+}
+
+/// Runs the `lithrez` binary with the given arguments, passing the given input on its standard
+/// input.
+pub fn lithrez_with_stdin<I, S>(args: I, input: &[u8]) -> Output
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<std::ffi::OsStr>,
+{
+    use std::io::Write;
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lithrez"))
+        .args(args)
+        .env("RUST_LOG", "warn")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to run lithrez");
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    child.wait_with_output()
+        .expect("failed to run lithrez")
+}
+
+/// Runs `lithrez` with the given arguments, asserts that it succeeded and returns its standard
+/// output.
+pub fn lithrez_ok<I, S>(args: I) -> String
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<std::ffi::OsStr>,
+{
+    let output = lithrez(args);
+    assert!(
+        output.status.success(),
+        "lithrez failed with {}; stderr:\n{}", output.status, String::from_utf8_lossy(&output.stderr),
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Runs `lithrez` with the given arguments, asserts that it failed and returns its standard error
+/// output.
+pub fn lithrez_err<I, S>(args: I) -> String
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<std::ffi::OsStr>,
+{
+    let output = lithrez(args);
+    assert!(!output.status.success(), "lithrez unexpectedly succeeded");
+    String::from_utf8(output.stderr).unwrap()
+}
+
+/// Writes the sample tree as a REZ file with the directory at the end into a fresh temporary file
+/// and returns its path.
+pub fn sample_rez(name: &str) -> PathBuf {
+    write_rez(name, &build_rez(&v1_header_prefix(), &sample_tree(), Layout::DirectoryAtEnd))
+}
+
+/// Runs `lithrez find` with the given arguments on the REZ file, with slashes as separators and LF
+/// line endings, and returns its output.
+pub fn find(rez_path: &Path, args: &[&str]) -> String {
+    styled_output("find", rez_path, args)
+}
+
+/// Runs `lithrez list` with the given arguments on the REZ file, with slashes as separators and LF
+/// line endings, and returns its output.
+pub fn list(rez_path: &Path, args: &[&str]) -> String {
+    styled_output("list", rez_path, args)
+}
+
+fn styled_output(command: &str, rez_path: &Path, args: &[&str]) -> String {
+    let mut all_args: Vec<&std::ffi::OsStr> = vec![command.as_ref()];
+    all_args.extend(args.iter().map(std::ffi::OsStr::new));
+    all_args.extend(["--path-sep", "slash", "--line-ending", "lf"].map(std::ffi::OsStr::new));
+    all_args.push(rez_path.as_os_str());
+    lithrez_ok(all_args)
+}
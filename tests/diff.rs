@@ -0,0 +1,46 @@
+//! Checks the `diff` command.
+
+mod common;
+
+use common::{build_rez, dir, lithrez, res, sample_rez, v1_header_prefix, write_rez, Layout};
+
+
+#[test]
+fn diff_by_size_and_content() {
+    let old_path = sample_rez("diff-old.rez");
+    let new_tree = vec![
+        res("README", "txt", 1, "the readme", 1600000000, b"HELLO WORLD\n"),
+        dir("sounds", 1600000300, vec![
+            res("Alpha", "wav", 3, "", 1600000200, &[0xAA; 200]),
+            res("bang", "wav", 5, "", 1600000400, b"bang"),
+        ]),
+        dir("tex", 1650000000, vec![
+            dir("ui", 1650000000, vec![
+                res("button", "dtx", 4, "", 1650000000, &[0u8; 64]),
+            ]),
+        ]),
+    ];
+    let new_bytes = build_rez(&v1_header_prefix(), &new_tree, Layout::DirectoryAtFront);
+    let new_path = write_rez("diff-new.rez", &new_bytes);
+
+    let output = lithrez(["diff".as_ref(), old_path.as_os_str(), new_path.as_os_str()]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "\
+M sounds/Alpha.wav (300 -> 200 bytes)
+A sounds/bang.wav (4 bytes)
+D sounds/boom.wav (12 bytes)
+");
+
+    let output = lithrez(["diff".as_ref(), "--content".as_ref(), old_path.as_os_str(), new_path.as_os_str()]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "\
+M README.txt (12 -> 12 bytes)
+M sounds/Alpha.wav (300 -> 200 bytes)
+A sounds/bang.wav (4 bytes)
+D sounds/boom.wav (12 bytes)
+");
+
+    let output = lithrez(["diff".as_ref(), "--content".as_ref(), old_path.as_os_str(), old_path.as_os_str()]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+}
@@ -0,0 +1,576 @@
+//! Checks the options of the `extract` command and the `cat` command.
+
+mod common;
+
+use common::{build_rez, dir, dtx_texture, lithrez_err, lithrez_ok, lithrez_with_stdin, res, sample_rez, sample_tree, temp_path, v1_header_prefix, write_embedded_rez, write_rez, Layout};
+
+
+#[test]
+fn collapse_single_dirs() {
+    let path = sample_rez("collapse.rez");
+    let output_dir = temp_path("collapse");
+    lithrez_ok(["extract".as_ref(), "--collapse-single-dirs".as_ref(), path.as_os_str(), output_dir.as_os_str()]);
+
+    assert!(output_dir.join("tex_ui").join("button.dtx").is_file());
+    assert!(!output_dir.join("tex").exists());
+    assert!(output_dir.join("sounds").join("boom.wav").is_file());
+}
+
+#[test]
+fn clean_requires_force() {
+    let path = sample_rez("clean.rez");
+    let output_dir = temp_path("clean");
+    std::fs::create_dir_all(output_dir.join("stale")).unwrap();
+    std::fs::write(output_dir.join("stale").join("old.txt"), b"old").unwrap();
+
+    // merging keeps stale files
+    lithrez_ok(["extract".as_ref(), path.as_os_str(), output_dir.as_os_str()]);
+    assert!(output_dir.join("stale").join("old.txt").is_file());
+
+    let output = common::lithrez(["extract".as_ref(), "--clean".as_ref(), path.as_os_str(), output_dir.as_os_str()]);
+    assert!(!output.status.success());
+    assert!(output_dir.join("stale").join("old.txt").is_file());
+
+    lithrez_ok(["extract".as_ref(), "--clean".as_ref(), "--force".as_ref(), path.as_os_str(), output_dir.as_os_str()]);
+    assert!(!output_dir.join("stale").exists());
+    assert!(output_dir.join("README.txt").is_file());
+}
+
+#[test]
+fn on_short_read() {
+    // with the directory at the front, the data of the last resource is at the end of the file
+    let mut bytes = build_rez(&v1_header_prefix(), &sample_tree(), Layout::DirectoryAtFront);
+    bytes.truncate(bytes.len() - 10);
+    let path = write_rez("short-read.rez", &bytes);
+    let button = |dir: &std::path::Path| dir.join("tex").join("ui").join("button.dtx");
+
+    let output_dir = temp_path("short-read-error");
+    let output = common::lithrez(["extract".as_ref(), path.as_os_str(), output_dir.as_os_str()]);
+    assert_eq!(output.status.code(), Some(4));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("cannot extract tex/ui/button.dtx stored at offset "));
+    assert!(!button(&output_dir).exists());
+
+    let output_dir = temp_path("short-read-skip");
+    lithrez_ok(["extract".as_ref(), "--on-short-read".as_ref(), "skip".as_ref(), path.as_os_str(), output_dir.as_os_str()]);
+    assert!(!button(&output_dir).exists());
+    assert!(output_dir.join("README.txt").is_file());
+
+    let output_dir = temp_path("short-read-truncate");
+    lithrez_ok(["extract".as_ref(), "--on-short-read".as_ref(), "truncate".as_ref(), path.as_os_str(), output_dir.as_os_str()]);
+    assert_eq!(std::fs::metadata(button(&output_dir)).unwrap().len(), 54);
+
+    let output = common::lithrez(["cat".as_ref(), path.as_os_str(), "tex/ui/button.dtx".as_ref()]);
+    assert_eq!(output.status.code(), Some(4));
+    assert_eq!(output.stdout.len(), 54);
+}
+
+#[test]
+fn dedup_links() {
+    let tree = vec![
+        common::res("one", "txt", 1, "", 0, b"same"),
+        common::dir("d", 0, vec![common::res("two", "txt", 2, "", 0, b"same")]),
+        common::res("three", "txt", 3, "", 0, b"different"),
+    ];
+    let bytes = build_rez(&v1_header_prefix(), &tree, Layout::DirectoryAtEnd);
+    let path = write_rez("dedup.rez", &bytes);
+    let output_dir = temp_path("dedup");
+    lithrez_ok(["extract".as_ref(), "--dedup-links".as_ref(), path.as_os_str(), output_dir.as_os_str()]);
+
+    assert_eq!(std::fs::read(output_dir.join("d").join("two.txt")).unwrap(), b"same");
+    assert_eq!(std::fs::read(output_dir.join("three.txt")).unwrap(), b"different");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        assert_eq!(std::fs::metadata(output_dir.join("one.txt")).unwrap().nlink(), 2);
+        assert_eq!(std::fs::metadata(output_dir.join("three.txt")).unwrap().nlink(), 1);
+    }
+}
+
+#[test]
+fn retries_are_reported() {
+    let path = sample_rez("retries.rez");
+    let output_dir = temp_path("retries");
+    // a directory in place of a file makes writing it fail every time
+    std::fs::create_dir_all(output_dir.join("README.txt")).unwrap();
+
+    let output = common::lithrez(["extract".as_ref(), "--retries".as_ref(), "2".as_ref(), "--retry-delay".as_ref(), "1".as_ref(), path.as_os_str(), output_dir.as_os_str()]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("attempt 2 of 2"));
+    assert!(stderr.contains("1 file(s) could not be written"));
+    assert!(output_dir.join("sounds").join("boom.wav").is_file());
+}
+
+#[test]
+fn ext_map() {
+    let path = sample_rez("ext-map.rez");
+    let output_dir = temp_path("ext-map");
+    lithrez_ok(["extract".as_ref(), "--ext-map".as_ref(), "DTX=dds".as_ref(), path.as_os_str(), output_dir.as_os_str()]);
+
+    assert!(output_dir.join("tex").join("ui").join("button.dds").is_file());
+    assert!(!output_dir.join("tex").join("ui").join("button.dtx").exists());
+    assert!(output_dir.join("sounds").join("boom.wav").is_file());
+
+    // the listing is unaffected
+    let listing = lithrez_ok(["list".as_ref(), "--type".as_ref(), "resource".as_ref(), path.as_os_str()]);
+    assert!(listing.contains("button.dtx"));
+}
+
+#[test]
+fn max_name_length() {
+    let tree = vec![
+        res("a_long_resource_name", "text", 1, "the description", 1600000000, b"text"),
+        res("data", "dat", 2, "", 1600000000, b"data"),
+    ];
+    let path = write_rez("max-name-length.rez", &build_rez(&v1_header_prefix(), &tree, Layout::DirectoryAtEnd));
+    let output_dir = temp_path("max-name-length");
+    lithrez_ok(["extract".as_ref(), "--max-name-length".as_ref(), "20".as_ref(), "--descriptions".as_ref(), "--ext-map".as_ref(), "dat=averyveryverylongextension".as_ref(), path.as_os_str(), output_dir.as_os_str()]);
+
+    // the description sidecar counts towards the limit, and overlong extensions are clamped too
+    let mut names: Vec<String> = std::fs::read_dir(&output_dir).unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    assert_eq!(names.len(), 3);
+    assert!(names.iter().all(|name| name.len() <= 20), "{:?}", names);
+    assert!(names[0].starts_with("a_~") && names[0].ends_with(".text"), "{:?}", names);
+    assert_eq!(names[1], format!("{}.txt", names[0]));
+    assert_eq!(std::fs::read(output_dir.join(&names[1])).unwrap(), b"the description");
+    assert!(names[2].starts_with('~') && names[2].ends_with(".averyv"), "{:?}", names);
+
+    // a limit without room for the hash is rejected
+    let stderr = lithrez_err(["extract".as_ref(), "--max-name-length".as_ref(), "13".as_ref(), "--descriptions".as_ref(), path.as_os_str(), output_dir.as_os_str()]);
+    assert!(stderr.contains("--max-name-length must be at least 14 with --descriptions"), "{}", stderr);
+}
+
+#[test]
+fn names_too_long_for_the_file_system() {
+    let long_name: &'static str = "x".repeat(300).leak();
+    let long_dir = vec![
+        dir(long_name, 1600000000, vec![
+            res("short", "txt", 1, "", 1600000000, b"short"),
+        ]),
+    ];
+    let long_file = vec![
+        res(long_name, "txt", 1, "", 1600000000, b"long"),
+    ];
+    for (name, tree) in [("long-dir-name", long_dir), ("long-file-name", long_file)] {
+        let path = write_rez(&format!("{}.rez", name), &build_rez(&v1_header_prefix(), &tree, Layout::DirectoryAtEnd));
+        let output = common::lithrez(["extract".as_ref(), "--transcode-text-ext".as_ref(), "txt".as_ref(), path.as_os_str(), temp_path(name).as_os_str()]);
+        assert_eq!(output.status.code(), Some(3), "{}", name);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("the name is too long for the file system; consider using --max-name-length"), "{}: {}", name, stderr);
+    }
+}
+
+#[test]
+fn embedded_past_4_gib() {
+    const BASE_OFFSET: u64 = 0x1_0000_1000;
+    let bytes = build_rez(&v1_header_prefix(), &sample_tree(), Layout::DirectoryAtEnd);
+    let path = write_embedded_rez("extract-embedded.bin", BASE_OFFSET, &bytes);
+    let output_dir = temp_path("extract-embedded");
+    lithrez_ok(["extract".as_ref(), "--base-offset".as_ref(), "0x100001000".as_ref(), path.as_os_str(), output_dir.as_os_str()]);
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(std::fs::read(output_dir.join("README.txt")).unwrap(), b"hello world\n");
+    assert_eq!(std::fs::read(output_dir.join("tex").join("ui").join("button.dtx")).unwrap(), vec![0u8; 64]);
+}
+
+#[test]
+fn transcode_text() {
+    let tree = vec![
+        res("notes", "TXT", 1, "", 0, b"caf\xE9 \x80\r\n"),
+        res("image", "dtx", 2, "", 0, b"caf\xE9 \x80\r\n"),
+    ];
+    let bytes = build_rez(&v1_header_prefix(), &tree, Layout::DirectoryAtEnd);
+    let path = write_rez("transcode.rez", &bytes);
+    let output_dir = temp_path("transcode");
+    lithrez_ok(["extract".as_ref(), "--transcode-text-ext".as_ref(), "txt,cfg".as_ref(), "--from-encoding".as_ref(), "cp1252".as_ref(), path.as_os_str(), output_dir.as_os_str()]);
+
+    assert_eq!(std::fs::read(output_dir.join("notes.TXT")).unwrap(), "caf\u{E9} \u{20AC}\r\n".as_bytes());
+    assert_eq!(std::fs::read(output_dir.join("image.dtx")).unwrap(), b"caf\xE9 \x80\r\n");
+
+    let output_dir = temp_path("transcode-utf16");
+    lithrez_ok(["extract".as_ref(), "--transcode-text-ext".as_ref(), "txt".as_ref(), "--to-encoding".as_ref(), "utf-16le".as_ref(), path.as_os_str(), output_dir.as_os_str()]);
+    assert_eq!(std::fs::read(output_dir.join("notes.TXT")).unwrap(), b"c\0a\0f\0\xE9\0 \0\xAC\x20\r\0\n\0");
+}
+
+#[test]
+fn ndjson_events() {
+    // with the directory at the front, the data of the last resource is at the end of the file
+    let mut bytes = build_rez(&v1_header_prefix(), &sample_tree(), Layout::DirectoryAtFront);
+    bytes.truncate(bytes.len() - 10);
+    let path = write_rez("events.rez", &bytes);
+    let output_dir = temp_path("events");
+    let output = lithrez_ok(["extract".as_ref(), "--events".as_ref(), "ndjson".as_ref(), "--on-short-read".as_ref(), "skip".as_ref(), path.as_os_str(), output_dir.as_os_str()]);
+
+    let events: Vec<serde_json::Value> = output.lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(events.len(), 6);
+    assert_eq!(events[0], serde_json::json!({"event": "start", "total_files": 4, "total_bytes": 12 + 12 + 300 + 64}));
+    assert_eq!(events[1], serde_json::json!({"event": "file", "path": "README.txt", "bytes": 12}));
+    assert_eq!(events[4]["event"], "skip");
+    assert_eq!(events[4]["path"], "tex/ui/button.dtx");
+    assert_eq!(events[5], serde_json::json!({"event": "done", "files": 3, "bytes": 12 + 12 + 300}));
+}
+
+#[cfg(unix)] // the free space is only determined on Unix-like systems
+#[test]
+fn refuses_without_free_space() {
+    // many resources sharing almost 4 GiB of data take up more space than any test machine has
+    const RESOURCE_COUNT: u32 = 10_000;
+    let encode_block = |data_position: u32| {
+        let mut block = Vec::new();
+        for i in 0..RESOURCE_COUNT {
+            block.extend_from_slice(&0u32.to_le_bytes()); // resource
+            block.extend_from_slice(&data_position.to_le_bytes());
+            block.extend_from_slice(&(u32::MAX - data_position).to_le_bytes());
+            block.extend_from_slice(&0u32.to_le_bytes()); // time
+            block.extend_from_slice(&(i + 1).to_le_bytes()); // id
+            block.extend_from_slice(b"nib\0");
+            block.extend_from_slice(&0u32.to_le_bytes()); // key count
+            block.extend_from_slice(format!("f{}\0\0", i).as_bytes());
+        }
+        block
+    };
+    let mut bytes = v1_header_prefix();
+    let header_length = u32::try_from(bytes.len()).unwrap() + 9*4 + 1;
+    let block_length = u32::try_from(encode_block(0).len()).unwrap();
+    for value in [header_length, block_length, 0, u32::MAX, 0, 0, 16, 16, 16] {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    bytes.push(0x00);
+    bytes.extend(encode_block(header_length + block_length));
+
+    let path = write_rez("no-free-space.rez", &bytes);
+    std::fs::File::options().write(true).open(&path).unwrap()
+        .set_len(u32::MAX.into()).unwrap();
+    let output_dir = temp_path("no-free-space");
+    let output = common::lithrez(["extract".as_ref(), path.as_os_str(), output_dir.as_os_str()]);
+    // a dry run writes nothing, so it needs no space
+    let dry_run_output = common::lithrez(["extract".as_ref(), "--dry-run".as_ref(), path.as_os_str(), output_dir.as_os_str()]);
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(dry_run_output.status.success());
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("refusing to extract"));
+    assert!(!output_dir.exists());
+}
+
+#[test]
+fn cat_single_file() {
+    let path = sample_rez("cat.rez");
+
+    assert_eq!(lithrez_ok(["cat".as_ref(), path.as_os_str(), "README.txt".as_ref()]), "hello world\n");
+    let output = common::lithrez(["cat".as_ref(), path.as_os_str(), "sounds/Alpha.wav".as_ref()]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, vec![0xAA; 300]);
+
+    let output = common::lithrez(["cat".as_ref(), path.as_os_str(), "sounds".as_ref()]);
+    assert_eq!(output.status.code(), Some(5));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("sounds not found in REZ file"), "unexpected error output:\n{}", stderr);
+}
+
+#[test]
+fn missing_files() {
+    let missing = temp_path("missing.rez");
+    let output = common::lithrez(["extract".as_ref(), missing.as_os_str(), temp_path("missing-out").as_os_str()]);
+    assert_eq!(output.status.code(), Some(3));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(&format!("failed to open {}: ", missing.display())), "unexpected error output:\n{}", stderr);
+    assert!(!stderr.contains("panicked"));
+
+    let path = sample_rez("missing-only.rez");
+    let output = common::lithrez(["extract".as_ref(), "--only".as_ref(), "music".as_ref(), path.as_os_str(), temp_path("missing-only").as_os_str()]);
+    assert_eq!(output.status.code(), Some(5));
+}
+
+#[test]
+fn only_uses_extracted_dir_names() {
+    let path = sample_rez("only-dir-names.rez");
+    for only in ["tex", "tex/ui"] {
+        let output_dir = temp_path("only-dir-names");
+        lithrez_ok(["extract".as_ref(), "--only".as_ref(), only.as_ref(), "--collapse-single-dirs".as_ref(), path.as_os_str(), output_dir.as_os_str()]);
+        assert!(output_dir.join("tex_ui").join("button.dtx").is_file(), "--only {}", only);
+        assert!(!output_dir.join("tex").exists(), "--only {}", only);
+    }
+
+    let tree = vec![
+        dir("..", 1600000000, vec![
+            res("escaped", "txt", 1, "", 1600000000, b"escaped"),
+        ]),
+    ];
+    let path = write_rez("only-unsafe-names.rez", &build_rez(&v1_header_prefix(), &tree, Layout::DirectoryAtEnd));
+    let parent_dir = temp_path("only-unsafe-names");
+    let output_dir = parent_dir.join("out");
+    lithrez_ok(["extract".as_ref(), "--only".as_ref(), "..".as_ref(), path.as_os_str(), output_dir.as_os_str()]);
+    assert_eq!(std::fs::read(output_dir.join("__").join("escaped.txt")).unwrap(), b"escaped");
+    assert!(!parent_dir.join("escaped.txt").exists());
+}
+
+#[test]
+fn parallel_jobs() {
+    let path = sample_rez("jobs.rez");
+    let output_dir = temp_path("jobs");
+    lithrez_ok(["extract".as_ref(), "--jobs".as_ref(), "3".as_ref(), "--collapse-single-dirs".as_ref(), path.as_os_str(), output_dir.as_os_str()]);
+
+    assert_eq!(std::fs::read(output_dir.join("README.txt")).unwrap(), b"hello world\n");
+    assert_eq!(std::fs::read(output_dir.join("sounds").join("Alpha.wav")).unwrap(), vec![0xAA; 300]);
+    assert_eq!(std::fs::read(output_dir.join("sounds").join("boom.wav")).unwrap().len(), 12);
+    assert_eq!(std::fs::read(output_dir.join("tex_ui").join("button.dtx")).unwrap(), vec![0u8; 64]);
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn memory_mapped() {
+    let path = sample_rez("mmap.rez");
+    let listing = lithrez_ok(["list".as_ref(), path.as_os_str()]);
+    let mapped_listing = lithrez_ok(["list".as_ref(), "--mmap".as_ref(), path.as_os_str()]);
+    assert_eq!(mapped_listing, listing);
+
+    for jobs in ["1", "2"] {
+        let output_dir = temp_path("mmap");
+        lithrez_ok(["extract".as_ref(), "--mmap".as_ref(), "--jobs".as_ref(), jobs.as_ref(), path.as_os_str(), output_dir.as_os_str()]);
+        assert_eq!(std::fs::read(output_dir.join("README.txt")).unwrap(), b"hello world\n");
+        assert_eq!(std::fs::read(output_dir.join("sounds").join("Alpha.wav")).unwrap(), vec![0xAA; 300]);
+        assert_eq!(std::fs::read(output_dir.join("tex").join("ui").join("button.dtx")).unwrap(), vec![0u8; 64]);
+    }
+}
+
+#[test]
+fn convert_dtx() {
+    let tree = vec![
+        dir("tex", 1600000000, vec![
+            res("red", "dtx", 1, "", 1600000000, &dtx_texture(2, 1, 3, &[0, 0, 255, 255, 255, 0, 0, 128])),
+            res("broken", "dtx", 2, "", 1600000000, &[0u8; 64]),
+        ]),
+    ];
+    let bytes = build_rez(&v1_header_prefix(), &tree, Layout::DirectoryAtEnd);
+    let path = write_rez("convert-dtx.rez", &bytes);
+
+    let output_dir = temp_path("convert-dtx");
+    lithrez_ok(["extract".as_ref(), path.as_os_str(), output_dir.as_os_str()]);
+    assert!(!output_dir.join("tex").join("red.png").exists());
+
+    let output_dir = temp_path("convert-dtx");
+    lithrez_ok(["extract".as_ref(), "--convert-dtx".as_ref(), path.as_os_str(), output_dir.as_os_str()]);
+    assert!(output_dir.join("tex").join("red.dtx").is_file());
+    assert!(!output_dir.join("tex").join("broken.png").exists());
+
+    let decoder = png::Decoder::new(std::fs::File::open(output_dir.join("tex").join("red.png")).unwrap());
+    let mut reader = decoder.read_info().unwrap();
+    let mut pixels = vec![0u8; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).unwrap();
+    assert_eq!((info.width, info.height), (2, 1));
+    assert_eq!(info.color_type, png::ColorType::Rgba);
+    assert_eq!(&pixels[..info.buffer_size()], &[255, 0, 0, 255, 0, 0, 255, 128]);
+}
+
+#[test]
+fn exclude_patterns() {
+    let path = sample_rez("exclude.rez");
+    let output_dir = temp_path("exclude");
+    lithrez_ok(["extract".as_ref(), "--exclude".as_ref(), "**.wav".as_ref(), "--exclude".as_ref(), "tex/**".as_ref(), path.as_os_str(), output_dir.as_os_str()]);
+    assert!(output_dir.join("README.txt").is_file());
+    assert!(!output_dir.join("sounds").join("boom.wav").exists());
+    assert!(!output_dir.join("sounds").join("Alpha.wav").exists());
+    assert!(!output_dir.join("tex").join("ui").join("button.dtx").exists());
+
+    // exclusions win over inclusions
+    let output_dir = temp_path("exclude");
+    lithrez_ok(["extract".as_ref(), "-f".as_ref(), "sounds/**".as_ref(), "--exclude".as_ref(), "**/boom.*".as_ref(), path.as_os_str(), output_dir.as_os_str()]);
+    assert!(output_dir.join("sounds").join("Alpha.wav").is_file());
+    assert!(!output_dir.join("sounds").join("boom.wav").exists());
+    assert!(!output_dir.join("README.txt").exists());
+}
+
+#[test]
+fn dry_run() {
+    let mut tree = sample_tree();
+    tree.push(res("readme", "TXT", 5, "", 1600000000, b"lower"));
+    let bytes = build_rez(&v1_header_prefix(), &tree, Layout::DirectoryAtEnd);
+    let path = write_rez("dry-run.rez", &bytes);
+    let output_dir = temp_path("dry-run");
+    std::fs::create_dir_all(output_dir.join("sounds")).unwrap();
+    std::fs::write(output_dir.join("sounds").join("boom.wav"), b"old").unwrap();
+
+    let output = common::lithrez(["extract".as_ref(), "--dry-run".as_ref(), "--descriptions".as_ref(), "--ext".as_ref(), "txt,wav".as_ref(), path.as_os_str(), output_dir.as_os_str()]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let expected: Vec<String> = [
+        ("README.txt", vec!["README.txt"]),
+        ("README.txt (description)", vec!["README.txt.txt"]),
+        ("sounds/boom.wav", vec!["sounds", "boom.wav"]),
+        ("sounds/Alpha.wav", vec!["sounds", "Alpha.wav"]),
+        ("sounds/Alpha.wav (description)", vec!["sounds", "Alpha.wav.txt"]),
+        ("readme.TXT", vec!["readme.TXT"]),
+    ].iter()
+        .map(|(source, components)| {
+            let mut target = output_dir.clone();
+            target.extend(components);
+            format!("{} -> {}\n", source, target.display())
+        })
+        .collect();
+    assert_eq!(stdout, expected.concat());
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("sounds/boom.wav would overwrite the existing file"));
+    assert!(stderr.contains("README.txt and readme.TXT would be written to"));
+
+    // nothing has been touched
+    assert_eq!(std::fs::read(output_dir.join("sounds").join("boom.wav")).unwrap(), b"old");
+    assert!(!output_dir.join("README.txt").exists());
+}
+
+#[test]
+fn existing_files() {
+    let path = sample_rez("existing.rez");
+    let populate = || {
+        let output_dir = temp_path("existing");
+        std::fs::create_dir_all(output_dir.join("sounds")).unwrap();
+        std::fs::write(output_dir.join("README.txt"), b"old readme").unwrap();
+        std::fs::write(output_dir.join("sounds").join("boom.wav"), b"old boom").unwrap();
+        std::fs::write(output_dir.join("sounds").join("boom~1.wav"), b"old boom 1").unwrap();
+        output_dir
+    };
+
+    let output_dir = populate();
+    lithrez_ok(["extract".as_ref(), "--skip-existing".as_ref(), path.as_os_str(), output_dir.as_os_str()]);
+    assert_eq!(std::fs::read(output_dir.join("README.txt")).unwrap(), b"old readme");
+    assert_eq!(std::fs::read(output_dir.join("sounds").join("Alpha.wav")).unwrap(), vec![0xAA; 300]);
+
+    for jobs in ["1", "2"] {
+        let output_dir = populate();
+        lithrez_ok(["extract".as_ref(), "--rename-duplicates".as_ref(), "--jobs".as_ref(), jobs.as_ref(), path.as_os_str(), output_dir.as_os_str()]);
+        assert_eq!(std::fs::read(output_dir.join("README.txt")).unwrap(), b"old readme");
+        assert_eq!(std::fs::read(output_dir.join("README~1.txt")).unwrap(), b"hello world\n");
+        assert_eq!(std::fs::read(output_dir.join("sounds").join("boom~1.wav")).unwrap(), b"old boom 1");
+        assert_eq!(std::fs::read(output_dir.join("sounds").join("boom~2.wav")).unwrap().len(), 12);
+    }
+
+    // overwrite this one, skip all further ones
+    let output_dir = populate();
+    let output = lithrez_with_stdin(["extract".as_ref(), "--prompt".as_ref(), path.as_os_str(), output_dir.as_os_str()], b"x\no\nS\n");
+    assert!(output.status.success());
+    assert_eq!(std::fs::read(output_dir.join("README.txt")).unwrap(), b"hello world\n");
+    assert_eq!(std::fs::read(output_dir.join("sounds").join("boom.wav")).unwrap(), b"old boom");
+    assert_eq!(String::from_utf8_lossy(&output.stderr).matches("already exists").count(), 3);
+
+    let output = common::lithrez(["extract".as_ref(), "--skip-existing".as_ref(), "--overwrite".as_ref(), path.as_os_str(), output_dir.as_os_str()]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn preserve_times() {
+    let modified = |path: std::path::PathBuf| std::fs::metadata(path).unwrap()
+        .modified().unwrap()
+        .duration_since(std::time::UNIX_EPOCH).unwrap()
+        .as_secs();
+    let path = sample_rez("preserve-times.rez");
+
+    let output_dir = temp_path("preserve-times");
+    lithrez_ok(["extract".as_ref(), path.as_os_str(), output_dir.as_os_str()]);
+    assert_eq!(modified(output_dir.join("README.txt")), 1600000000);
+    assert_eq!(modified(output_dir.join("sounds").join("boom.wav")), 1600000100);
+    assert_eq!(modified(output_dir.join("sounds")), 1600000300);
+    assert_eq!(modified(output_dir.join("tex").join("ui")), 1650000000);
+
+    let output_dir = temp_path("preserve-times");
+    lithrez_ok(["extract".as_ref(), "--no-preserve-times".as_ref(), path.as_os_str(), output_dir.as_os_str()]);
+    assert!(modified(output_dir.join("README.txt")) > 1650000000);
+    assert!(modified(output_dir.join("sounds")) > 1650000000);
+}
+
+#[test]
+fn flatten() {
+    let mut tree = sample_tree();
+    tree.push(dir("other", 1600000000, vec![
+        res("boom", "wav", 9, "", 1600000000, b"other boom"),
+    ]));
+    let bytes = build_rez(&v1_header_prefix(), &tree, Layout::DirectoryAtEnd);
+    let path = write_rez("flatten.rez", &bytes);
+
+    let output_dir = temp_path("flatten");
+    lithrez_ok(["extract".as_ref(), "--flatten".as_ref(), path.as_os_str(), output_dir.as_os_str()]);
+    let mut names: Vec<String> = std::fs::read_dir(&output_dir).unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["Alpha.wav", "README.txt", "boom~2.wav", "boom~9.wav", "button.dtx"]);
+    assert_eq!(std::fs::read(output_dir.join("boom~9.wav")).unwrap(), b"other boom");
+
+    let output_dir = temp_path("flatten");
+    lithrez_ok(["extract".as_ref(), "--flatten".as_ref(), "--flatten-collisions".as_ref(), "existing".as_ref(), "--rename-duplicates".as_ref(), "--ext".as_ref(), "wav".as_ref(), path.as_os_str(), output_dir.as_os_str()]);
+    assert_eq!(std::fs::read(output_dir.join("boom.wav")).unwrap().len(), 12);
+    assert_eq!(std::fs::read(output_dir.join("boom~1.wav")).unwrap(), b"other boom");
+    assert!(!output_dir.join("sounds").exists());
+}
+
+#[test]
+fn lowercase_names() {
+    let path = sample_rez("lowercase.rez");
+    let output_dir = temp_path("lowercase");
+    lithrez_ok(["extract".as_ref(), "--lowercase".as_ref(), "--ext-map".as_ref(), "dtx=DDS".as_ref(), path.as_os_str(), output_dir.as_os_str()]);
+    assert_eq!(std::fs::read(output_dir.join("readme.txt")).unwrap(), b"hello world\n");
+    assert_eq!(std::fs::read(output_dir.join("sounds").join("alpha.wav")).unwrap(), vec![0xAA; 300]);
+    assert!(output_dir.join("tex").join("ui").join("button.dds").is_file());
+    assert!(!output_dir.join("README.txt").exists());
+}
+
+#[test]
+fn unsafe_names() {
+    let tree = vec![
+        dir("..", 1600000000, vec![
+            res("escaped", "txt", 1, "", 1600000000, b"escaped"),
+        ]),
+        res("CON", "txt", 2, "", 1600000000, b"console"),
+        res("a/../../b", "txt", 3, "", 1600000000, b"separators"),
+    ];
+    let bytes = build_rez(&v1_header_prefix(), &tree, Layout::DirectoryAtEnd);
+    let path = write_rez("unsafe-names.rez", &bytes);
+
+    let parent_dir = temp_path("unsafe-names");
+    let output_dir = parent_dir.join("out");
+    let output = common::lithrez(["extract".as_ref(), path.as_os_str(), output_dir.as_os_str()]);
+    assert!(output.status.success());
+    assert_eq!(std::fs::read(output_dir.join("__").join("escaped.txt")).unwrap(), b"escaped");
+    assert_eq!(std::fs::read(output_dir.join("CON_.txt")).unwrap(), b"console");
+    assert_eq!(std::fs::read(output_dir.join("a_.._.._b.txt")).unwrap(), b"separators");
+    assert!(!parent_dir.join("escaped.txt").exists());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("rewriting the unsafe directory name \"..\""));
+    assert!(stderr.contains("rewriting the unsafe file name of \"CON.txt\""));
+
+    let parent_dir = temp_path("unsafe-names");
+    let output_dir = parent_dir.join("out");
+    lithrez_ok(["extract".as_ref(), "--trust-names".as_ref(), "--id".as_ref(), "1".as_ref(), path.as_os_str(), output_dir.as_os_str()]);
+    assert_eq!(std::fs::read(parent_dir.join("escaped.txt")).unwrap(), b"escaped");
+}
+
+#[test]
+fn quiet() {
+    let path = sample_rez("quiet.rez");
+    let extract = |extra_args: &[&str]| {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_lithrez"))
+            .arg("extract")
+            .args(extra_args)
+            .arg(&path)
+            .arg(temp_path("quiet"))
+            .env("RUST_LOG", "info")
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stderr).unwrap()
+    };
+
+    assert!(extract(&[]).contains("extracting sounds/boom.wav as "));
+    assert!(!extract(&["--quiet"]).contains("extracting"));
+    assert!(!extract(&["--quiet"]).contains("selected for extraction"));
+
+    let verbose = extract(&["-v"]);
+    assert!(verbose.contains("4 files with a total size of 388 bytes selected for extraction"));
+    assert!(!verbose.contains("reading directory block"));
+    assert!(extract(&["-vv"]).contains("reading directory block at "));
+}
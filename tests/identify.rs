@@ -0,0 +1,36 @@
+//! Checks the `identify` command.
+
+mod common;
+
+use common::{build_rez, lithrez_ok, sample_rez, sample_tree, v1_header_prefix, v2_header_prefix, write_rez, Layout, FILE_TYPE};
+
+
+#[test]
+fn identify_mixed_files() {
+    let v1 = sample_rez("identify-v1.rez");
+    let v2 = write_rez("identify-v2.rez", &build_rez(&v2_header_prefix(7), &sample_tree(), Layout::DirectoryAtEnd));
+    let other = write_rez("identify-other.rez", b"PK\x03\x04 definitely not a REZ file");
+    let short = write_rez("identify-short.rez", b"\r\n");
+
+    let output = lithrez_ok(["identify".as_ref(), "--count".as_ref(), v1.as_os_str(), v2.as_os_str(), other.as_os_str(), short.as_os_str()]);
+    let expected = format!(
+        "{}: {} v1 (4 files)\n{}: {} v2 (4 files)\n{}: not a REZ file\n{}: not a REZ file\n",
+        v1.display(), FILE_TYPE, v2.display(), FILE_TYPE, other.display(), short.display(),
+    );
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn missing_file_type() {
+    let mut header = v1_header_prefix();
+    header[2..62].fill(b' ');
+    let path = write_rez("identify-no-type.rez", &build_rez(&header, &sample_tree(), Layout::DirectoryAtEnd));
+
+    let output = lithrez_ok(["identify".as_ref(), path.as_os_str()]);
+    assert_eq!(output, format!("{}: (no file type) v1\n", path.display()));
+
+    let output_dir = common::temp_path("expect-no-type");
+    lithrez_ok(["extract".as_ref(), "--expect-type".as_ref(), "".as_ref(), path.as_os_str(), output_dir.as_os_str()]);
+    let output = common::lithrez(["extract".as_ref(), "--expect-type".as_ref(), FILE_TYPE.as_ref(), path.as_os_str(), output_dir.as_os_str()]);
+    assert!(!output.status.success());
+}
@@ -0,0 +1,65 @@
+//! Checks the `info` and `du` commands.
+
+mod common;
+
+use common::{build_rez, lithrez_ok, sample_rez, sample_tree, v1_header_prefix, write_rez, Layout, FILE_TYPE, USER_TITLE};
+
+
+#[test]
+fn info_with_extensions() {
+    let path = sample_rez("info.rez");
+    let file_size = std::fs::metadata(&path).unwrap().len() as usize;
+
+    let info = lithrez_ok(["info".as_ref(), path.as_os_str()]);
+    assert!(info.contains(&format!("file type: {}\n", FILE_TYPE)));
+    assert!(info.contains(&format!("user title: {}\n", USER_TITLE)));
+    assert!(info.contains(&format!("size: {} bytes\n", file_size)));
+    assert!(info.contains("files: 4\n"));
+    assert!(!info.contains("extensions:"));
+
+    let info = lithrez_ok(["info".as_ref(), "--with-extensions".as_ref(), path.as_os_str()]);
+    assert!(info.ends_with("extensions:\n  dtx: 1\n  txt: 1\n  wav: 2\n"));
+}
+
+#[test]
+fn header_fields() {
+    let path = sample_rez("info-header.rez");
+    let file_size = std::fs::metadata(&path).unwrap().len() as usize;
+
+    let info = lithrez_ok(["info".as_ref(), "--header-only".as_ref(), path.as_os_str()]);
+    // the test builder writes the root directory block last
+    let root_dir = info.lines()
+        .find_map(|line| line.strip_prefix("root directory: "))
+        .and_then(|rest| rest.strip_suffix(" bytes"))
+        .and_then(|rest| rest.split_once('+'))
+        .map(|(position, size)| (position.parse::<usize>().unwrap(), size.parse::<usize>().unwrap()))
+        .unwrap();
+    assert_eq!(root_dir.0 + root_dir.1, file_size);
+    assert!(info.contains("root directory time: 1700000000\n"));
+    assert!(info.contains(&format!("next write position: {}\n", file_size)));
+    assert!(info.ends_with("\
+largest key array: 0
+largest directory name size: 16
+largest file name size: 16
+largest description size: 32
+"));
+}
+
+#[test]
+fn control_characters_are_escaped() {
+    let mut bytes = build_rez(&v1_header_prefix(), &sample_tree(), Layout::DirectoryAtEnd);
+    // the user title follows the CR LF after the file type
+    let user_title_start = 2 + 60 + 2;
+    bytes[user_title_start..user_title_start + 4].copy_from_slice(b"\x1B[2J");
+    let path = write_rez("info-control-chars.rez", &bytes);
+
+    let info = lithrez_ok(["info".as_ref(), path.as_os_str()]);
+    assert!(info.contains("user title: \\x1B[2J archive\n"));
+}
+
+#[test]
+fn du() {
+    let path = sample_rez("du.rez");
+    let sizes = lithrez_ok(["du".as_ref(), path.as_os_str()]);
+    assert_eq!(sizes, "388\t/\n312\tsounds\n64\ttex\n64\ttex/ui\n0\tsounds/empty\n");
+}
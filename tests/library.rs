@@ -0,0 +1,294 @@
+//! Uses the library crate directly, without going through the command-line interface.
+
+mod common;
+
+use std::io::{Cursor, Read};
+
+use common::{build_rez, dtx_texture, sample_tree, v1_header_prefix, Layout};
+use lithrez::{dtx, rez};
+
+
+#[test]
+fn read_directory_and_resource() {
+    let bytes = build_rez(&v1_header_prefix(), &sample_tree(), Layout::DirectoryAtEnd);
+    let mut cursor = Cursor::new(bytes);
+    let rez_file = rez::File::try_read(&mut cursor).unwrap();
+
+    assert!(rez_file.entry_by_path("sounds/empty").unwrap().is_directory());
+    assert!(rez_file.contains("sounds/boom.wav"));
+    let readme = rez_file.resource_by_path("README.txt").unwrap();
+    assert_eq!(readme.id, 1);
+
+    let mut reader = rez::RezReader::new(cursor);
+    reader.set_base_offset(rez_file.base_offset);
+    let data = reader.read_resource(readme).unwrap();
+    assert_eq!(data, b"hello world\n");
+}
+
+#[test]
+fn stream_resource() {
+    let bytes = build_rez(&v1_header_prefix(), &sample_tree(), Layout::DirectoryAtFront);
+    let mut prefixed = b"junk".to_vec();
+    prefixed.extend_from_slice(&bytes);
+    let mut cursor = Cursor::new(prefixed);
+    cursor.set_position(4);
+    let rez_file = rez::File::try_read(&mut cursor).unwrap();
+
+    let alpha = rez_file.resource_by_path("sounds/Alpha.wav").unwrap();
+    let mut data = rez_file.open_resource(&mut cursor, alpha);
+    let mut start = [0u8; 100];
+    data.read_exact(&mut start).unwrap();
+    assert_eq!(start, [0xAA; 100]);
+    assert_eq!(data.remaining(), 200);
+    let mut rest = Vec::new();
+    data.read_to_end(&mut rest).unwrap();
+    assert_eq!(rest, vec![0xAA; 200]);
+
+    // the last resource is cut short
+    let button = rez_file.resource_by_path("tex/ui/button.dtx").unwrap();
+    let mut truncated = Cursor::new(cursor.get_ref()[..cursor.get_ref().len() - 10].to_vec());
+    let err = rez_file.open_resource(&mut truncated, button).read_to_end(&mut Vec::new()).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn archive_lookup() {
+    let bytes = build_rez(&v1_header_prefix(), &sample_tree(), Layout::DirectoryAtEnd);
+    let mut archive = rez::Archive::open(Cursor::new(bytes)).unwrap();
+
+    assert!(archive.exists("sounds/boom.wav"));
+    assert!(archive.exists("SOUNDS\\Boom.WAV"));
+    assert!(!archive.exists("sounds"));
+    assert!(!archive.exists("sounds/boom.wav/x"));
+    assert_eq!(archive.get("/sounds//alpha.wav").unwrap().id, 3);
+    assert_eq!(archive.read("readme.TXT").unwrap(), b"hello world\n");
+    assert_eq!(archive.read("Sounds/Alpha.wav").unwrap(), vec![0xAA; 300]);
+    assert!(matches!(archive.read("sounds/missing.wav"), Err(rez::Error::NoSuchEntry { .. })));
+    assert_eq!(archive.rez_file().entry_by_path_ignoring_case("TEX/UI").unwrap().name(), "ui");
+    assert!(archive.rez_file().entry_by_path("TEX/UI").is_none());
+
+    // a damaged size must not make reading allocate that much up front
+    let mut bytes = archive.into_inner().0.into_inner();
+    let mut readme_entry = 12u32.to_le_bytes().to_vec();
+    readme_entry.extend_from_slice(&1600000000u32.to_le_bytes());
+    readme_entry.extend_from_slice(&1u32.to_le_bytes());
+    let index = bytes.windows(readme_entry.len()).position(|w| w == readme_entry).unwrap();
+    bytes[index..index+4].copy_from_slice(&u32::MAX.to_le_bytes());
+    let mut archive = rez::Archive::open(Cursor::new(bytes)).unwrap();
+    match archive.read("README.txt") {
+        Err(rez::Error::Io(e)) => assert_eq!(e.kind(), std::io::ErrorKind::UnexpectedEof),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn walk_depth_first() {
+    let bytes = build_rez(&v1_header_prefix(), &sample_tree(), Layout::DirectoryAtFront);
+    let rez_file = rez::File::try_read(&mut Cursor::new(bytes)).unwrap();
+
+    let paths: Vec<(String, bool)> = rez_file.walk()
+        .map(|(path, entry)| (path, entry.is_directory()))
+        .collect();
+    let expected = [
+        ("README.txt", false),
+        ("sounds", true),
+        ("sounds/boom.wav", false),
+        ("sounds/Alpha.wav", false),
+        ("sounds/empty", true),
+        ("tex", true),
+        ("tex/ui", true),
+        ("tex/ui/button.dtx", false),
+    ];
+    assert_eq!(paths, expected.map(|(path, is_dir)| (path.to_owned(), is_dir)));
+}
+
+#[test]
+fn lazy_directories() {
+    let bytes = build_rez(&v1_header_prefix(), &sample_tree(), Layout::DirectoryAtEnd);
+    let mut reader = Cursor::new(bytes);
+    let eager = rez::File::try_read(&mut reader).unwrap();
+    reader.set_position(0);
+    let mut lazy = rez::File::try_read_lazy(&mut reader).unwrap();
+
+    let names: Vec<_> = lazy.root_entries.iter().map(|e| e.name().into_owned()).collect();
+    assert_eq!(names, ["README.txt", "sounds", "tex"]);
+    let sounds = lazy.root_entries[1].as_directory().unwrap();
+    assert!(!sounds.entries_loaded);
+    assert!(sounds.entries.is_empty());
+    assert!(!lazy.contains("tex/ui/button.dtx"));
+
+    let ui = lazy.load_directory(&mut reader, "tex\\ui").unwrap();
+    assert_eq!(ui.entries[0].as_resource().unwrap().id, 4);
+    assert!(lazy.contains("tex/ui/button.dtx"));
+    assert!(!lazy.root_entries[1].as_directory().unwrap().entries_loaded);
+    assert!(matches!(lazy.load_directory(&mut reader, "tex/nope"), Err(rez::Error::NoSuchEntry { .. })));
+    assert!(matches!(lazy.load_directory(&mut reader, "README.txt"), Err(rez::Error::NoSuchEntry { .. })));
+
+    // writing would lose the entries of the sounds directory
+    let result = lazy.clone().write(&mut Cursor::new(Vec::new()), |_path, _res| Ok(std::io::empty()));
+    assert!(matches!(result, Err(rez::Error::CannotModify { .. })));
+
+    let (base_offset, file_size) = (lazy.base_offset, lazy.file_size);
+    if let rez::Entry::Directory(sounds) = &mut lazy.root_entries[1] {
+        sounds.load_tree(&mut reader, base_offset, file_size).unwrap();
+    }
+    assert_eq!(lazy, eager);
+}
+
+#[test]
+fn writer_round_trip() {
+    let mut writer = rez::Writer::new(Cursor::new(Vec::new()), "test type", "test title", 1600000000).unwrap();
+    writer.add_directory("empty", 1600000100).unwrap();
+    writer.add_resource("sounds/loud", resource("boom", "wav", 2), &mut &b"boom"[..]).unwrap();
+    writer.add_resource("", resource("README", "txt", 1), &mut &b"hello world\n"[..]).unwrap();
+    writer.set_sorted(true);
+    let err = writer.add_resource("", resource("README", "txt", 3), &mut &b""[..]).unwrap_err();
+    assert!(matches!(err, rez::Error::DuplicateEntry { .. }));
+    let err = writer.add_resource("SOUNDS/Loud", resource("Boom", "WAV", 3), &mut &b""[..]).unwrap_err();
+    assert!(matches!(err, rez::Error::DuplicateEntry { .. }));
+    let err = writer.rename("README.txt", "Sounds").unwrap_err();
+    assert!(matches!(err, rez::Error::DuplicateEntry { .. }));
+    // only changing the case is a rename
+    writer.rename("README.txt", "readme.TXT").unwrap();
+    writer.rename("readme.TXT", "README.txt").unwrap();
+    let (cursor, written) = writer.finish().unwrap();
+
+    let mut cursor = Cursor::new(cursor.into_inner());
+    let rez_file = rez::File::try_read(&mut cursor).unwrap();
+    assert_eq!(rez_file, written);
+    assert!(rez_file.is_sorted);
+    let names: Vec<_> = rez_file.root_entries.iter().map(|e| e.name().into_owned()).collect();
+    assert_eq!(names, ["empty", "README.txt", "sounds"]);
+
+    let boom = rez_file.resource_by_path("sounds/loud/boom.wav").unwrap();
+    let mut reader = rez::RezReader::new(cursor);
+    assert_eq!(reader.read_resource(boom).unwrap(), b"boom");
+}
+
+#[test]
+fn resources_with_readers() {
+    let mut bytes = b"junk".to_vec();
+    bytes.extend_from_slice(&build_rez(&v1_header_prefix(), &sample_tree(), Layout::DirectoryAtFront));
+    let mut cursor = Cursor::new(bytes);
+    cursor.set_position(4);
+    let rez_file = rez::File::try_read(&mut cursor).unwrap();
+    let mut reader = rez::RezReader::new(cursor);
+
+    let mut resources = reader.resources_with_readers(&rez_file);
+    let mut visited = Vec::new();
+    while let Some((path, mut data)) = resources.next_resource() {
+        // skipping a resource without reading from it must not affect the following ones
+        if path == "sounds/boom.wav" {
+            visited.push((path, Vec::new()));
+            continue;
+        }
+        let mut buf = Vec::new();
+        data.read_to_end(&mut buf).unwrap();
+        visited.push((path, buf));
+    }
+    assert_eq!(visited, [
+        ("README.txt".to_owned(), b"hello world\n".to_vec()),
+        ("sounds/boom.wav".to_owned(), Vec::new()),
+        ("sounds/Alpha.wav".to_owned(), vec![0xAA; 300]),
+        ("tex/ui/button.dtx".to_owned(), vec![0x00; 64]),
+    ]);
+    assert!(resources.next_resource().is_none());
+}
+
+#[test]
+fn extract_to_map() {
+    let mut bytes = b"junk".to_vec();
+    bytes.extend_from_slice(&build_rez(&v1_header_prefix(), &sample_tree(), Layout::DirectoryAtEnd));
+    let mut cursor = Cursor::new(bytes);
+    cursor.set_position(4);
+    let rez_file = rez::File::try_read(&mut cursor).unwrap();
+    let mut reader = rez::RezReader::new(cursor);
+
+    let sounds = |path: &str, _res: &rez::Resource| path.starts_with("sounds/");
+    let map = reader.extract_to_map(&rez_file, sounds).unwrap();
+    let mut paths: Vec<&str> = map.keys().map(|path| path.as_str()).collect();
+    paths.sort_unstable();
+    assert_eq!(paths, ["sounds/Alpha.wav", "sounds/boom.wav"]);
+    assert_eq!(map["sounds/boom.wav"], b"RIFF....WAVE");
+    assert_eq!(map["sounds/Alpha.wav"], vec![0xAA; 300]);
+    // the base offset of the REZ file only applies while extracting
+    assert_eq!(reader.base_offset(), 0);
+
+    let map = reader.extract_to_map_capped(&rez_file, sounds, 312).unwrap();
+    assert_eq!(map.len(), 2);
+    let err = reader.extract_to_map_capped(&rez_file, sounds, 311).unwrap_err();
+    assert!(matches!(err, rez::Error::SizeCapExceeded { cap: 311, required: 312 }), "{:?}", err);
+    assert_eq!(reader.base_offset(), 0);
+}
+
+#[test]
+fn compact_with_empty_resource() {
+    let tree = vec![
+        common::res("empty", "txt", 1, "", 0, b""),
+        common::res("data", "txt", 2, "", 0, b"data"),
+    ];
+    let mut bytes = build_rez(&v1_header_prefix(), &tree, Layout::DirectoryAtEnd);
+    // place the empty resource within the header: type, position, size, time, ID, extension,
+    // number of keys, name
+    let name_start = bytes.windows(6).position(|w| w == b"empty\0").unwrap();
+    bytes[name_start - 6*4..name_start - 5*4].copy_from_slice(&0u32.to_le_bytes());
+
+    let mut writer = rez::Writer::open(Cursor::new(bytes)).unwrap();
+    writer.compact().unwrap();
+    let (cursor, _written) = writer.finish().unwrap();
+
+    let mut cursor = Cursor::new(cursor.into_inner());
+    let rez_file = rez::File::try_read(&mut cursor).unwrap();
+    let mut reader = rez::RezReader::new(cursor);
+    assert_eq!(reader.read_resource(rez_file.resource_by_path("empty.txt").unwrap()).unwrap(), b"");
+    assert_eq!(reader.read_resource(rez_file.resource_by_path("data.txt").unwrap()).unwrap(), b"data");
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn mapped_resource_data() {
+    let bytes = build_rez(&v1_header_prefix(), &sample_tree(), Layout::DirectoryAtEnd);
+    let mut prefixed = b"junk".to_vec();
+    prefixed.extend_from_slice(&bytes);
+    let path = common::write_rez("library-mapped.rez", &prefixed);
+
+    let file = std::fs::File::open(&path).unwrap();
+    let mapped = rez::MappedFile::open(&file, 4).unwrap();
+    assert_eq!(mapped.bytes(), &prefixed[..]);
+    let readme = mapped.rez_file().resource_by_path("README.txt").unwrap();
+    assert_eq!(mapped.resource_data(readme).unwrap(), b"hello world\n");
+    let alpha = mapped.rez_file().resource_by_path("sounds/Alpha.wav").unwrap();
+    assert_eq!(mapped.rez_file().resource_data(&prefixed, alpha).unwrap(), &[0xAA; 300][..]);
+    assert_eq!(mapped.rez_file().resource_data(&prefixed[..200], alpha), None);
+}
+
+#[test]
+fn decode_dxt1_texture() {
+    // red and blue endpoints; the pixels cycle through the four palette entries row by row
+    let mut block = Vec::new();
+    block.extend_from_slice(&0xF800u16.to_le_bytes());
+    block.extend_from_slice(&0x001Fu16.to_le_bytes());
+    block.extend_from_slice(&0b11_10_01_00_11_10_01_00_11_10_01_00_11_10_01_00u32.to_le_bytes());
+    let texture = dtx::decode(&dtx_texture(3, 2, 4, &block)).unwrap();
+    assert_eq!(texture.width, 3);
+    assert_eq!(texture.height, 2);
+    assert_eq!(texture.rgba, [
+        255, 0, 0, 255, 0, 0, 255, 255, 170, 0, 85, 255,
+        255, 0, 0, 255, 0, 0, 255, 255, 170, 0, 85, 255,
+    ]);
+
+    let too_short = dtx_texture(8, 8, 4, &block);
+    assert!(matches!(dtx::decode(&too_short), Err(dtx::Error::Truncated { expected: 32, available: 8 })));
+}
+
+fn resource(name: &str, extension: &str, id: u32) -> rez::Resource {
+    rez::Resource {
+        header: rez::EntryHeader::default(),
+        id,
+        extension: extension.to_owned(),
+        extension_bytes: [0; 4],
+        name: name.to_owned(),
+        description: String::new(),
+        keys: Vec::new(),
+    }
+}
@@ -0,0 +1,250 @@
+//! Checks the output options of the `list` and `find` commands.
+
+mod common;
+
+use common::{build_rez, dir, find, list, lithrez_ok, res, sample_rez, sample_tree, v1_header_prefix, write_rez, Layout};
+
+
+#[test]
+fn list_resources_only() {
+    let path = sample_rez("list-type-resource.rez");
+    let listing = list(&path, &["--type", "resource"]);
+    assert_eq!(listing, "\
+README.txt [1, the readme] (1600000000, 168+12 bytes)
+sounds/boom.wav [2] (1600000100, 180+12 bytes)
+sounds/Alpha.wav [3, orig: c:\\alpha.wav] (1600000200, 192+300 bytes)
+tex/ui/button.dtx [4] (1650000000, 492+64 bytes)
+");
+}
+
+#[test]
+fn streaming_matches_filtered_listing() {
+    // without a filter, entries are output while reading; with one, the whole tree is read first
+    let bytes = build_rez(&v1_header_prefix(), &sample_tree(), Layout::DirectoryAtFront);
+    let path = write_rez("list-streaming.rez", &bytes);
+    for entry_type in ["all", "resource"] {
+        let streamed = lithrez_ok(["list".as_ref(), "--type".as_ref(), entry_type.as_ref(), path.as_os_str()]);
+        let filtered = lithrez_ok(["list".as_ref(), "--type".as_ref(), entry_type.as_ref(), "-f".as_ref(), "**".as_ref(), path.as_os_str()]);
+        // the filter hides directories without any resources
+        let streamed_without_empty: String = streamed.lines()
+            .filter(|line| !line.trim_start().starts_with("empty "))
+            .map(|line| format!("{}\n", line))
+            .collect();
+        assert_eq!(streamed_without_empty, filtered);
+    }
+}
+
+#[test]
+fn list_directories_only() {
+    let path = sample_rez("list-type-directory.rez");
+    let listing = list(&path, &["--type", "directory"]);
+    assert_eq!(listing, "\
+sounds (1600000300)/
+  empty (1600000000)/
+tex (1650000000)/
+  ui (1650000000)/
+");
+}
+
+#[test]
+fn find_directories_with_filter() {
+    let path = sample_rez("find-type-directory.rez");
+    let found = find(&path, &["--type", "directory", "-f", "**.dtx"]);
+    assert_eq!(found, "tex\ntex/ui\n");
+}
+
+#[test]
+fn invalid_pattern_is_reported() {
+    let path = sample_rez("invalid-pattern.rez");
+    let output = common::lithrez(["list".as_ref(), "-f".as_ref(), "".as_ref(), path.as_os_str()]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("invalid pattern: the pattern is empty"));
+}
+
+#[test]
+fn count_entries() {
+    let path = sample_rez("count.rez");
+    assert_eq!(list(&path, &["--count"]), "8\n");
+    assert_eq!(list(&path, &["--count", "--type", "resource", "-f", "**.wav"]), "2\n");
+    assert_eq!(list(&path, &["--count", "--type", "directory", "-f", "**.dtx"]), "2\n");
+    assert_eq!(find(&path, &["--count", "-f", "**.wav"]), "2\n");
+
+    // like grep, no matches result in a nonzero exit status
+    let output = common::lithrez(["list".as_ref(), "--count".as_ref(), "-f".as_ref(), "nomatch*".as_ref(), path.as_os_str()]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8(output.stdout).unwrap().trim_end(), "0");
+}
+
+#[test]
+fn find_by_id_range() {
+    let path = sample_rez("find-id-range.rez");
+    let found = find(&path, &["--id-range", "2..4", "-f", "**.wav"]);
+    assert_eq!(found, "sounds/boom.wav\nsounds/Alpha.wav\n");
+
+    for invalid in ["4..2", "2-4", "2.."] {
+        let output = common::lithrez(["find".as_ref(), "--id-range".as_ref(), invalid.as_ref(), path.as_os_str()]);
+        assert_eq!(output.status.code(), Some(2));
+    }
+}
+
+#[test]
+fn find_with_classes_and_negation() {
+    let path = sample_rez("find-classes.rez");
+    let find_filtered = |filters: &[&str]| {
+        let args: Vec<&str> = filters.iter().flat_map(|filter| ["-f", filter]).collect();
+        find(&path, &args)
+    };
+
+    assert_eq!(find_filtered(&["sounds/[ab]*"]), "sounds/boom.wav\n");
+    assert_eq!(find_filtered(&["sounds/[A-Z]*"]), "sounds/Alpha.wav\n");
+    assert_eq!(find_filtered(&["sounds/[!A-Z]*"]), "sounds/boom.wav\n");
+    assert_eq!(find_filtered(&["**", "!**.wav"]), "README.txt\ntex/ui/button.dtx\n");
+    assert_eq!(find_filtered(&["!sounds/**", "!README.*"]), "tex/ui/button.dtx\n");
+
+    // classes never match a slash
+    let output = common::lithrez(["find".as_ref(), "-f".as_ref(), "sounds[/]boom.wav".as_ref(), path.as_os_str()]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+
+    let output = common::lithrez(["find".as_ref(), "-f".as_ref(), "sounds/[ab".as_ref(), path.as_os_str()]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unterminated character class"));
+}
+
+#[test]
+fn find_with_regex() {
+    let path = sample_rez("find-regex.rez");
+    let found = find(&path, &["--regex", r"^sounds/[a-z]+\.wav$", "-f", "*.txt"]);
+    assert_eq!(found, "README.txt\nsounds/boom.wav\n");
+
+    // not anchored unless requested
+    let found = find(&path, &["--regex", "ui/"]);
+    assert_eq!(found, "tex/ui/button.dtx\n");
+
+    let output = common::lithrez(["list".as_ref(), "--regex".as_ref(), "(".as_ref(), path.as_os_str()]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("invalid pattern: \"(\""));
+}
+
+#[test]
+fn find_ignoring_case() {
+    let path = sample_rez("find-ignore-case.rez");
+    let output = common::lithrez(["find".as_ref(), "-f".as_ref(), "SOUNDS/a*".as_ref(), path.as_os_str()]);
+    assert_eq!(output.status.code(), Some(1));
+
+    let found = find(&path, &["--ignore-case", "-f", "SOUNDS/a*", "--regex", "^readme"]);
+    assert_eq!(found, "README.txt\nsounds/Alpha.wav\n");
+}
+
+#[test]
+fn find_by_extension() {
+    let path = sample_rez("find-ext.rez");
+    let found = find(&path, &["--ext", "DTX,.txt"]);
+    assert_eq!(found, "README.txt\ntex/ui/button.dtx\n");
+
+    let found = find(&path, &["--ext", "wav", "--ext", "txt", "-f", "sounds/**"]);
+    assert_eq!(found, "sounds/boom.wav\nsounds/Alpha.wav\n");
+}
+
+#[test]
+fn find_by_id() {
+    let path = sample_rez("find-id.rez");
+    let found = find(&path, &["--id", "1,4"]);
+    assert_eq!(found, "README.txt\ntex/ui/button.dtx\n");
+
+    // either an ID or the range
+    let found = find(&path, &["--id", "1", "--id-range", "3..3"]);
+    assert_eq!(found, "README.txt\nsounds/Alpha.wav\n");
+
+    let output = common::lithrez(["find".as_ref(), "--id".as_ref(), "one".as_ref(), path.as_os_str()]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn find_by_size_and_time() {
+    let path = sample_rez("find-size.rez");
+    let found = find(&path, &["--min-size", "13"]);
+    assert_eq!(found, "sounds/Alpha.wav\ntex/ui/button.dtx\n");
+
+    let found = find(&path, &["--max-size", "1K", "--older-than", "1600000100"]);
+    assert_eq!(found, "README.txt\nsounds/boom.wav\n");
+
+    let found = find(&path, &["--max-size", "64", "--newer-than", "2021-01-01"]);
+    assert_eq!(found, "tex/ui/button.dtx\n");
+
+    for invalid in ["12Q", "K", "5G"] {
+        let output = common::lithrez(["find".as_ref(), "--min-size".as_ref(), invalid.as_ref(), path.as_os_str()]);
+        assert_eq!(output.status.code(), Some(2));
+    }
+}
+
+#[test]
+fn debug_dump() {
+    let path = sample_rez("debug-dump.rez");
+    let dump = lithrez_ok(["list".as_ref(), "--debug-dump".as_ref(), path.as_os_str()]);
+    assert!(dump.starts_with("FileHeader {\n"));
+    assert!(dump.contains("\nFile {\n"));
+    assert!(dump.contains("name: \"button\","));
+}
+
+#[test]
+fn json_listing() {
+    let path = sample_rez("list-json.rez");
+    let listing = lithrez_ok(["list".as_ref(), "--json".as_ref(), "-f".as_ref(), "sounds/b*".as_ref(), path.as_os_str()]);
+    let tree: serde_json::Value = serde_json::from_str(&listing).unwrap();
+    assert_eq!(tree, serde_json::json!([
+        {
+            "type": "directory",
+            "name": "sounds",
+            "time": 1600000300,
+            "entries": [
+                {
+                    "type": "resource",
+                    "path": "sounds/boom.wav",
+                    "name": "boom",
+                    "extension": "wav",
+                    "id": 2,
+                    "description": "",
+                    "offset": 180,
+                    "size": 12,
+                    "time": 1600000100,
+                    "keys": [],
+                },
+            ],
+        },
+    ]));
+}
+
+#[test]
+fn csv_and_tsv_listing() {
+    let nodes = [
+        dir("docs", 1600000000, vec![
+            res("notes", "txt", 7, "one, \"two\"", 1600000100, b"notes"),
+        ]),
+        res("README", "txt", 1, "the readme", 1600000200, b"hello"),
+    ];
+    let bytes = build_rez(&v1_header_prefix(), &nodes, Layout::DirectoryAtEnd);
+    let path = write_rez("list-csv.rez", &bytes);
+
+    let csv = lithrez_ok(["list".as_ref(), "--csv".as_ref(), path.as_os_str()]);
+    assert_eq!(csv, "\
+path,extension,id,size,offset,time,description
+docs/notes.txt,txt,7,5,168,1600000100,\"one, \"\"two\"\"\"
+README.txt,txt,1,5,173,1600000200,the readme
+");
+    let tsv = lithrez_ok(["list".as_ref(), "--tsv".as_ref(), "-f".as_ref(), "README.txt".as_ref(), path.as_os_str()]);
+    assert_eq!(tsv, "\
+path\textension\tid\tsize\toffset\ttime\tdescription
+README.txt\ttxt\t1\t5\t173\t1600000200\tthe readme
+");
+}
+
+#[test]
+fn whatis() {
+    let path = sample_rez("whatis.rez");
+    let output = lithrez_ok(["whatis".as_ref(), path.as_os_str(), "0x1F0".as_ref()]);
+    assert_eq!(output, "tex/ui/button.dtx (byte 4 of 64, stored at 492+64)\n");
+
+    let output = common::lithrez(["whatis".as_ref(), path.as_os_str(), "10".as_ref()]);
+    assert_eq!(output.status.code(), Some(1));
+}
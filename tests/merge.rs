@@ -0,0 +1,41 @@
+//! Checks the `merge` command.
+
+mod common;
+
+use common::{build_rez, dir, lithrez_ok, res, sample_rez, temp_path, v1_header_prefix, write_rez, Layout};
+
+
+#[test]
+fn merge_with_conflict_policies() {
+    let base_path = sample_rez("merge-base.rez");
+    let patch_tree = vec![
+        res("README", "txt", 1, "the patched readme", 1700000000, b"patched\n"),
+        dir("sounds", 1700000000, vec![
+            res("bang", "wav", 5, "", 1700000000, b"bang"),
+        ]),
+    ];
+    let patch_bytes = build_rez(&v1_header_prefix(), &patch_tree, Layout::DirectoryAtFront);
+    let patch_path = write_rez("merge-patch.rez", &patch_bytes);
+
+    let merged_path = temp_path("merge-last.rez");
+    lithrez_ok(["merge".as_ref(), "-o".as_ref(), merged_path.as_os_str(), base_path.as_os_str(), patch_path.as_os_str()]);
+    let found = lithrez_ok(["find".as_ref(), merged_path.as_os_str()]);
+    assert_eq!(found, "\
+sounds/boom.wav
+sounds/Alpha.wav
+sounds/bang.wav
+tex/ui/button.dtx
+README.txt
+");
+    assert_eq!(lithrez_ok(["cat".as_ref(), merged_path.as_os_str(), "README.txt".as_ref()]), "patched\n");
+    assert_eq!(lithrez_ok(["cat".as_ref(), merged_path.as_os_str(), "sounds/bang.wav".as_ref()]), "bang");
+    lithrez_ok(["verify".as_ref(), merged_path.as_os_str()]);
+
+    let merged_path = temp_path("merge-first.rez");
+    lithrez_ok([
+        "merge".as_ref(), "--on-conflict".as_ref(), "first-wins".as_ref(), "-o".as_ref(), merged_path.as_os_str(),
+        base_path.as_os_str(), patch_path.as_os_str(),
+    ]);
+    assert_eq!(lithrez_ok(["cat".as_ref(), merged_path.as_os_str(), "README.txt".as_ref()]), "hello world\n");
+    assert_eq!(lithrez_ok(["cat".as_ref(), merged_path.as_os_str(), "sounds/bang.wav".as_ref()]), "bang");
+}
@@ -0,0 +1,113 @@
+//! Modifies handcrafted REZ files in place and checks the resulting contents.
+
+mod common;
+
+use std::path::Path;
+
+use common::{build_rez, lithrez_err, lithrez_ok, sample_rez, temp_path, v1_header_prefix, write_rez, Layout};
+
+
+fn find_all(rez_path: &Path) -> String {
+    lithrez_ok(["find".as_ref(), rez_path.as_os_str()])
+}
+
+fn extract_one(rez_path: &Path, name: &str, entry_path: &str) -> Vec<u8> {
+    let output_dir = temp_path(name);
+    lithrez_ok(["extract".as_ref(), rez_path.as_os_str(), output_dir.as_os_str()]);
+    std::fs::read(output_dir.join(entry_path)).unwrap()
+}
+
+
+#[test]
+fn add_files_and_directories() {
+    let rez_path = sample_rez("add.rez");
+
+    let input_dir = temp_path("add-input");
+    std::fs::create_dir_all(input_dir.join("more")).unwrap();
+    std::fs::write(input_dir.join("more").join("bang.wav"), b"bang").unwrap();
+    std::fs::write(input_dir.join("notes.txt"), b"some notes\n").unwrap();
+
+    lithrez_ok([
+        "add".as_ref(), "--directory".as_ref(), "sounds/new".as_ref(), rez_path.as_os_str(),
+        input_dir.join("more").as_os_str(), input_dir.join("notes.txt").as_os_str(),
+    ]);
+    assert_eq!(find_all(&rez_path), "\
+README.txt
+sounds/boom.wav
+sounds/Alpha.wav
+sounds/new/more/bang.wav
+sounds/new/notes.txt
+tex/ui/button.dtx
+");
+    let listing = lithrez_ok(["list".as_ref(), "--filter".as_ref(), "sounds/new/**".as_ref(), rez_path.as_os_str()]);
+    assert!(listing.contains("bang.wav [5]"), "unexpected listing:\n{}", listing);
+    assert!(listing.contains("notes.txt [6]"), "unexpected listing:\n{}", listing);
+    assert_eq!(extract_one(&rez_path, "add-extracted", "sounds/new/notes.txt"), b"some notes\n");
+    assert_eq!(extract_one(&rez_path, "add-extracted-old", "sounds/Alpha.wav"), vec![0xAA; 300]);
+
+    // adding an existing file fails but leaves a readable REZ file
+    let stderr = lithrez_err(["add".as_ref(), rez_path.as_os_str(), input_dir.join("notes.txt").as_os_str(), input_dir.join("notes.txt").as_os_str()]);
+    assert!(stderr.contains("an entry already exists at \"notes.txt\""), "unexpected error output:\n{}", stderr);
+    lithrez_ok(["verify".as_ref(), rez_path.as_os_str()]);
+    assert!(find_all(&rez_path).contains("\nnotes.txt\n"));
+}
+
+#[test]
+fn add_without_free_ids() {
+    let tree = vec![common::res("last", "txt", u32::MAX - 1, "", 0, b"last")];
+    let bytes = build_rez(&v1_header_prefix(), &tree, Layout::DirectoryAtEnd);
+    let rez_path = write_rez("add-ids.rez", &bytes);
+    let input_dir = temp_path("add-ids-input");
+    std::fs::create_dir_all(&input_dir).unwrap();
+    std::fs::write(input_dir.join("one.txt"), b"one").unwrap();
+    std::fs::write(input_dir.join("two.txt"), b"two").unwrap();
+
+    // the highest ID is still available
+    lithrez_ok(["add".as_ref(), rez_path.as_os_str(), input_dir.join("one.txt").as_os_str()]);
+    let stderr = lithrez_err(["add".as_ref(), rez_path.as_os_str(), input_dir.join("two.txt").as_os_str()]);
+    assert!(stderr.contains("no resource ID is left for "), "unexpected error output:\n{}", stderr);
+    lithrez_ok(["verify".as_ref(), rez_path.as_os_str()]);
+}
+
+#[test]
+fn remove_and_compact() {
+    let rez_path = sample_rez("rm.rez");
+
+    lithrez_ok(["rm".as_ref(), rez_path.as_os_str(), "sounds/boom.wav".as_ref(), "tex".as_ref()]);
+    assert_eq!(find_all(&rez_path), "README.txt\nsounds/Alpha.wav\n");
+    let uncompacted_length = std::fs::metadata(&rez_path).unwrap().len();
+
+    lithrez_ok(["rm".as_ref(), "--compact".as_ref(), rez_path.as_os_str(), "sounds/empty".as_ref()]);
+    assert_eq!(find_all(&rez_path), "README.txt\nsounds/Alpha.wav\n");
+    let compacted_length = std::fs::metadata(&rez_path).unwrap().len();
+    // header, the data of the two remaining files and a smaller directory
+    assert!(compacted_length < uncompacted_length - 12 - 64);
+    lithrez_ok(["verify".as_ref(), rez_path.as_os_str()]);
+    assert_eq!(extract_one(&rez_path, "rm-extracted", "README.txt"), b"hello world\n");
+    assert_eq!(extract_one(&rez_path, "rm-extracted-alpha", "sounds/Alpha.wav"), vec![0xAA; 300]);
+
+    let stderr = lithrez_err(["rm".as_ref(), rez_path.as_os_str(), "sounds/boom.wav".as_ref()]);
+    assert!(stderr.contains("no entry exists at \"sounds/boom.wav\""), "unexpected error output:\n{}", stderr);
+}
+
+#[test]
+fn rename_and_move() {
+    let rez_path = sample_rez("mv.rez");
+
+    lithrez_ok(["mv".as_ref(), rez_path.as_os_str(), "README.txt".as_ref(), "LIESMICH.text".as_ref()]);
+    lithrez_ok(["mv".as_ref(), rez_path.as_os_str(), "tex/ui".as_ref(), "sounds/empty".as_ref()]);
+    lithrez_ok(["mv".as_ref(), rez_path.as_os_str(), "sounds/boom.wav".as_ref(), "tex/kaboom.wav".as_ref()]);
+    assert_eq!(find_all(&rez_path), "\
+sounds/Alpha.wav
+sounds/empty/ui/button.dtx
+tex/kaboom.wav
+LIESMICH.text
+");
+    lithrez_ok(["verify".as_ref(), rez_path.as_os_str()]);
+    assert_eq!(extract_one(&rez_path, "mv-extracted", "LIESMICH.text"), b"hello world\n");
+
+    let stderr = lithrez_err(["mv".as_ref(), rez_path.as_os_str(), "sounds".as_ref(), "sounds/empty".as_ref()]);
+    assert!(stderr.contains("cannot move \"sounds\" into itself"), "unexpected error output:\n{}", stderr);
+    let stderr = lithrez_err(["mv".as_ref(), rez_path.as_os_str(), "tex/kaboom.wav".as_ref(), "sounds/Alpha.wav".as_ref()]);
+    assert!(stderr.contains("an entry already exists at \"sounds/Alpha.wav\""), "unexpected error output:\n{}", stderr);
+}
@@ -0,0 +1,234 @@
+//! Parses handcrafted REZ files and checks the resulting directory listing or the reported error.
+
+mod common;
+
+use common::{
+    build_rez, build_rez_with, dir, lithrez, lithrez_ok, obfuscated_header_prefix, res, sample_rez,
+    sample_tree, temp_path, v1_header_prefix, v2_header_prefix, valid_obfuscated_header_prefix,
+    write_embedded_rez, write_rez, Layout, Node,
+};
+
+
+/// The listing of `sample_tree()` without the data positions, which depend on the header and
+/// layout.
+const SAMPLE_LISTING: &str = "\
+README.txt [1, the readme] (1600000000, 12 bytes)
+sounds (1600000300)/
+  boom.wav [2] (1600000100, 12 bytes)
+  Alpha.wav [3, orig: c:\\alpha.wav] (1600000200, 300 bytes)
+  empty (1600000000)/
+tex (1650000000)/
+  ui (1650000000)/
+    button.dtx [4] (1650000000, 64 bytes)
+";
+
+
+fn list(name: &str, bytes: &[u8]) -> String {
+    let path = write_rez(name, bytes);
+    let listing = lithrez_ok(["list".as_ref(), path.as_os_str()]);
+    strip_positions(&listing)
+}
+
+/// Lists the given REZ file, expecting it to be rejected as invalid, and returns the error output.
+fn list_err(name: &str, bytes: &[u8]) -> String {
+    let path = write_rez(name, bytes);
+    let output = lithrez(["list".as_ref(), path.as_os_str()]);
+    assert_eq!(output.status.code(), Some(4));
+    String::from_utf8(output.stderr).unwrap()
+}
+
+/// Turns "(time, position+size bytes)" into "(time, size bytes)".
+fn strip_positions(listing: &str) -> String {
+    let position_regex = regex::Regex::new(r", [0-9]+\+").unwrap();
+    position_regex.replace_all(listing, ", ").into_owned()
+}
+
+fn assert_error(stderr: &str, expected: &str) {
+    assert!(stderr.contains(expected), "expected {:?} in error output:\n{}", expected, stderr);
+}
+
+
+#[test]
+fn v1_header() {
+    let bytes = build_rez(&v1_header_prefix(), &sample_tree(), Layout::DirectoryAtEnd);
+    assert_eq!(list("v1.rez", &bytes), SAMPLE_LISTING);
+}
+
+#[test]
+fn v1_header_exact_positions() {
+    let path = sample_rez("v1-positions.rez");
+    let listing = lithrez_ok(["list".as_ref(), path.as_os_str()]);
+    assert!(listing.starts_with("README.txt [1, the readme] (1600000000, 168+12 bytes)\n"));
+    assert!(listing.contains("    button.dtx [4] (1650000000, 492+64 bytes)\n"));
+}
+
+#[test]
+fn obfuscated_header() {
+    let bytes = build_rez(&valid_obfuscated_header_prefix(), &sample_tree(), Layout::DirectoryAtEnd);
+    assert_eq!(list("obfuscated.rez", &bytes), SAMPLE_LISTING);
+}
+
+#[test]
+fn v2_header() {
+    let bytes = build_rez(&v2_header_prefix(7), &sample_tree(), Layout::DirectoryAtEnd);
+    assert_eq!(list("v2.rez", &bytes), SAMPLE_LISTING);
+}
+
+#[test]
+fn v2_header_extra_padding() {
+    let bytes = build_rez(&v2_header_prefix(11), &sample_tree(), Layout::DirectoryAtEnd);
+    assert_eq!(list("v2-padded.rez", &bytes), SAMPLE_LISTING);
+}
+
+#[test]
+fn directory_at_front() {
+    let bytes = build_rez(&v1_header_prefix(), &sample_tree(), Layout::DirectoryAtFront);
+    assert_eq!(list("front.rez", &bytes), SAMPLE_LISTING);
+}
+
+#[test]
+fn empty_archive() {
+    let bytes = build_rez(&v1_header_prefix(), &[], Layout::DirectoryAtEnd);
+    assert_eq!(list("empty.rez", &bytes), "");
+}
+
+#[test]
+fn sorted_flag_is_verified() {
+    let tree = vec![
+        res("b", "txt", 1, "", 0, b"b"),
+        res("A", "txt", 2, "", 0, b"a"),
+        dir("c", 0, vec![]),
+    ];
+
+    let unsorted = build_rez_with(&v1_header_prefix(), &tree, Layout::DirectoryAtEnd, 0, false);
+    let path = write_rez("unsorted.rez", &unsorted);
+    lithrez_ok(["verify".as_ref(), path.as_os_str()]);
+
+    let claims_sorted = build_rez_with(&v1_header_prefix(), &tree, Layout::DirectoryAtEnd, 0, true);
+    let path = write_rez("claims-sorted.rez", &claims_sorted);
+    let output = common::lithrez(["verify".as_ref(), path.as_os_str()]);
+    assert_eq!(output.status.code(), Some(1));
+}
+
+
+#[test]
+fn invalid_control_byte() {
+    let mut bytes = build_rez(&v1_header_prefix(), &sample_tree(), Layout::DirectoryAtEnd);
+    bytes[0] = b'X';
+    assert_error(&list_err("bad-control.rez", &bytes), "invalid control byte 0 (expected one of [13, 38], obtained 88)");
+}
+
+#[test]
+fn invalid_version_2() {
+    let mut header = v2_header_prefix(7);
+    let version_index = header.len() - 4;
+    header[version_index..].copy_from_slice(&3u32.to_le_bytes());
+    let bytes = build_rez(&header, &sample_tree(), Layout::DirectoryAtEnd);
+    assert_error(&list_err("bad-version-2.rez", &bytes), "invalid version 3 (expected 2)");
+}
+
+#[test]
+fn invalid_version_obfuscated() {
+    let header = obfuscated_header_prefix(0x42, "12345678", 0x42 ^ 0x11, &(12345678u32 ^ 0x016B4423).to_string(), 0x24, 0x24 ^ 0x11, 2);
+    let bytes = build_rez(&header, &sample_tree(), Layout::DirectoryAtEnd);
+    assert_error(&list_err("bad-version-obfuscated.rez", &bytes), "invalid version 2 (expected 1)");
+}
+
+#[test]
+fn invalid_detect_head() {
+    let header = obfuscated_header_prefix(0x42, "12345678", 0x42, &(12345678u32 ^ 0x016B4423).to_string(), 0x24, 0x24 ^ 0x11, 1);
+    let bytes = build_rez(&header, &sample_tree(), Layout::DirectoryAtEnd);
+    assert_error(&list_err("bad-detect-head.rez", &bytes), "invalid DetectHead");
+}
+
+#[test]
+fn invalid_detect_tail() {
+    let header = obfuscated_header_prefix(0x42, "12345678", 0x42 ^ 0x11, &(12345678u32 ^ 0x016B4423).to_string(), 0x24, 0x24, 1);
+    let bytes = build_rez(&header, &sample_tree(), Layout::DirectoryAtEnd);
+    assert_error(&list_err("bad-detect-tail.rez", &bytes), "invalid DetectTail");
+}
+
+#[test]
+fn invalid_encode_integer() {
+    let header = obfuscated_header_prefix(0x42, "1234x678", 0x42 ^ 0x11, "0", 0x24, 0x24 ^ 0x11, 1);
+    let bytes = build_rez(&header, &sample_tree(), Layout::DirectoryAtEnd);
+    assert_error(&list_err("bad-encode-integer.rez", &bytes), "invalid \"encode\" value integer: \"1234x678\"");
+}
+
+#[test]
+fn encode_value_mismatch() {
+    let header = obfuscated_header_prefix(0x42, "12345678", 0x42 ^ 0x11, "12345678", 0x24, 0x24 ^ 0x11, 1);
+    let bytes = build_rez(&header, &sample_tree(), Layout::DirectoryAtEnd);
+    assert_error(&list_err("encode-mismatch.rez", &bytes), "encode value mismatch");
+}
+
+#[test]
+fn unknown_entry_type() {
+    let tree = vec![
+        res("README", "txt", 1, "", 0, b"hello"),
+        Node::Unknown { type_code: 7 },
+    ];
+    let bytes = build_rez(&v1_header_prefix(), &tree, Layout::DirectoryAtEnd);
+    assert_error(&list_err("unknown-type.rez", &bytes), "cannot handle entries with type code 7");
+}
+
+#[test]
+fn truncated_directory() {
+    let mut bytes = build_rez(&v1_header_prefix(), &sample_tree(), Layout::DirectoryAtEnd);
+    bytes.truncate(bytes.len() - 10);
+    assert_error(&list_err("truncated.rez", &bytes), "is truncated (expected 89 bytes, only 79 available; missing 10)");
+}
+
+#[test]
+fn padded_directory_blocks() {
+    for padding in [3, 16, 21] {
+        let bytes = build_rez_with(&v1_header_prefix(), &sample_tree(), Layout::DirectoryAtEnd, padding, false);
+        assert_eq!(list(&format!("padded-{}.rez", padding), &bytes), SAMPLE_LISTING);
+    }
+}
+
+#[test]
+fn embedded_past_4_gib() {
+    const BASE_OFFSET: u64 = 0x1_0000_1000;
+    let bytes = build_rez(&v1_header_prefix(), &sample_tree(), Layout::DirectoryAtEnd);
+    let path = write_embedded_rez("embedded.bin", BASE_OFFSET, &bytes);
+    let base_offset = BASE_OFFSET.to_string();
+
+    let listing = lithrez_ok(["list".as_ref(), "--base-offset".as_ref(), base_offset.as_ref(), path.as_os_str()]);
+    assert_eq!(strip_positions(&listing), SAMPLE_LISTING);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn embedded_in_other_commands() {
+    const BASE_OFFSET: u64 = 0x1000;
+    let bytes = build_rez(&v1_header_prefix(), &sample_tree(), Layout::DirectoryAtEnd);
+    let path = write_embedded_rez("embedded-commands.bin", BASE_OFFSET, &bytes);
+    let plain_path = sample_rez("embedded-commands.rez");
+    let base_offset = BASE_OFFSET.to_string();
+    let run = |args: &[&str]| {
+        let mut all_args: Vec<&std::ffi::OsStr> = args.iter().map(|a| a.as_ref()).collect();
+        all_args.extend(["--base-offset".as_ref(), base_offset.as_ref(), path.as_os_str()]);
+        lithrez_ok(all_args)
+    };
+
+    assert_eq!(run(&["find", "-f", "**.wav"]), "sounds/boom.wav\nsounds/Alpha.wav\n");
+    lithrez_ok(["has".as_ref(), "--base-offset".as_ref(), base_offset.as_ref(), path.as_os_str(), "sounds/boom.wav".as_ref()]);
+    assert!(run(&["info"]).contains(&format!("size: {} bytes\n", bytes.len())));
+    assert!(run(&["du"]).starts_with("388\t/\n"));
+    // whatis takes offsets within the given file
+    let readme_offset = (BASE_OFFSET + 168).to_string();
+    let whatis = lithrez_ok(["whatis".as_ref(), "--base-offset".as_ref(), base_offset.as_ref(), path.as_os_str(), readme_offset.as_ref()]);
+    assert!(whatis.starts_with("README.txt (byte 0 of 12"), "{}", whatis);
+
+    // the embedded and the standalone REZ file have the same contents
+    lithrez_ok(["diff".as_ref(), "--content".as_ref(), "--old-base-offset".as_ref(), base_offset.as_ref(), path.as_os_str(), plain_path.as_os_str()]);
+
+    let merged_path = temp_path("embedded-commands-merged.rez");
+    let base_offsets = format!("{},0", BASE_OFFSET);
+    lithrez_ok(["merge".as_ref(), "--base-offset".as_ref(), base_offsets.as_ref(), "-o".as_ref(), merged_path.as_os_str(), path.as_os_str(), plain_path.as_os_str()]);
+    lithrez_ok(["diff".as_ref(), "--content".as_ref(), merged_path.as_os_str(), plain_path.as_os_str()]);
+    let output = lithrez(["merge".as_ref(), "--base-offset".as_ref(), base_offset.as_ref(), "-o".as_ref(), merged_path.as_os_str(), path.as_os_str(), plain_path.as_os_str()]);
+    assert_eq!(output.status.code(), Some(2));
+}
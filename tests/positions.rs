@@ -0,0 +1,28 @@
+//! Checks the tables written by the `positions` command.
+
+mod common;
+
+use common::{build_rez, lithrez_ok, res, temp_path, v1_header_prefix, write_rez, Layout};
+
+
+#[test]
+fn binary_layout() {
+    let tree = vec![
+        res("a", "txt", 1, "", 0, b"hello"),
+        common::dir("d", 0, vec![res("b", "bin", 2, "", 0, b"xyz")]),
+    ];
+    let bytes = build_rez(&v1_header_prefix(), &tree, Layout::DirectoryAtEnd);
+    let path = write_rez("positions.rez", &bytes);
+    let table_path = temp_path("positions.bin");
+    lithrez_ok(["positions".as_ref(), path.as_os_str(), table_path.as_os_str()]);
+
+    let mut expected = Vec::new();
+    expected.extend_from_slice(&2u32.to_le_bytes());
+    expected.extend_from_slice(&168u32.to_le_bytes());
+    expected.extend_from_slice(&5u32.to_le_bytes());
+    expected.extend_from_slice(b"a.txt\0");
+    expected.extend_from_slice(&173u32.to_le_bytes());
+    expected.extend_from_slice(&3u32.to_le_bytes());
+    expected.extend_from_slice(b"d/b.bin\0");
+    assert_eq!(std::fs::read(&table_path).unwrap(), expected);
+}
@@ -0,0 +1,82 @@
+//! Extracts handcrafted REZ files, packs the result again and checks that nothing was lost.
+
+mod common;
+
+use std::path::Path;
+
+use common::{build_rez, lithrez_ok, sample_tree, temp_path, v1_header_prefix, valid_obfuscated_header_prefix, write_rez, Layout};
+
+
+/// Collects the relative paths and contents of all files below the directory.
+fn collect_files(base: &Path, relative: &str, files: &mut Vec<(String, Vec<u8>)>) {
+    let mut children: Vec<_> = std::fs::read_dir(base.join(relative))
+        .unwrap()
+        .map(|e| e.unwrap())
+        .collect();
+    children.sort_by_key(|e| e.file_name());
+    for child in children {
+        let name = child.file_name().into_string().unwrap();
+        let child_relative = if relative.is_empty() { name } else { format!("{}/{}", relative, name) };
+        if child.file_type().unwrap().is_dir() {
+            collect_files(base, &child_relative, files);
+        } else {
+            files.push((child_relative, std::fs::read(child.path()).unwrap()));
+        }
+    }
+}
+
+fn extract_pack_extract(name: &str, bytes: &[u8]) {
+    let original = write_rez(&format!("{}.rez", name), bytes);
+    let first_dir = temp_path(&format!("{}-first", name));
+    let repacked = temp_path(&format!("{}-repacked.rez", name));
+    let second_dir = temp_path(&format!("{}-second", name));
+
+    lithrez_ok(["extract".as_ref(), original.as_os_str(), first_dir.as_os_str()]);
+    lithrez_ok(["pack".as_ref(), first_dir.as_os_str(), repacked.as_os_str()]);
+    lithrez_ok(["extract".as_ref(), repacked.as_os_str(), second_dir.as_os_str()]);
+
+    let mut first_files = Vec::new();
+    collect_files(&first_dir, "", &mut first_files);
+    let mut second_files = Vec::new();
+    collect_files(&second_dir, "", &mut second_files);
+
+    assert_eq!(first_files.len(), 4);
+    assert_eq!(first_files, second_files);
+}
+
+
+#[test]
+fn round_trip_v1() {
+    let bytes = build_rez(&v1_header_prefix(), &sample_tree(), Layout::DirectoryAtEnd);
+    extract_pack_extract("round-trip-v1", &bytes);
+}
+
+#[test]
+fn round_trip_obfuscated() {
+    let bytes = build_rez(&valid_obfuscated_header_prefix(), &sample_tree(), Layout::DirectoryAtFront);
+    extract_pack_extract("round-trip-obfuscated", &bytes);
+}
+
+#[test]
+fn dtx_extension_order() {
+    // standard REZ files store the extension reversed and NUL-padded at the end
+    let bytes = build_rez(&v1_header_prefix(), &sample_tree(), Layout::DirectoryAtEnd);
+    assert!(bytes.windows(4).any(|w| w == b"xtd\0"));
+    let original = write_rez("dtx.rez", &bytes);
+
+    let listing = lithrez_ok(["list".as_ref(), "--type".as_ref(), "resource".as_ref(), original.as_os_str()]);
+    assert!(listing.contains("tex/ui/button.dtx"));
+    let listing = lithrez_ok(["list".as_ref(), "--type".as_ref(), "resource".as_ref(), "--ext-order".as_ref(), "normal".as_ref(), original.as_os_str()]);
+    assert!(listing.contains("tex/ui/button.xtd"));
+
+    // packing writes the extension back the same way
+    let extracted = temp_path("dtx-extracted");
+    let repacked = temp_path("dtx-repacked.rez");
+    lithrez_ok(["extract".as_ref(), original.as_os_str(), extracted.as_os_str()]);
+    assert!(extracted.join("tex").join("ui").join("button.dtx").is_file());
+    lithrez_ok(["pack".as_ref(), extracted.as_os_str(), repacked.as_os_str()]);
+    let repacked_bytes = std::fs::read(&repacked).unwrap();
+    assert!(repacked_bytes.windows(4).any(|w| w == b"xtd\0"));
+    let listing = lithrez_ok(["list".as_ref(), "--type".as_ref(), "resource".as_ref(), repacked.as_os_str()]);
+    assert!(listing.contains("tex/ui/button.dtx"));
+}
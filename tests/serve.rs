@@ -0,0 +1,98 @@
+//! Checks the `serve` command by sending HTTP requests to it.
+
+mod common;
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::process::{Child, Command, Stdio};
+
+use common::{build_rez, dir, res, sample_tree, v1_header_prefix, write_rez, Layout};
+
+
+/// Stops the server when dropped, even if the test fails.
+struct Server(Child);
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// Sends a GET request with the given additional header lines and returns the whole response.
+fn get(address: &str, path: &str, extra_headers: &str) -> String {
+    let mut stream = TcpStream::connect(address).unwrap();
+    write!(stream, "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n{}\r\n", path, address, extra_headers).unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).unwrap();
+    String::from_utf8_lossy(&response).into_owned()
+}
+
+/// Starts serving the REZ file with the given name and contents, returning the server along with
+/// its address.
+fn start_server(name: &str, bytes: &[u8]) -> (Server, String) {
+    let path = write_rez(name, bytes);
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lithrez"))
+        .args(["serve".as_ref(), "--address".as_ref(), "127.0.0.1:0".as_ref(), path.as_os_str()])
+        .env("RUST_LOG", "info")
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let stderr = child.stderr.take().unwrap();
+    let server = Server(child);
+
+    let mut line = String::new();
+    BufReader::new(stderr).read_line(&mut line).unwrap();
+    let address = line.split("http://").nth(1).unwrap().trim_end().trim_end_matches('/').to_owned();
+    (server, address)
+}
+
+#[test]
+fn serve_index_and_ranges() {
+    let bytes = build_rez(&v1_header_prefix(), &sample_tree(), Layout::DirectoryAtEnd);
+    let (_server, address) = start_server("serve.rez", &bytes);
+
+    let index = get(&address, "/sounds/", "");
+    assert!(index.starts_with("HTTP/1.1 200 "));
+    assert!(index.contains("<a href=\"../\">"));
+    assert!(index.contains("<a href=\"empty/\">empty/</a>"));
+    assert!(index.contains("<a href=\"Alpha.wav\">Alpha.wav</a> (300 bytes)"));
+
+    let redirect = get(&address, "/tex/ui", "");
+    assert!(redirect.starts_with("HTTP/1.1 301 "));
+    assert!(redirect.contains("Location: /tex/ui/\r\n"));
+
+    let readme = get(&address, "/README.txt", "");
+    assert!(readme.starts_with("HTTP/1.1 200 "));
+    assert!(readme.ends_with("\r\n\r\nhello world\n"));
+
+    let partial = get(&address, "/README.txt", "Range: bytes=6-\r\n");
+    assert!(partial.starts_with("HTTP/1.1 206 "));
+    assert!(partial.contains("Content-Range: bytes 6-11/12\r\n"));
+    assert!(partial.ends_with("\r\n\r\nworld\n"));
+
+    let suffix = get(&address, "/README.txt", "Range: bytes=-3\r\n");
+    assert!(suffix.ends_with("\r\n\r\nld\n"));
+
+    let unsatisfiable = get(&address, "/README.txt", "Range: bytes=12-\r\n");
+    assert!(unsatisfiable.starts_with("HTTP/1.1 416 "));
+    assert!(unsatisfiable.contains("Content-Range: bytes */12\r\n"));
+
+    let missing = get(&address, "/sounds/missing.wav", "");
+    assert!(missing.starts_with("HTTP/1.1 404 "));
+}
+
+#[test]
+fn redirect_non_ascii_directory() {
+    // the name is stored as UTF-8 bytes, which are decoded as ISO-8859-1 ("MÃ¼sik")
+    let tree = vec![dir("M\u{fc}sik", 1600000000, vec![res("song", "wav", 1, "", 1600000000, b"RIFF")])];
+    let bytes = build_rez(&v1_header_prefix(), &tree, Layout::DirectoryAtEnd);
+    let (_server, address) = start_server("serve-non-ascii.rez", &bytes);
+
+    let redirect = get(&address, "/M%C3%83%C2%BCsik", "");
+    assert!(redirect.starts_with("HTTP/1.1 301 "));
+    assert!(redirect.contains("Location: /M%C3%83%C2%BCsik/\r\n"));
+
+    // the server is still alive
+    let song = get(&address, "/M%C3%83%C2%BCsik/song.wav", "");
+    assert!(song.ends_with("\r\n\r\nRIFF"));
+}
@@ -0,0 +1,62 @@
+//! Checks the `to-tar` command.
+
+mod common;
+
+use std::io::Read;
+
+use common::{build_rez, lithrez, sample_rez, sample_tree, v1_header_prefix, write_rez, Layout};
+
+
+#[test]
+fn tar_stream() {
+    let path = sample_rez("to-tar.rez");
+    let output = lithrez(["to-tar".as_ref(), path.as_os_str(), "-".as_ref()]);
+    assert!(output.status.success());
+
+    let mut archive = tar::Archive::new(&output.stdout[..]);
+    let mut files = Vec::new();
+    for entry in archive.entries().unwrap() {
+        let mut entry = entry.unwrap();
+        let path = entry.path().unwrap().to_string_lossy().into_owned();
+        let mtime = entry.header().mtime().unwrap();
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data).unwrap();
+        files.push((path, mtime, data));
+    }
+
+    let paths: Vec<&str> = files.iter().map(|(path, _, _)| path.as_str()).collect();
+    assert_eq!(paths, ["README.txt", "sounds/", "sounds/boom.wav", "sounds/Alpha.wav", "sounds/empty/", "tex/", "tex/ui/", "tex/ui/button.dtx"]);
+    assert_eq!(files[0].1, 1600000000);
+    assert_eq!(files[0].2, b"hello world\n");
+    assert_eq!(files[3].2, vec![0xAA; 300]);
+    assert_eq!(files[7].2, vec![0u8; 64]);
+}
+
+#[test]
+fn on_short_read() {
+    // with the directory at the front, the data of the last resource is at the end of the file
+    let mut bytes = build_rez(&v1_header_prefix(), &sample_tree(), Layout::DirectoryAtFront);
+    bytes.truncate(bytes.len() - 10);
+    let path = write_rez("to-tar-short.rez", &bytes);
+
+    let output = lithrez(["to-tar".as_ref(), path.as_os_str(), "-".as_ref()]);
+    assert_eq!(output.status.code(), Some(4));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("cannot convert tex/ui/button.dtx stored at offset "));
+    assert!(output.stdout.is_empty());
+
+    let button_size = |on_short_read: &str| {
+        let output = lithrez(["to-tar".as_ref(), "--on-short-read".as_ref(), on_short_read.as_ref(), path.as_os_str()]);
+        assert!(output.status.success());
+        let mut archive = tar::Archive::new(&output.stdout[..]);
+        let mut size = None;
+        for entry in archive.entries().unwrap() {
+            let entry = entry.unwrap();
+            if entry.path().unwrap().to_str() == Some("tex/ui/button.dtx") {
+                size = Some(entry.header().size().unwrap());
+            }
+        }
+        size
+    };
+    assert_eq!(button_size("skip"), None);
+    assert_eq!(button_size("truncate"), Some(54));
+}
@@ -0,0 +1,170 @@
+//! Checks the problems reported by the `verify` command.
+
+mod common;
+
+use common::{build_rez, build_rez_with, dir, lithrez, res, sample_tree, v1_header_prefix, write_rez, Layout, Node};
+
+
+fn verify_json(name: &str, bytes: &[u8]) -> (Option<i32>, serde_json::Value) {
+    verify_json_with(name, bytes, &[])
+}
+
+fn verify_json_with(name: &str, bytes: &[u8], extra_args: &[&str]) -> (Option<i32>, serde_json::Value) {
+    let path = write_rez(name, bytes);
+    let mut args: Vec<&std::ffi::OsStr> = vec!["verify".as_ref(), "--report".as_ref(), "json".as_ref()];
+    args.extend(extra_args.iter().map(std::ffi::OsStr::new));
+    args.push(path.as_os_str());
+    let output = lithrez(args);
+    let report = serde_json::from_slice(&output.stdout).unwrap();
+    (output.status.code(), report)
+}
+
+/// Overwrites the position of the resource with the given name in the directory block.
+fn set_position(bytes: &mut [u8], name: &str, position: u32) {
+    let mut needle = name.as_bytes().to_vec();
+    needle.push(0);
+    let name_start = bytes.windows(needle.len())
+        .position(|w| w == needle)
+        .unwrap();
+    // type, position, size, time, ID, extension, number of keys, name
+    let position_start = name_start - 6*4;
+    bytes[position_start..position_start + 4].copy_from_slice(&position.to_le_bytes());
+}
+
+
+#[test]
+fn report_sort_violation() {
+    let tree = vec![
+        res("b", "txt", 1, "", 0, b"b"),
+        res("A", "txt", 2, "", 0, b"a"),
+        dir("c", 0, vec![]),
+    ];
+    let bytes = build_rez_with(&v1_header_prefix(), &tree, Layout::DirectoryAtEnd, 0, true);
+    let (code, report) = verify_json("report-sort.rez", &bytes);
+    assert_eq!(code, Some(1));
+    assert_eq!(report.as_array().unwrap().len(), 1);
+    assert_eq!(report[0]["severity"], "error");
+    assert_eq!(report[0]["kind"], "SortViolation");
+    assert_eq!(report[0]["path"], "/");
+}
+
+#[test]
+fn report_duplicate_id_as_warning() {
+    let tree = vec![
+        res("a", "txt", 1, "", 0, b"a"),
+        dir("sub", 0, vec![res("b", "txt", 1, "", 0, b"b")]),
+    ];
+    let bytes = build_rez(&v1_header_prefix(), &tree, Layout::DirectoryAtEnd);
+    let (code, report) = verify_json("report-duplicate-id.rez", &bytes);
+    assert_eq!(code, Some(0));
+    assert_eq!(report.as_array().unwrap().len(), 1);
+    assert_eq!(report[0]["severity"], "warning");
+    assert_eq!(report[0]["kind"], "DuplicateId");
+    assert_eq!(report[0]["path"], "sub/b.txt");
+}
+
+#[test]
+fn report_unknown_type() {
+    let tree = vec![
+        res("README", "txt", 1, "", 0, b"hello"),
+        Node::Unknown { type_code: 7 },
+    ];
+    let bytes = build_rez(&v1_header_prefix(), &tree, Layout::DirectoryAtEnd);
+    let (code, report) = verify_json("report-unknown-type.rez", &bytes);
+    assert_eq!(code, Some(1));
+    assert_eq!(report[0]["kind"], "UnknownType");
+}
+
+#[test]
+fn offsets_only() {
+    let bytes = build_rez(&v1_header_prefix(), &sample_tree(), Layout::DirectoryAtFront);
+    let (code, report) = verify_json_with("offsets-only-ok.rez", &bytes, &["--offsets-only"]);
+    assert_eq!(code, Some(0));
+    assert_eq!(report.as_array().unwrap().len(), 0);
+
+    // with the directory at the front, the data of the last resource is at the end of the file
+    let truncated = &bytes[..bytes.len() - 10];
+    let (code, report) = verify_json_with("offsets-only-truncated.rez", truncated, &["--offsets-only"]);
+    assert_eq!(code, Some(1));
+    assert_eq!(report.as_array().unwrap().len(), 1);
+    assert_eq!(report[0]["kind"], "OutOfBounds");
+    assert!(report[0]["detail"].as_str().unwrap().starts_with("data at "));
+
+    // with the directory at the end, the root directory block is cut off
+    let bytes = build_rez(&v1_header_prefix(), &sample_tree(), Layout::DirectoryAtEnd);
+    let truncated = &bytes[..bytes.len() - 10];
+    let (code, report) = verify_json_with("offsets-only-truncated-root.rez", truncated, &["--offsets-only"]);
+    assert_eq!(code, Some(1));
+    assert_eq!(report[0]["path"], "/");
+    assert!(report[0]["detail"].as_str().unwrap().starts_with("directory block at "));
+}
+
+#[test]
+fn report_overlaps_and_bounds() {
+    let mut bytes = build_rez(&v1_header_prefix(), &sample_tree(), Layout::DirectoryAtEnd);
+    // README.txt is at 168+12
+    set_position(&mut bytes, "boom", 168);
+    set_position(&mut bytes, "Alpha", 170);
+    set_position(&mut bytes, "button", 1_000_000);
+    let (code, report) = verify_json("report-overlaps.rez", &bytes);
+    assert_eq!(code, Some(1));
+
+    let problems: Vec<(&str, &str, &str)> = report.as_array().unwrap().iter()
+        .map(|p| (p["severity"].as_str().unwrap(), p["kind"].as_str().unwrap(), p["path"].as_str().unwrap()))
+        .collect();
+    assert_eq!(problems, [
+        ("error", "OutOfBounds", "tex/ui/button.dtx"),
+        ("warning", "Overlap", "sounds/boom.wav"),
+        ("error", "Overlap", "sounds/Alpha.wav"),
+    ]);
+}
+
+#[test]
+fn report_directory_padding() {
+    let bytes = build_rez_with(&v1_header_prefix(), &sample_tree(), Layout::DirectoryAtEnd, 8, false);
+    let (code, report) = verify_json("report-padding.rez", &bytes);
+    assert_eq!(code, Some(0));
+    let paths: Vec<&str> = report.as_array().unwrap().iter()
+        .inspect(|p| assert_eq!(p["kind"], "DirectorySize"))
+        .map(|p| p["path"].as_str().unwrap())
+        .collect();
+    assert_eq!(paths, ["/", "sounds", "sounds/empty", "tex", "tex/ui"]);
+
+    // cutting the root directory block short makes its last entry incomplete
+    let mut bytes = build_rez(&v1_header_prefix(), &sample_tree(), Layout::DirectoryAtEnd);
+    let root_dir_size_start = v1_header_prefix().len() + 4;
+    let root_dir_size = u32::from_le_bytes(bytes[root_dir_size_start..root_dir_size_start + 4].try_into().unwrap());
+    bytes[root_dir_size_start..root_dir_size_start + 4].copy_from_slice(&(root_dir_size - 3).to_le_bytes());
+    let (code, report) = verify_json("report-short-root.rez", &bytes);
+    assert_eq!(code, Some(1));
+    assert_eq!(report[0]["kind"], "DirectorySize");
+}
+
+#[test]
+fn report_trailing_bytes_as_warning() {
+    let mut bytes = build_rez(&v1_header_prefix(), &sample_tree(), Layout::DirectoryAtFront);
+    bytes.extend_from_slice(&[0; 5]);
+    let (code, report) = verify_json("report-trailing.rez", &bytes);
+    assert_eq!(code, Some(0));
+    assert_eq!(report.as_array().unwrap().len(), 1);
+    assert_eq!(report[0]["severity"], "warning");
+    assert_eq!(report[0]["kind"], "TrailingBytes");
+    assert!(report[0]["detail"].as_str().unwrap().starts_with("5 bytes follow the end of the REZ data at "));
+}
+
+#[test]
+fn verify_embedded() {
+    let mut bytes = b"junk".to_vec();
+    bytes.extend_from_slice(&build_rez_with(&v1_header_prefix(), &sample_tree(), Layout::DirectoryAtEnd, 8, false));
+    let (code, report) = verify_json_with("verify-embedded.rez", &bytes, &["--base-offset", "4"]);
+    assert_eq!(code, Some(0));
+    let paths: Vec<&str> = report.as_array().unwrap().iter()
+        .inspect(|p| assert_eq!(p["kind"], "DirectorySize"))
+        .map(|p| p["path"].as_str().unwrap())
+        .collect();
+    assert_eq!(paths, ["/", "sounds", "sounds/empty", "tex", "tex/ui"]);
+
+    let (code, report) = verify_json_with("verify-embedded-offsets.rez", &bytes, &["--base-offset", "4", "--offsets-only"]);
+    assert_eq!(code, Some(0));
+    assert_eq!(report.as_array().unwrap().len(), 0);
+}
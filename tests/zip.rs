@@ -0,0 +1,134 @@
+//! Checks the conversion between REZ files and ZIP archives.
+
+mod common;
+
+use std::io::{Read, Write};
+
+use common::{build_rez, list, lithrez_ok, sample_rez, sample_tree, temp_path, v1_header_prefix, write_rez, Layout};
+
+
+#[test]
+fn rez_to_zip() {
+    let path = sample_rez("to-zip.rez");
+    let zip_path = temp_path("to-zip.zip");
+    lithrez_ok(["to-zip".as_ref(), path.as_os_str(), zip_path.as_os_str()]);
+
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(&zip_path).unwrap()).unwrap();
+    let names: Vec<&str> = archive.file_names().collect();
+    for name in ["README.txt", "sounds/", "sounds/boom.wav", "sounds/Alpha.wav", "sounds/empty/", "tex/ui/button.dtx"] {
+        assert!(names.contains(&name), "{} is missing from {:?}", name, names);
+    }
+
+    let mut readme = archive.by_name("README.txt").unwrap();
+    let mut data = Vec::new();
+    readme.read_to_end(&mut data).unwrap();
+    assert_eq!(data, b"hello world\n");
+    // 1600000000 is 2020-09-13 12:26:40 UTC; ZIP times have a resolution of two seconds
+    let time = readme.last_modified().unwrap();
+    assert_eq!((time.year(), time.month(), time.day()), (2020, 9, 13));
+    assert_eq!((time.hour(), time.minute(), time.second()), (12, 26, 40));
+    drop(readme);
+
+    let mut alpha = archive.by_name("sounds/Alpha.wav").unwrap();
+    let mut data = Vec::new();
+    alpha.read_to_end(&mut data).unwrap();
+    assert_eq!(data, vec![0xAA; 300]);
+    drop(alpha);
+
+    // only the selected files, and no separate directory entries
+    let zip_path = temp_path("to-zip-filtered.zip");
+    lithrez_ok(["to-zip".as_ref(), "--store".as_ref(), "-f".as_ref(), "sounds/*.wav".as_ref(), path.as_os_str(), zip_path.as_os_str()]);
+    let archive = zip::ZipArchive::new(std::fs::File::open(&zip_path).unwrap()).unwrap();
+    let mut names: Vec<&str> = archive.file_names().collect();
+    names.sort_unstable();
+    assert_eq!(names, ["sounds/Alpha.wav", "sounds/boom.wav"]);
+}
+
+#[test]
+fn zip_to_rez() {
+    let zip_path = temp_path("from-zip.zip");
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(&zip_path).unwrap());
+    // 2021-03-04 05:06:08 UTC is 1614834368
+    let time = zip::DateTime::from_date_and_time(2021, 3, 4, 5, 6, 8).unwrap();
+    let options = zip::write::SimpleFileOptions::default().last_modified_time(time);
+    zip.start_file("models/trees/oak.ltb", options).unwrap();
+    zip.write_all(b"oak").unwrap();
+    zip.add_directory("empty/", options).unwrap();
+    zip.start_file("README.txt", options).unwrap();
+    zip.write_all(b"hello world\n").unwrap();
+    zip.finish().unwrap();
+
+    let path = temp_path("from-zip.rez");
+    lithrez_ok(["from-zip".as_ref(), "--sorted".as_ref(), zip_path.as_os_str(), path.as_os_str()]);
+    let listing = list(&path, &[]);
+    let lines: Vec<&str> = listing.lines().collect();
+    assert_eq!(lines.len(), 5);
+    assert!(lines[0].starts_with("empty (1614834368"), "{}", lines[0]);
+    assert!(lines[1].starts_with("models (1614834368"), "{}", lines[1]);
+    assert!(lines[2].starts_with("  trees (1614834368"), "{}", lines[2]);
+    assert!(lines[3].starts_with("    oak.ltb [1] (1614834368, "), "{}", lines[3]);
+    assert!(lines[4].starts_with("README.txt [2] (1614834368, "), "{}", lines[4]);
+
+    let output_dir = temp_path("from-zip");
+    lithrez_ok(["extract".as_ref(), path.as_os_str(), output_dir.as_os_str()]);
+    assert_eq!(std::fs::read(output_dir.join("models").join("trees").join("oak.ltb")).unwrap(), b"oak");
+    assert_eq!(std::fs::read(output_dir.join("README.txt")).unwrap(), b"hello world\n");
+}
+
+#[test]
+fn rez_to_zip_on_short_read() {
+    // with the directory at the front, the data of the last resource is at the end of the file
+    let mut bytes = build_rez(&v1_header_prefix(), &sample_tree(), Layout::DirectoryAtFront);
+    bytes.truncate(bytes.len() - 10);
+    let path = write_rez("to-zip-short.rez", &bytes);
+
+    let zip_path = temp_path("to-zip-short.zip");
+    let output = common::lithrez(["to-zip".as_ref(), path.as_os_str(), zip_path.as_os_str()]);
+    assert_eq!(output.status.code(), Some(4));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("cannot convert tex/ui/button.dtx stored at offset "));
+    assert!(!zip_path.exists());
+
+    lithrez_ok(["to-zip".as_ref(), "--on-short-read".as_ref(), "skip".as_ref(), path.as_os_str(), zip_path.as_os_str()]);
+    let archive = zip::ZipArchive::new(std::fs::File::open(&zip_path).unwrap()).unwrap();
+    assert!(archive.index_for_name("tex/ui/button.dtx").is_none());
+    assert!(archive.index_for_name("README.txt").is_some());
+
+    lithrez_ok(["to-zip".as_ref(), "--on-short-read".as_ref(), "truncate".as_ref(), path.as_os_str(), zip_path.as_os_str()]);
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(&zip_path).unwrap()).unwrap();
+    assert_eq!(archive.by_name("tex/ui/button.dtx").unwrap().size(), 54);
+}
+
+#[test]
+fn zip_to_rez_names() {
+    let options = zip::write::SimpleFileOptions::default();
+
+    // a leading dot does not start an extension, and directories match regardless of case
+    let zip_path = temp_path("from-zip-names.zip");
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(&zip_path).unwrap());
+    zip.start_file(".gitignore", options).unwrap();
+    zip.write_all(b"*.bak\n").unwrap();
+    zip.start_file("Sounds/boom.wav", options).unwrap();
+    zip.write_all(b"boom").unwrap();
+    zip.start_file("sounds/bang.wav", options).unwrap();
+    zip.write_all(b"bang").unwrap();
+    zip.finish().unwrap();
+
+    let path = temp_path("from-zip-names.rez");
+    lithrez_ok(["from-zip".as_ref(), zip_path.as_os_str(), path.as_os_str()]);
+    // like all resources without an extension, it is named with a trailing dot
+    assert_eq!(lithrez_ok(["cat".as_ref(), path.as_os_str(), ".gitignore.".as_ref()]), "*.bak\n");
+    assert_eq!(lithrez_ok(["cat".as_ref(), path.as_os_str(), "Sounds/bang.wav".as_ref()]), "bang");
+    let listing = list(&path, &[]);
+    assert_eq!(listing.lines().filter(|line| line.starts_with("Sounds ")).count(), 1, "{}", listing);
+    assert!(!listing.contains("sounds "), "{}", listing);
+
+    // names differing only in case are duplicates
+    let zip_path = temp_path("from-zip-duplicate.zip");
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(&zip_path).unwrap());
+    zip.start_file("readme.txt", options).unwrap();
+    zip.start_file("README.TXT", options).unwrap();
+    zip.finish().unwrap();
+    let output = common::lithrez(["from-zip".as_ref(), zip_path.as_os_str(), temp_path("from-zip-duplicate.rez").as_os_str()]);
+    assert_eq!(output.status.code(), Some(4));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("more than once"));
+}